@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Config, ConfigError, StorageBackend};
+
+/// Settings read from a `--config` file
+///
+/// Every field is optional; anything left out keeps its default, and
+/// anything set by a flag or environment variable wins over the file.
+///
+/// ```toml
+/// [server]
+/// listen_addr = "0.0.0.0:3000"
+///
+/// [storage]
+/// backend = "s3"
+/// s3_bucket = "videos"
+///
+/// [limits]
+/// max_sessions = 200
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    /// Extra stores addressable by URL, as `[[stores]]` tables
+    pub stores: Option<Vec<StoreConfig>>,
+    pub server: ServerSection,
+    pub storage: StorageSection,
+    pub encoding: EncodingSection,
+    pub limits: LimitsSection,
+    pub caching: CachingSection,
+    pub auth: AuthSection,
+    pub telemetry: TelemetrySection,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub listen_addr: Option<String>,
+    pub shutdown_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSection {
+    pub backend: Option<StorageBackend>,
+    pub local_path: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+    pub s3_session_token: Option<String>,
+    pub s3_profile: Option<String>,
+    pub gcs_bucket: Option<String>,
+    pub gcs_service_account_path: Option<String>,
+    pub gcs_service_account_key: Option<String>,
+    pub gcs_endpoint: Option<String>,
+    pub azure_account: Option<String>,
+    pub azure_container: Option<String>,
+    pub azure_access_key: Option<String>,
+    pub azure_sas_token: Option<String>,
+    pub azure_client_id: Option<String>,
+    pub azure_client_secret: Option<String>,
+    pub azure_tenant_id: Option<String>,
+    pub azure_federated_token_file: Option<String>,
+    pub azure_endpoint: Option<String>,
+    pub azure_use_emulator: Option<bool>,
+    pub http_url: Option<String>,
+    pub http_headers: Option<Vec<String>>,
+    pub http_bearer_token: Option<String>,
+    pub http_range_probe: Option<String>,
+    pub timeout_ms: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub retry_backoff_ms: Option<u64>,
+    pub retry_max_backoff_ms: Option<u64>,
+    pub hedge_after_ms: Option<u64>,
+    pub breaker_threshold: Option<u32>,
+    pub breaker_cooldown_ms: Option<u64>,
+    pub part_size: Option<u64>,
+    pub fetch_concurrency: Option<usize>,
+    pub coalesce_gap: Option<u64>,
+    pub allowed_prefixes: Option<Vec<String>>,
+    pub allowed_extensions: Option<Vec<String>>,
+    pub max_path_length: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncodingSection {
+    pub jpeg_quality: Option<u8>,
+    pub min_jpeg_quality: Option<u8>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsSection {
+    pub max_frames_per_request: Option<usize>,
+    pub max_fps_per_client: Option<u32>,
+    pub max_bytes_per_minute: Option<u64>,
+    pub max_sessions: Option<usize>,
+    pub default_credit_window: Option<u32>,
+    pub slow_client_threshold_ms: Option<u64>,
+    pub slow_client_timeout_ms: Option<u64>,
+    pub ready_max_frames_in_flight: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CachingSection {
+    pub http_cache_max_age: Option<u64>,
+    pub fetch_cache_bytes: Option<u64>,
+    pub fetch_cache_block_size: Option<u64>,
+    pub disk_cache_dir: Option<String>,
+    pub disk_cache_bytes: Option<u64>,
+    pub prefetch_gops: Option<u32>,
+    pub prefetch_max_bytes: Option<u64>,
+    pub prefetch_concurrency: Option<usize>,
+    pub offsets_write_back: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSection {
+    pub api_keys: Option<Vec<String>>,
+    pub hmac_secret: Option<String>,
+    pub jwks_file: Option<String>,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    pub url_signing_key: Option<String>,
+    pub signed_url_max_ttl: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetrySection {
+    pub log_level: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub service_name: Option<String>,
+}
+
+/// A store clients can address by URL, e.g. `SetVideo` with
+/// `gs://archive/clip.h265`
+///
+/// `url` names the bucket, container or local directory; `backend` and the
+/// bucket fields are taken from it. Other `[storage]` keys set this store's
+/// region, endpoint and credentials; anything left out uses the default
+/// store's value.
+///
+/// ```toml
+/// [[stores]]
+/// name = "archive"
+/// url = "gs://archive-footage"
+/// gcs_service_account_path = "/etc/gcs/archive.json"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreConfig {
+    /// Used in logs
+    pub name: String,
+    /// `s3://bucket`, `gs://bucket`, `az://container`, `fs:///directory` or
+    /// an `https://` origin
+    pub url: String,
+    #[serde(flatten)]
+    pub storage: StorageSection,
+    /// Keys that are not storage settings; rejected by validation, since
+    /// `deny_unknown_fields` has no effect on flattened sections
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, serde_json::Value>,
+}
+
+impl StoreConfig {
+    /// `base` with this store's storage settings layered on top
+    pub fn apply_to(&self, base: &Config) -> Config {
+        let mut config = base.clone();
+        config.stores.clear();
+        let file = FileConfig {
+            storage: self.storage.clone(),
+            ..FileConfig::default()
+        };
+        file.apply(&mut config, |_| false);
+        config
+    }
+}
+
+/// Copy each value the file sets into `config`, unless `explicit` says a
+/// flag or environment variable already set that field
+macro_rules! layer {
+    ($config:ident, $explicit:ident, $($field:ident = $value:expr),* $(,)?) => {
+        $(
+            if let Some(value) = $value {
+                if !$explicit(stringify!($field)) {
+                    $config.$field = value;
+                }
+            }
+        )*
+    };
+}
+
+impl FileConfig {
+    /// Read a TOML or YAML file, picking the format from its extension
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let parse_error = |message: String| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&text).map_err(|e| parse_error(e.to_string())),
+            Some("yaml" | "yml") => {
+                serde_norway::from_str(&text).map_err(|e| parse_error(e.to_string()))
+            }
+            _ => Err(ConfigError::UnsupportedFormat(path.to_path_buf())),
+        }
+    }
+
+    /// Layer the file under `config`
+    ///
+    /// `explicit` is called with a `Config` field name and returns true when
+    /// that field was set on the command line or in the environment.
+    pub fn apply(self, config: &mut Config, explicit: impl Fn(&str) -> bool) {
+        let Self {
+            stores,
+            server,
+            storage,
+            encoding,
+            limits,
+            caching,
+            auth,
+            telemetry,
+        } = self;
+
+        if let Some(stores) = stores {
+            config.stores = stores;
+        }
+        layer!(
+            config,
+            explicit,
+            listen_addr = server.listen_addr,
+            shutdown_timeout_secs = server.shutdown_timeout_secs,
+        );
+        layer!(
+            config,
+            explicit,
+            storage_backend = storage.backend,
+            local_path = storage.local_path,
+            s3_bucket = storage.s3_bucket,
+            s3_region = storage.s3_region,
+            s3_endpoint = storage.s3_endpoint.map(Some),
+            s3_access_key = storage.s3_access_key.map(Some),
+            s3_secret_key = storage.s3_secret_key.map(Some),
+            s3_session_token = storage.s3_session_token.map(Some),
+            s3_profile = storage.s3_profile.map(Some),
+            gcs_bucket = storage.gcs_bucket,
+            gcs_service_account_path = storage.gcs_service_account_path.map(Some),
+            gcs_service_account_key = storage.gcs_service_account_key.map(Some),
+            gcs_endpoint = storage.gcs_endpoint.map(Some),
+            azure_account = storage.azure_account,
+            azure_container = storage.azure_container,
+            azure_access_key = storage.azure_access_key.map(Some),
+            azure_sas_token = storage.azure_sas_token.map(Some),
+            azure_client_id = storage.azure_client_id.map(Some),
+            azure_client_secret = storage.azure_client_secret.map(Some),
+            azure_tenant_id = storage.azure_tenant_id.map(Some),
+            azure_federated_token_file = storage.azure_federated_token_file.map(Some),
+            azure_endpoint = storage.azure_endpoint.map(Some),
+            azure_use_emulator = storage.azure_use_emulator,
+            http_url = storage.http_url,
+            http_headers = storage.http_headers,
+            http_bearer_token = storage.http_bearer_token.map(Some),
+            http_range_probe = storage.http_range_probe.map(Some),
+            storage_timeout_ms = storage.timeout_ms,
+            storage_max_retries = storage.max_retries,
+            storage_retry_backoff_ms = storage.retry_backoff_ms,
+            storage_retry_max_backoff_ms = storage.retry_max_backoff_ms,
+            storage_hedge_after_ms = storage.hedge_after_ms,
+            storage_breaker_threshold = storage.breaker_threshold,
+            storage_breaker_cooldown_ms = storage.breaker_cooldown_ms,
+            storage_part_size = storage.part_size,
+            storage_fetch_concurrency = storage.fetch_concurrency,
+            storage_coalesce_gap = storage.coalesce_gap,
+            allowed_prefixes = storage.allowed_prefixes,
+            allowed_extensions = storage.allowed_extensions,
+            max_path_length = storage.max_path_length,
+        );
+        layer!(
+            config,
+            explicit,
+            jpeg_quality = encoding.jpeg_quality,
+            min_jpeg_quality = encoding.min_jpeg_quality,
+        );
+        layer!(
+            config,
+            explicit,
+            max_frames_per_request = limits.max_frames_per_request,
+            max_fps_per_client = limits.max_fps_per_client,
+            max_bytes_per_minute = limits.max_bytes_per_minute,
+            max_sessions = limits.max_sessions,
+            default_credit_window = limits.default_credit_window,
+            slow_client_threshold_ms = limits.slow_client_threshold_ms,
+            slow_client_timeout_ms = limits.slow_client_timeout_ms,
+            ready_max_frames_in_flight = limits.ready_max_frames_in_flight,
+        );
+        layer!(
+            config,
+            explicit,
+            http_cache_max_age = caching.http_cache_max_age,
+            fetch_cache_bytes = caching.fetch_cache_bytes,
+            fetch_cache_block_size = caching.fetch_cache_block_size,
+            disk_cache_dir = caching.disk_cache_dir.map(Some),
+            disk_cache_bytes = caching.disk_cache_bytes,
+            prefetch_gops = caching.prefetch_gops,
+            prefetch_max_bytes = caching.prefetch_max_bytes,
+            prefetch_concurrency = caching.prefetch_concurrency,
+            offsets_write_back = caching.offsets_write_back,
+        );
+        layer!(
+            config,
+            explicit,
+            auth_api_keys = auth.api_keys,
+            auth_hmac_secret = auth.hmac_secret.map(Some),
+            auth_jwks_file = auth.jwks_file.map(Some),
+            auth_jwt_issuer = auth.jwt_issuer.map(Some),
+            auth_jwt_audience = auth.jwt_audience.map(Some),
+            url_signing_key = auth.url_signing_key.map(Some),
+            signed_url_max_ttl = auth.signed_url_max_ttl,
+        );
+        layer!(
+            config,
+            explicit,
+            log_level = telemetry.log_level,
+            otlp_endpoint = telemetry.otlp_endpoint.map(Some),
+            otel_service_name = telemetry.service_name,
+        );
+    }
+}
@@ -0,0 +1,1004 @@
+use std::path::PathBuf;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+mod file;
+mod reload;
+
+pub use file::{FileConfig, StorageSection, StoreConfig};
+pub use reload::{ConfigHandle, ReloadSummary};
+
+/// MinIO's out-of-the-box access and secret key
+const MINIO_DEFAULT_KEY: &str = "minioadmin";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Local,
+    S3,
+    Gcs,
+    Azure,
+    Http,
+}
+
+impl StorageBackend {
+    /// Whether listings come back sorted by key, so a page can stop early
+    pub fn lists_in_order(self) -> bool {
+        matches!(self, Self::S3 | Self::Gcs | Self::Azure)
+    }
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+#[command(name = "bucket-streamer")]
+#[command(about = "Video frame streaming server")]
+#[command(version)]
+pub struct Config {
+    /// TOML or YAML config file; environment variables and flags override it
+    #[arg(long = "config", env = "CONFIG_FILE")]
+    pub config_file: Option<PathBuf>,
+
+    /// Server listen address
+    #[arg(long, env = "LISTEN_ADDR", default_value = "0.0.0.0:3000")]
+    pub listen_addr: String,
+
+    /// Storage backend: "local", "s3", "gcs", "azure" or "http"
+    #[arg(long, env = "STORAGE_BACKEND", default_value = "local")]
+    pub storage_backend: StorageBackend,
+
+    /// Local storage path (when using local backend)
+    #[arg(long, env = "LOCAL_PATH", default_value = "./data")]
+    pub local_path: String,
+
+    /// S3 bucket name (when using s3 backend)
+    #[arg(long, env = "S3_BUCKET", default_value = "")]
+    pub s3_bucket: String,
+
+    /// S3 region (when using s3 backend)
+    #[arg(long, env = "S3_REGION", default_value = "us-east-1")]
+    pub s3_region: String,
+
+    /// S3 endpoint URL (for MinIO or custom S3)
+    #[arg(long, env = "S3_ENDPOINT")]
+    pub s3_endpoint: Option<String>,
+
+    /// S3 access key (unset = standard AWS credential chain)
+    #[arg(long, env = "S3_ACCESS_KEY")]
+    pub s3_access_key: Option<String>,
+
+    /// S3 secret key, set together with the access key
+    #[arg(long, env = "S3_SECRET_KEY")]
+    pub s3_secret_key: Option<String>,
+
+    /// S3 session token for temporary access keys
+    #[arg(long, env = "S3_SESSION_TOKEN")]
+    pub s3_session_token: Option<String>,
+
+    /// Profile in the shared AWS credentials/config files (default: `AWS_PROFILE`)
+    #[arg(long, env = "S3_PROFILE")]
+    pub s3_profile: Option<String>,
+
+    /// GCS bucket name (when using gcs backend)
+    #[arg(long, env = "GCS_BUCKET", default_value = "")]
+    pub gcs_bucket: String,
+
+    /// GCS service account JSON file (unset = application default credentials)
+    #[arg(long, env = "GCS_SERVICE_ACCOUNT_PATH")]
+    pub gcs_service_account_path: Option<String>,
+
+    /// GCS service account JSON, inline
+    #[arg(long, env = "GCS_SERVICE_ACCOUNT_KEY")]
+    pub gcs_service_account_key: Option<String>,
+
+    /// GCS base URL, e.g. a private endpoint or an emulator such as
+    /// fake-gcs-server; OAuth is disabled only when no service account is set
+    #[arg(long, env = "GCS_ENDPOINT")]
+    pub gcs_endpoint: Option<String>,
+
+    /// Azure storage account (when using azure backend)
+    #[arg(long, env = "AZURE_STORAGE_ACCOUNT", default_value = "")]
+    pub azure_account: String,
+
+    /// Azure blob container (when using azure backend)
+    #[arg(long, env = "AZURE_CONTAINER", default_value = "")]
+    pub azure_container: String,
+
+    /// Azure storage account key
+    #[arg(long, env = "AZURE_STORAGE_ACCESS_KEY")]
+    pub azure_access_key: Option<String>,
+
+    /// Azure shared access signature, e.g. `sv=...&sig=...`
+    #[arg(long, env = "AZURE_STORAGE_SAS_TOKEN")]
+    pub azure_sas_token: Option<String>,
+
+    /// Azure AD application (client) ID for service principal or workload identity
+    #[arg(long, env = "AZURE_CLIENT_ID")]
+    pub azure_client_id: Option<String>,
+
+    /// Azure AD client secret for a service principal
+    #[arg(long, env = "AZURE_CLIENT_SECRET")]
+    pub azure_client_secret: Option<String>,
+
+    /// Azure AD tenant ID
+    #[arg(long, env = "AZURE_TENANT_ID")]
+    pub azure_tenant_id: Option<String>,
+
+    /// Workload identity token file (AKS)
+    #[arg(long, env = "AZURE_FEDERATED_TOKEN_FILE")]
+    pub azure_federated_token_file: Option<String>,
+
+    /// Azure blob endpoint, for sovereign clouds or private endpoints
+    #[arg(long, env = "AZURE_STORAGE_ENDPOINT")]
+    pub azure_endpoint: Option<String>,
+
+    /// Use the Azurite emulator (`AZURITE_BLOB_STORAGE_URL`, default
+    /// `http://127.0.0.1:10000`)
+    #[arg(long, env = "AZURE_STORAGE_USE_EMULATOR")]
+    pub azure_use_emulator: bool,
+
+    /// Base URL of an HTTP(S) file server or CDN (when using http backend)
+    #[arg(long, env = "HTTP_URL", default_value = "")]
+    pub http_url: String,
+
+    /// Extra header for every origin request as `Name: value`; repeat the
+    /// flag for more (values may contain commas, so there is no delimiter)
+    #[arg(long = "http-header", env = "HTTP_HEADERS")]
+    pub http_headers: Vec<String>,
+
+    /// Bearer token sent to the HTTP origin
+    #[arg(long, env = "HTTP_BEARER_TOKEN")]
+    pub http_bearer_token: Option<String>,
+
+    /// Object read with a one-byte range at startup, to check the origin
+    /// honours `Range` requests
+    #[arg(long, env = "HTTP_RANGE_PROBE")]
+    pub http_range_probe: Option<String>,
+
+    /// Per-attempt limit on storage reads in milliseconds (0 = none)
+    #[arg(long, env = "STORAGE_TIMEOUT_MS", default_value = "10000")]
+    pub storage_timeout_ms: u64,
+
+    /// Retries after a throttled or transient storage failure
+    #[arg(long, env = "STORAGE_MAX_RETRIES", default_value = "3")]
+    pub storage_max_retries: u32,
+
+    /// Longest delay before the first retry in milliseconds; doubles per
+    /// retry, with full jitter
+    #[arg(long, env = "STORAGE_RETRY_BACKOFF_MS", default_value = "100")]
+    pub storage_retry_backoff_ms: u64,
+
+    /// Cap on the retry delay in milliseconds
+    #[arg(long, env = "STORAGE_RETRY_MAX_BACKOFF_MS", default_value = "5000")]
+    pub storage_retry_max_backoff_ms: u64,
+
+    /// Send a second range read when the first takes longer than this many
+    /// milliseconds (0 = never)
+    #[arg(long, env = "STORAGE_HEDGE_AFTER_MS", default_value = "0")]
+    pub storage_hedge_after_ms: u64,
+
+    /// Consecutive storage failures that open a store's circuit breaker
+    /// (0 = never)
+    #[arg(long, env = "STORAGE_BREAKER_THRESHOLD", default_value = "5")]
+    pub storage_breaker_threshold: u32,
+
+    /// How long an open circuit breaker refuses reads in milliseconds
+    #[arg(long, env = "STORAGE_BREAKER_COOLDOWN_MS", default_value = "30000")]
+    pub storage_breaker_cooldown_ms: u64,
+
+    /// Split range reads larger than this many bytes into concurrent parts
+    #[arg(long, env = "STORAGE_PART_SIZE", default_value = "8388608")]
+    pub storage_part_size: u64,
+
+    /// Parts or merged ranges of one read fetched at a time
+    #[arg(long, env = "STORAGE_FETCH_CONCURRENCY", default_value = "4")]
+    pub storage_fetch_concurrency: usize,
+
+    /// Merge ranges of a multi-range read that are at most this many bytes
+    /// apart into one fetch
+    #[arg(long, env = "STORAGE_COALESCE_GAP", default_value = "1048576")]
+    pub storage_coalesce_gap: u64,
+
+    /// Extra stores addressable by URL; set in the config file only
+    #[arg(skip)]
+    #[serde(default)]
+    pub stores: Vec<StoreConfig>,
+
+    /// JPEG encoding quality (1-100)
+    #[arg(long, env = "JPEG_QUALITY", default_value = "80")]
+    pub jpeg_quality: u8,
+
+    /// Cache-Control max-age for HTTP frame responses (seconds)
+    #[arg(long, env = "HTTP_CACHE_MAX_AGE", default_value = "86400")]
+    pub http_cache_max_age: u64,
+
+    /// Memory for cached object blocks, per store, in bytes (0 = no cache)
+    #[arg(long, env = "FETCH_CACHE_BYTES", default_value = "268435456")]
+    pub fetch_cache_bytes: u64,
+
+    /// Size of the blocks objects are cached in, in bytes
+    #[arg(long, env = "FETCH_CACHE_BLOCK_SIZE", default_value = "1048576")]
+    pub fetch_cache_block_size: u64,
+
+    /// Directory for an on-disk block cache between the memory cache and
+    /// storage (unset = no disk cache)
+    #[arg(long, env = "DISK_CACHE_DIR")]
+    pub disk_cache_dir: Option<String>,
+
+    /// Disk space for cached object blocks, per store, in bytes
+    #[arg(long, env = "DISK_CACHE_BYTES", default_value = "10737418240")]
+    pub disk_cache_bytes: u64,
+
+    /// GOPs to read ahead of a client moving forward through a video
+    /// (0 = no read-ahead)
+    #[arg(long, env = "PREFETCH_GOPS", default_value = "2")]
+    pub prefetch_gops: u32,
+
+    /// Cap on the bytes read ahead of one client, in bytes
+    #[arg(long, env = "PREFETCH_MAX_BYTES", default_value = "67108864")]
+    pub prefetch_max_bytes: u64,
+
+    /// Read-ahead fetches running at once across all clients; further
+    /// read-ahead is skipped until one finishes
+    #[arg(long, env = "PREFETCH_CONCURRENCY", default_value = "16")]
+    pub prefetch_concurrency: usize,
+
+    /// Write offsets indexes generated for videos without a sidecar back to
+    /// the store, next to the video (off: a GET never writes to storage,
+    /// and each process keeps the indexes it generated in memory instead)
+    #[arg(long, env = "OFFSETS_WRITE_BACK")]
+    pub offsets_write_back: bool,
+
+    /// Static API keys, comma-separated; `key=prefix1|prefix2` limits a key
+    /// to those path prefixes
+    #[arg(long, env = "AUTH_API_KEYS", value_delimiter = ',')]
+    pub auth_api_keys: Vec<String>,
+
+    /// Shared secret for HMAC-signed (HS256) tokens
+    #[arg(long, env = "AUTH_HMAC_SECRET")]
+    pub auth_hmac_secret: Option<String>,
+
+    /// JWKS file used to verify asymmetrically signed JWTs
+    #[arg(long, env = "AUTH_JWKS_FILE")]
+    pub auth_jwks_file: Option<String>,
+
+    /// Required `iss` claim for tokens
+    #[arg(long, env = "AUTH_JWT_ISSUER")]
+    pub auth_jwt_issuer: Option<String>,
+
+    /// Required `aud` claim for tokens
+    #[arg(long, env = "AUTH_JWT_AUDIENCE")]
+    pub auth_jwt_audience: Option<String>,
+
+    /// Secret for signing pre-signed frame URLs (disabled when unset)
+    #[arg(long, env = "URL_SIGNING_KEY")]
+    pub url_signing_key: Option<String>,
+
+    /// Longest lifetime a pre-signed URL may be minted with (seconds)
+    #[arg(long, env = "SIGNED_URL_MAX_TTL", default_value = "604800")]
+    pub signed_url_max_ttl: u64,
+
+    /// Prefixes client-supplied paths must fall under, comma-separated
+    /// (empty = anywhere in the store)
+    #[arg(long, env = "ALLOWED_PREFIXES", value_delimiter = ',')]
+    pub allowed_prefixes: Vec<String>,
+
+    /// Video file extensions clients may open, comma-separated (empty = any)
+    #[arg(long, env = "ALLOWED_EXTENSIONS", value_delimiter = ',')]
+    pub allowed_extensions: Vec<String>,
+
+    /// Maximum length of a client-supplied path in bytes
+    #[arg(long, env = "MAX_PATH_LENGTH", default_value = "1024")]
+    pub max_path_length: usize,
+
+    /// Maximum frames in one request (0 = unlimited)
+    #[arg(long, env = "MAX_FRAMES_PER_REQUEST", default_value = "1000")]
+    pub max_frames_per_request: usize,
+
+    /// Frames per second per client or token (0 = unlimited)
+    #[arg(long, env = "MAX_FPS_PER_CLIENT", default_value = "0")]
+    pub max_fps_per_client: u32,
+
+    /// Encoded bytes per minute per client or token (0 = unlimited)
+    #[arg(long, env = "MAX_BYTES_PER_MINUTE", default_value = "0")]
+    pub max_bytes_per_minute: u64,
+
+    /// Maximum concurrent WebSocket sessions (0 = unlimited)
+    #[arg(long, env = "MAX_SESSIONS", default_value = "0")]
+    pub max_sessions: usize,
+
+    /// Frames in flight per WebSocket session before waiting for `Ack`
+    /// (0 = unlimited until the client sends `Credit`)
+    #[arg(long, env = "DEFAULT_CREDIT_WINDOW", default_value = "0")]
+    pub default_credit_window: u32,
+
+    /// Time to send a frame, after any wait for credit, above which a
+    /// client counts as slow (ms)
+    #[arg(long, env = "SLOW_CLIENT_THRESHOLD_MS", default_value = "1000")]
+    pub slow_client_threshold_ms: u64,
+
+    /// Disconnect a client whose socket cannot take a frame for this long,
+    /// not counting any wait for credit (ms, 0 = never)
+    #[arg(long, env = "SLOW_CLIENT_TIMEOUT_MS", default_value = "30000")]
+    pub slow_client_timeout_ms: u64,
+
+    /// Lowest JPEG quality slow clients are downgraded to
+    #[arg(long, env = "MIN_JPEG_QUALITY", default_value = "40")]
+    pub min_jpeg_quality: u8,
+
+    /// How often to check `--config` for changes (seconds, 0 = only on SIGHUP)
+    #[arg(long, env = "CONFIG_WATCH_INTERVAL_SECS", default_value = "10")]
+    pub config_watch_interval_secs: u64,
+
+    /// Frames processing at once above which `/health/ready` fails (0 = no limit)
+    #[arg(long, env = "READY_MAX_FRAMES_IN_FLIGHT", default_value = "256")]
+    pub ready_max_frames_in_flight: usize,
+
+    /// Time to let sessions and requests finish after SIGTERM (seconds)
+    #[arg(long, env = "SHUTDOWN_TIMEOUT_SECS", default_value = "30")]
+    pub shutdown_timeout_secs: u64,
+
+    /// OTLP gRPC endpoint for trace export, e.g. `http://localhost:4317`
+    /// (unset = tracing is only logged)
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Service name attached to exported traces
+    #[arg(long, env = "OTEL_SERVICE_NAME", default_value = "bucket-streamer")]
+    pub otel_service_name: String,
+
+    /// Log level (trace, debug, info, warn, error)
+    #[arg(long, env = "RUST_LOG", default_value = "info")]
+    pub log_level: String,
+}
+
+impl Config {
+    fn from_matches(matches: &ArgMatches) -> Result<Self, ConfigError> {
+        let mut config = Self::from_arg_matches(matches).unwrap_or_else(|e| e.exit());
+        if let Some(path) = config.config_file.clone() {
+            let file = FileConfig::load(&path)?;
+            file.apply(&mut config, |id| set_explicitly(matches, id));
+        }
+        Ok(config)
+    }
+
+    /// Validate configuration values, reporting every invalid field
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        let mut invalid = |field: &'static str, message: &str| {
+            errors.push(FieldError {
+                field,
+                message: message.to_string(),
+            });
+        };
+
+        if self.storage_backend == StorageBackend::S3 && self.s3_bucket.is_empty() {
+            invalid("s3_bucket", "required when using s3 backend");
+        }
+        if self.s3_access_key.is_some() != self.s3_secret_key.is_some() {
+            invalid("s3_secret_key", "must be set together with s3_access_key");
+        }
+        if self.s3_session_token.is_some() && self.s3_access_key.is_none() {
+            invalid(
+                "s3_session_token",
+                "requires s3_access_key and s3_secret_key",
+            );
+        }
+        if self.storage_backend == StorageBackend::S3
+            && self.s3_endpoint.is_none()
+            && self.s3_access_key.as_deref() == Some(MINIO_DEFAULT_KEY)
+            && self.s3_secret_key.as_deref() == Some(MINIO_DEFAULT_KEY)
+        {
+            invalid(
+                "s3_access_key",
+                "default MinIO credentials are only allowed with s3_endpoint",
+            );
+        }
+        if self.storage_backend == StorageBackend::Gcs {
+            if self.gcs_bucket.is_empty() {
+                invalid("gcs_bucket", "required when using gcs backend");
+            }
+            if self.gcs_service_account_path.is_some() && self.gcs_service_account_key.is_some() {
+                invalid(
+                    "gcs_service_account_path",
+                    "set at most one of gcs_service_account_path and gcs_service_account_key",
+                );
+            }
+        }
+        if self.storage_backend == StorageBackend::Azure {
+            if self.azure_container.is_empty() {
+                invalid("azure_container", "required when using azure backend");
+            }
+            if self.azure_account.is_empty() && !self.azure_use_emulator {
+                invalid("azure_account", "required unless azure_use_emulator is set");
+            }
+            if self.azure_client_secret.is_some()
+                && (self.azure_client_id.is_none() || self.azure_tenant_id.is_none())
+            {
+                invalid(
+                    "azure_client_secret",
+                    "requires azure_client_id and azure_tenant_id",
+                );
+            }
+        }
+        if self.storage_backend == StorageBackend::Http
+            && !(self.http_url.starts_with("http://") || self.http_url.starts_with("https://"))
+        {
+            invalid(
+                "http_url",
+                "an http:// or https:// URL is required when using http backend",
+            );
+        }
+        for header in &self.http_headers {
+            match header.split_once(':') {
+                Some((name, _)) if !name.trim().is_empty() => {
+                    if self.http_bearer_token.is_some()
+                        && name.trim().eq_ignore_ascii_case("authorization")
+                    {
+                        invalid(
+                            "http_bearer_token",
+                            "conflicts with an Authorization header in http_headers",
+                        );
+                    }
+                }
+                _ => invalid(
+                    "http_headers",
+                    &format!("'{}' is not a `Name: value` header", header),
+                ),
+            }
+        }
+        if self.storage_retry_backoff_ms > self.storage_retry_max_backoff_ms {
+            invalid(
+                "storage_retry_backoff_ms",
+                "must not exceed storage_retry_max_backoff_ms",
+            );
+        }
+        if self.storage_part_size == 0 {
+            invalid("storage_part_size", "must be at least 1");
+        }
+        if self.storage_fetch_concurrency == 0 {
+            invalid("storage_fetch_concurrency", "must be at least 1");
+        }
+        if self.fetch_cache_block_size == 0 {
+            invalid("fetch_cache_block_size", "must be at least 1");
+        }
+        if self.prefetch_concurrency == 0 {
+            invalid("prefetch_concurrency", "must be at least 1");
+        }
+        if self.disk_cache_dir.is_some() && self.disk_cache_bytes == 0 {
+            invalid(
+                "disk_cache_bytes",
+                "must be at least 1 with a disk_cache_dir",
+            );
+        }
+        for (i, store) in self.stores.iter().enumerate() {
+            if store.name.is_empty() {
+                invalid("stores", "every store needs a name");
+            } else if self.stores[..i]
+                .iter()
+                .any(|other| other.name == store.name)
+            {
+                invalid("stores", &format!("duplicate store name '{}'", store.name));
+            }
+            if !store.url.contains("://") {
+                invalid(
+                    "stores",
+                    &format!("store '{}' url must be a storage URL", store.name),
+                );
+            }
+            if store.storage.part_size == Some(0) {
+                invalid(
+                    "stores",
+                    &format!("store '{}' part_size must be at least 1", store.name),
+                );
+            }
+            for key in store.unknown.keys() {
+                invalid(
+                    "stores",
+                    &format!("store '{}' has unknown setting '{}'", store.name, key),
+                );
+            }
+        }
+        if self.storage_backend == StorageBackend::Local && self.local_path.is_empty() {
+            invalid("local_path", "required when using local backend");
+        }
+        if !(1..=100).contains(&self.jpeg_quality) {
+            invalid("jpeg_quality", "must be between 1 and 100");
+        }
+        if !(1..=100).contains(&self.min_jpeg_quality) {
+            invalid("min_jpeg_quality", "must be between 1 and 100");
+        } else if self.min_jpeg_quality > self.jpeg_quality {
+            invalid("min_jpeg_quality", "must not exceed jpeg_quality");
+        }
+        if self.max_path_length == 0 {
+            invalid("max_path_length", "must be greater than 0");
+        }
+        if self
+            .auth_api_keys
+            .iter()
+            .any(|entry| entry.trim().is_empty())
+        {
+            invalid("auth_api_keys", "must not contain empty keys");
+        }
+        if self.auth_hmac_secret.as_deref() == Some("") {
+            invalid("auth_hmac_secret", "must not be empty");
+        }
+        if self.url_signing_key.as_deref() == Some("") {
+            invalid("url_signing_key", "must not be empty");
+        }
+        if self.url_signing_key.is_some() && self.signed_url_max_ttl == 0 {
+            invalid("signed_url_max_ttl", "must be greater than 0");
+        }
+        if self.slow_client_threshold_ms == 0 {
+            invalid("slow_client_threshold_ms", "must be greater than 0");
+        }
+        if let Some(endpoint) = &self.otlp_endpoint {
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                invalid("otlp_endpoint", "must be an http:// or https:// URL");
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+/// Parsed CLI args and environment, kept so the config can be re-read
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    matches: ArgMatches,
+}
+
+impl ConfigSource {
+    /// Parse CLI args and environment
+    pub fn from_args() -> Self {
+        Self {
+            matches: Config::command().get_matches(),
+        }
+    }
+
+    /// Build the config, layered over `--config` if given
+    ///
+    /// Precedence, highest first: flags, environment, config file, defaults.
+    /// Calling this again re-reads the file.
+    pub fn load(&self) -> Result<Config, ConfigError> {
+        Config::from_matches(&self.matches)
+    }
+}
+
+/// Whether a flag or environment variable set `id`, so the file must not
+fn set_explicitly(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            config_file: None,
+            listen_addr: "0.0.0.0:3000".to_string(),
+            storage_backend: StorageBackend::Local,
+            local_path: "./data".to_string(),
+            s3_bucket: "".to_string(),
+            s3_region: "us-east-1".to_string(),
+            s3_endpoint: None,
+            s3_access_key: None,
+            s3_secret_key: None,
+            s3_session_token: None,
+            s3_profile: None,
+            gcs_bucket: "".to_string(),
+            gcs_service_account_path: None,
+            gcs_service_account_key: None,
+            gcs_endpoint: None,
+            azure_account: "".to_string(),
+            azure_container: "".to_string(),
+            azure_access_key: None,
+            azure_sas_token: None,
+            azure_client_id: None,
+            azure_client_secret: None,
+            azure_tenant_id: None,
+            azure_federated_token_file: None,
+            azure_endpoint: None,
+            azure_use_emulator: false,
+            http_url: String::new(),
+            http_headers: Vec::new(),
+            http_bearer_token: None,
+            http_range_probe: None,
+            storage_timeout_ms: 10000,
+            storage_max_retries: 3,
+            storage_retry_backoff_ms: 100,
+            storage_retry_max_backoff_ms: 5000,
+            storage_hedge_after_ms: 0,
+            storage_breaker_threshold: 5,
+            storage_breaker_cooldown_ms: 30000,
+            storage_part_size: 8 * 1024 * 1024,
+            storage_fetch_concurrency: 4,
+            storage_coalesce_gap: 1024 * 1024,
+            stores: Vec::new(),
+            jpeg_quality: 80,
+            http_cache_max_age: 86400,
+            fetch_cache_bytes: 256 * 1024 * 1024,
+            fetch_cache_block_size: 1024 * 1024,
+            disk_cache_dir: None,
+            disk_cache_bytes: 10 * 1024 * 1024 * 1024,
+            prefetch_gops: 2,
+            prefetch_max_bytes: 64 * 1024 * 1024,
+            prefetch_concurrency: 16,
+            offsets_write_back: false,
+            auth_api_keys: Vec::new(),
+            auth_hmac_secret: None,
+            auth_jwks_file: None,
+            auth_jwt_issuer: None,
+            auth_jwt_audience: None,
+            url_signing_key: None,
+            signed_url_max_ttl: 604800,
+            allowed_prefixes: Vec::new(),
+            allowed_extensions: Vec::new(),
+            max_path_length: 1024,
+            max_frames_per_request: 1000,
+            max_fps_per_client: 0,
+            max_bytes_per_minute: 0,
+            max_sessions: 0,
+            default_credit_window: 0,
+            slow_client_threshold_ms: 1000,
+            slow_client_timeout_ms: 30000,
+            min_jpeg_quality: 40,
+            config_watch_interval_secs: 10,
+            ready_max_frames_in_flight: 256,
+            shutdown_timeout_secs: 30,
+            otlp_endpoint: None,
+            otel_service_name: "bucket-streamer".to_string(),
+            log_level: "info".to_string(),
+        }
+    }
+}
+
+/// One invalid setting, named by its `Config` field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("failed to parse config file {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+
+    #[error("unsupported config file extension for {0} (expected .toml, .yaml or .yml)")]
+    UnsupportedFormat(PathBuf),
+
+    #[error("invalid configuration: {}", join_errors(.0))]
+    Invalid(Vec<FieldError>),
+}
+
+fn join_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_s3_requires_bucket() {
+        let mut config = Config::default();
+        config.storage_backend = StorageBackend::S3;
+        config.s3_bucket = "".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_zero_part_size_rejected() {
+        let config = Config {
+            storage_part_size: 0,
+            ..Config::default()
+        };
+        let Err(ConfigError::Invalid(errors)) = config.validate() else {
+            panic!("expected validation errors");
+        };
+        assert_eq!(errors[0].field, "storage_part_size");
+    }
+
+    #[test]
+    fn test_validate_reports_every_field() {
+        let config = Config {
+            storage_backend: StorageBackend::S3,
+            jpeg_quality: 0,
+            url_signing_key: Some(String::new()),
+            otlp_endpoint: Some("localhost:4317".to_string()),
+            ..Config::default()
+        };
+        let Err(ConfigError::Invalid(errors)) = config.validate() else {
+            panic!("expected validation errors");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(
+            fields,
+            [
+                "s3_bucket",
+                "jpeg_quality",
+                "min_jpeg_quality",
+                "url_signing_key",
+                "otlp_endpoint"
+            ]
+        );
+    }
+
+    fn write_file(dir: &tempfile::TempDir, name: &str, contents: &str) -> String {
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        let matches = Config::command()
+            .try_get_matches_from(std::iter::once("bucket-streamer").chain(args.iter().copied()))
+            .unwrap();
+        Config::from_matches(&matches)
+    }
+
+    #[test]
+    fn test_config_file_layering() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = write_file(
+            &dir,
+            "streamer.toml",
+            r#"
+            [storage]
+            backend = "s3"
+            s3_bucket = "from-file"
+            s3_endpoint = "http://minio:9000"
+
+            [encoding]
+            jpeg_quality = 70
+
+            [limits]
+            max_sessions = 12
+
+            [caching]
+            offsets_write_back = true
+
+            [auth]
+            api_keys = ["a", "b=team"]
+            "#,
+        );
+
+        let config = parse(&["--config", &path, "--jpeg-quality", "95"]).unwrap();
+        assert_eq!(config.storage_backend, StorageBackend::S3);
+        assert_eq!(config.s3_bucket, "from-file");
+        assert_eq!(config.s3_endpoint.as_deref(), Some("http://minio:9000"));
+        assert_eq!(config.max_sessions, 12);
+        assert_eq!(config.auth_api_keys, ["a", "b=team"]);
+        assert!(config.offsets_write_back);
+        // Flags win over the file; unset sections keep defaults
+        assert_eq!(config.jpeg_quality, 95);
+        assert_eq!(config.http_cache_max_age, 86400);
+    }
+
+    #[test]
+    fn test_yaml_config_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = write_file(
+            &dir,
+            "streamer.yaml",
+            "server:\n  listen_addr: 127.0.0.1:8080\ncaching:\n  http_cache_max_age: 60\n",
+        );
+
+        let config = parse(&["--config", &path]).unwrap();
+        assert_eq!(config.listen_addr, "127.0.0.1:8080");
+        assert_eq!(config.http_cache_max_age, 60);
+    }
+
+    #[test]
+    fn test_config_file_stores() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = write_file(
+            &dir,
+            "streamer.toml",
+            r#"
+            [[stores]]
+            name = "archive"
+            url = "gs://archive-footage"
+            gcs_service_account_path = "/etc/gcs/archive.json"
+            allowed_extensions = ["h265"]
+            "#,
+        );
+
+        let config = parse(&["--config", &path]).unwrap();
+        let [store] = config.stores.as_slice() else {
+            panic!("expected one store");
+        };
+        assert_eq!(store.name, "archive");
+        assert_eq!(store.url, "gs://archive-footage");
+        assert_eq!(
+            store.storage.gcs_service_account_path.as_deref(),
+            Some("/etc/gcs/archive.json")
+        );
+        assert_eq!(store.storage.allowed_extensions, Some(vec!["h265".into()]));
+
+        let typo = write_file(
+            &dir,
+            "typo.toml",
+            "[[stores]]\nname = \"a\"\nurl = \"s3://a\"\ns3_regon = \"eu-west-1\"\n\n\
+             [[stores]]\nname = \"a\"\nurl = \"bucket\"\n",
+        );
+        let config = parse(&["--config", &typo]).unwrap();
+        let Err(ConfigError::Invalid(errors)) = config.validate() else {
+            panic!("expected invalid stores");
+        };
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "store 'a' has unknown setting 's3_regon'",
+                "duplicate store name 'a'",
+                "store 'a' url must be a storage URL",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_file_errors() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let typo = write_file(&dir, "typo.toml", "[limits]\nmax_session = 3\n");
+        assert!(matches!(
+            parse(&["--config", &typo]),
+            Err(ConfigError::Parse { .. })
+        ));
+
+        let ini = write_file(&dir, "streamer.ini", "");
+        assert!(matches!(
+            parse(&["--config", &ini]),
+            Err(ConfigError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_s3_credentials() {
+        let minio = Config {
+            storage_backend: StorageBackend::S3,
+            s3_bucket: "videos".to_string(),
+            s3_access_key: Some("minioadmin".to_string()),
+            s3_secret_key: Some("minioadmin".to_string()),
+            ..Config::default()
+        };
+        assert!(minio.validate().is_err());
+        assert!(Config {
+            s3_endpoint: Some("http://localhost:9000".to_string()),
+            ..minio.clone()
+        }
+        .validate()
+        .is_ok());
+
+        let Err(ConfigError::Invalid(errors)) = Config {
+            s3_secret_key: None,
+            s3_session_token: Some("token".to_string()),
+            ..minio
+        }
+        .validate() else {
+            panic!("expected validation errors");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["s3_secret_key"]);
+    }
+
+    #[test]
+    fn test_cloud_backends() {
+        let gcs = Config {
+            storage_backend: StorageBackend::Gcs,
+            gcs_bucket: "footage".to_string(),
+            ..Config::default()
+        };
+        assert!(gcs.validate().is_ok());
+        assert!(Config {
+            gcs_endpoint: Some("http://localhost:4443".to_string()),
+            gcs_service_account_path: Some("/etc/sa.json".to_string()),
+            ..gcs.clone()
+        }
+        .validate()
+        .is_ok());
+        assert!(Config {
+            gcs_bucket: String::new(),
+            gcs_service_account_key: Some("{}".to_string()),
+            gcs_service_account_path: Some("/etc/sa.json".to_string()),
+            ..gcs
+        }
+        .validate()
+        .is_err());
+
+        let azure = Config {
+            storage_backend: StorageBackend::Azure,
+            azure_container: "footage".to_string(),
+            ..Config::default()
+        };
+        assert!(azure.validate().is_err());
+        assert!(Config {
+            azure_use_emulator: true,
+            ..azure.clone()
+        }
+        .validate()
+        .is_ok());
+        assert!(Config {
+            azure_account: "acct".to_string(),
+            ..azure
+        }
+        .validate()
+        .is_ok());
+    }
+
+    #[test]
+    fn test_http_backend() {
+        let http = Config {
+            storage_backend: StorageBackend::Http,
+            ..Config::default()
+        };
+        assert!(http.validate().is_err());
+
+        let http = Config {
+            http_url: "https://cdn.example.com/videos".to_string(),
+            http_headers: vec!["X-Api-Key: abc".to_string()],
+            ..http
+        };
+        assert!(http.validate().is_ok());
+
+        let Err(ConfigError::Invalid(errors)) = Config {
+            http_headers: vec!["authorization: Basic x".to_string(), "nocolon".to_string()],
+            http_bearer_token: Some("token".to_string()),
+            ..http
+        }
+        .validate() else {
+            panic!("expected invalid headers");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["http_bearer_token", "http_headers"]);
+
+        // Header values keep their commas
+        let config = parse(&[
+            "--http-header",
+            "Accept: image/webp, */*",
+            "--http-header",
+            "X-Api-Key: abc",
+        ])
+        .unwrap();
+        assert_eq!(
+            config.http_headers,
+            ["Accept: image/webp, */*", "X-Api-Key: abc"]
+        );
+    }
+
+    #[test]
+    fn test_s3_with_bucket_valid() {
+        let mut config = Config::default();
+        config.storage_backend = StorageBackend::S3;
+        config.s3_bucket = "my-bucket".to_string();
+        assert!(config.validate().is_ok());
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+
+use super::{Config, ConfigError};
+
+/// Declare the reloadable fields: [`RELOADABLE`] lists their names and
+/// `apply_reloadable` copies those that differ
+macro_rules! reloadable {
+    ($($field:ident),* $(,)?) => {
+        /// Fields that take effect without a restart
+        ///
+        /// Everything else is read once at startup (listener, storage, auth,
+        /// path policy, telemetry) and keeps its old value until the server
+        /// restarts.
+        pub const RELOADABLE: &[&str] = &[$(stringify!($field)),*];
+
+        /// Copy each reloadable field of `new` that differs into `config`,
+        /// recording it as `field: old -> new`
+        fn apply_reloadable(config: &mut Config, new: &Config, applied: &mut Vec<String>) {
+            $(
+                if config.$field != new.$field {
+                    applied.push(format!(
+                        "{}: {} -> {}",
+                        stringify!($field),
+                        config.$field,
+                        new.$field
+                    ));
+                    config.$field = new.$field;
+                }
+            )*
+        }
+    };
+}
+
+reloadable!(
+    jpeg_quality,
+    min_jpeg_quality,
+    http_cache_max_age,
+    fetch_cache_bytes,
+    prefetch_gops,
+    prefetch_max_bytes,
+    signed_url_max_ttl,
+    max_frames_per_request,
+    max_fps_per_client,
+    max_bytes_per_minute,
+    max_sessions,
+    default_credit_window,
+    slow_client_threshold_ms,
+    slow_client_timeout_ms,
+    ready_max_frames_in_flight,
+);
+
+/// What a reload changed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReloadSummary {
+    /// Applied fields as `field: old -> new`
+    pub applied: Vec<String>,
+    /// Changed fields that were ignored because they need a restart
+    pub restart_required: Vec<String>,
+}
+
+impl ReloadSummary {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart_required.is_empty()
+    }
+}
+
+/// Current configuration, swapped atomically on reload
+///
+/// Readers take a snapshot with [`ConfigHandle::current`]. A request keeps
+/// the snapshot it started with; long-lived sessions take a new one for
+/// each frame.
+#[derive(Debug, Clone)]
+pub struct ConfigHandle {
+    tx: Arc<watch::Sender<Arc<Config>>>,
+}
+
+impl ConfigHandle {
+    pub fn new(config: Config) -> Self {
+        let (tx, _) = watch::channel(Arc::new(config));
+        Self { tx: Arc::new(tx) }
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.tx.borrow().clone()
+    }
+
+    /// Validate `new` and apply its reloadable fields
+    ///
+    /// Nothing is applied if any field is invalid.
+    pub fn reload(&self, new: Config) -> Result<ReloadSummary, ConfigError> {
+        new.validate()?;
+
+        let current = self.current();
+        let (merged, summary) = merge(&current, &new);
+        if !summary.applied.is_empty() {
+            self.tx.send_replace(Arc::new(merged));
+        }
+        Ok(summary)
+    }
+}
+
+/// `current` with the reloadable fields of `new`, and what differed
+fn merge(current: &Config, new: &Config) -> (Config, ReloadSummary) {
+    let mut merged = current.clone();
+    let mut summary = ReloadSummary::default();
+    apply_reloadable(&mut merged, new, &mut summary.applied);
+
+    // Values are left out: these include credentials
+    let old = to_map(current);
+    summary.restart_required = to_map(new)
+        .into_iter()
+        .filter(|(field, value)| {
+            !RELOADABLE.contains(&field.as_str()) && old.get(field) != Some(value)
+        })
+        .map(|(field, _)| field)
+        .collect();
+
+    (merged, summary)
+}
+
+fn to_map(config: &Config) -> Map<String, Value> {
+    match serde_json::to_value(config) {
+        Ok(Value::Object(map)) => map,
+        _ => unreachable!("Config serializes to an object"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_applies_reloadable_fields() {
+        let handle = ConfigHandle::new(Config::default());
+        let summary = handle
+            .reload(Config {
+                jpeg_quality: 90,
+                max_sessions: 10,
+                listen_addr: "127.0.0.1:4000".to_string(),
+                s3_region: "eu-west-1".to_string(),
+                ..Config::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            summary.applied,
+            ["jpeg_quality: 80 -> 90", "max_sessions: 0 -> 10"]
+        );
+        assert_eq!(summary.restart_required, ["listen_addr", "s3_region"]);
+
+        let current = handle.current();
+        assert_eq!(current.jpeg_quality, 90);
+        assert_eq!(current.max_sessions, 10);
+        assert_eq!(current.listen_addr, "0.0.0.0:3000");
+        assert_eq!(current.s3_region, "us-east-1");
+    }
+
+    #[test]
+    fn test_reload_rejects_invalid_config() {
+        let handle = ConfigHandle::new(Config::default());
+        let result = handle.reload(Config {
+            jpeg_quality: 0,
+            max_sessions: 10,
+            ..Config::default()
+        });
+
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+        assert_eq!(handle.current().max_sessions, 0);
+    }
+
+    #[test]
+    fn test_reload_unchanged() {
+        let handle = ConfigHandle::new(Config::default());
+        assert!(handle.reload(Config::default()).unwrap().is_empty());
+    }
+}
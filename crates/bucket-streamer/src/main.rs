@@ -1,47 +1,86 @@
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 use anyhow::Result;
 use tokio::net::TcpListener;
-use tracing_subscriber::EnvFilter;
 
 mod config;
 mod pipeline;
 mod server;
 mod storage;
+mod telemetry;
 
-use config::Config;
+use config::ConfigSource;
 use server::{create_router, AppState};
 use storage::create_store;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let config = Config::parse_args();
+    let source = ConfigSource::from_args();
+    let config = source.load()?;
 
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_level)),
-        )
-        .init();
+    let _tracing = telemetry::init_tracing(&config)?;
 
     config.validate()?;
 
     tracing::info!("Starting bucket-streamer");
+    telemetry::install_metrics();
     tracing::debug!(?config, "Configuration loaded");
 
     // Create storage backend
     let store = create_store(&config)?;
 
-    let state = AppState {
-        config: Arc::new(config.clone()),
-        store,
-    };
+    let state = AppState::new(config.clone(), store)?;
+    state.stores.check_origins().await?;
+    let shutdown = state.shutdown.clone();
+    let limiter = state.limiter.clone();
+    tokio::spawn(server::reload::watch(state.clone(), source));
 
     let app = create_router(state);
 
     let listener = TcpListener::bind(&config.listen_addr).await?;
     tracing::info!("Listening on {}", config.listen_addr);
 
-    axum::serve(listener, app).await?;
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            server::shutdown::signal().await;
+            tracing::info!("Shutdown signal received; draining connections");
+            shutdown.trigger();
+        }
+    });
+
+    let serve = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let shutdown = shutdown.clone();
+        async move { shutdown.triggered().await }
+    });
+
+    // WebSocket sessions outlive their HTTP connection, so wait for them too
+    let drain = async {
+        serve.await?;
+        server::shutdown::sessions_closed(&limiter).await;
+        anyhow::Ok(())
+    };
+    let deadline = Duration::from_secs(config.shutdown_timeout_secs);
+
+    tokio::select! {
+        result = drain => result?,
+        _ = async {
+            shutdown.triggered().await;
+            tokio::time::sleep(deadline).await;
+        } => {
+            tracing::warn!(
+                "Shutdown deadline of {:?} passed with {} sessions open; exiting",
+                deadline,
+                limiter.active_sessions()
+            );
+        }
+    }
 
+    tracing::info!("Shutdown complete");
     Ok(())
 }
@@ -11,19 +11,106 @@ use std::ptr;
 /// Buffer size for AVIOContext (32KB is a good balance)
 const AVIO_BUFFER_SIZE: usize = 32 * 1024;
 
+/// Bytes of a video file handed to FFmpeg
+///
+/// Either the whole file, or the parts of it needed to decode one GOP: the
+/// container header and the GOP's media data. Reads between parts see
+/// zeros, so the demuxer can walk the sample tables without the rest of
+/// the media data being fetched.
+#[derive(Debug, Clone, Default)]
+pub struct VideoData {
+    len: u64,
+    /// Sorted by offset and non-overlapping
+    parts: Vec<(u64, Bytes)>,
+}
+
+impl VideoData {
+    /// A file of `len` bytes of which only `parts` are present
+    pub fn sparse(len: u64, mut parts: Vec<(u64, Bytes)>) -> Self {
+        parts.retain(|(_, data)| !data.is_empty());
+        parts.sort_by_key(|(offset, _)| *offset);
+        Self { len, parts }
+    }
+
+    /// File size in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether every byte of `range` is present
+    pub fn covers(&self, range: std::ops::Range<u64>) -> bool {
+        let mut pos = range.start;
+        for (offset, data) in &self.parts {
+            if pos >= range.end {
+                break;
+            }
+            let end = offset + data.len() as u64;
+            if *offset <= pos && pos < end {
+                pos = end;
+            }
+        }
+        pos >= range.end && range.end <= self.len
+    }
+
+    /// Copy the bytes at `position` into `buf`, zero-filling gaps, and
+    /// return how many were copied
+    fn read_at(&self, position: u64, buf: &mut [u8]) -> usize {
+        let len = (buf.len() as u64).min(self.len.saturating_sub(position)) as usize;
+        let buf = &mut buf[..len];
+        buf.fill(0);
+        let end = position + len as u64;
+        for (offset, data) in &self.parts {
+            let part_end = offset + data.len() as u64;
+            if part_end <= position {
+                continue;
+            }
+            if *offset >= end {
+                break;
+            }
+            let from = position.max(*offset);
+            let to = end.min(part_end);
+            buf[(from - position) as usize..(to - position) as usize]
+                .copy_from_slice(&data[(from - offset) as usize..(to - offset) as usize]);
+        }
+        len
+    }
+}
+
+impl From<Bytes> for VideoData {
+    fn from(data: Bytes) -> Self {
+        Self::sparse(data.len() as u64, vec![(0, data)])
+    }
+}
+
+impl From<&Bytes> for VideoData {
+    fn from(data: &Bytes) -> Self {
+        data.clone().into()
+    }
+}
+
+impl From<&VideoData> for VideoData {
+    fn from(data: &VideoData) -> Self {
+        data.clone()
+    }
+}
+
 /// Holds video data and read position for FFmpeg callbacks
 #[derive(Debug)]
 pub struct InMemoryIO {
-    data: Bytes,
-    position: usize,
+    data: VideoData,
+    position: u64,
 }
 
 impl InMemoryIO {
-    pub fn new(data: Bytes) -> Self {
+    pub fn new(data: VideoData) -> Self {
         Self { data, position: 0 }
     }
 
-    pub fn len(&self) -> usize {
+    pub fn len(&self) -> u64 {
         self.data.len()
     }
 
@@ -34,21 +121,20 @@ impl InMemoryIO {
 
 /// FFmpeg read callback - called when decoder needs more data
 unsafe extern "C" fn read_packet(opaque: *mut c_void, buf: *mut c_uchar, buf_size: c_int) -> c_int {
-    if opaque.is_null() || buf.is_null() {
+    if opaque.is_null() || buf.is_null() || buf_size <= 0 {
         return ffi::AVERROR_EOF;
     }
 
     let io = &mut *(opaque as *mut InMemoryIO);
-    let remaining = io.data.len().saturating_sub(io.position);
+    let buf = std::slice::from_raw_parts_mut(buf, buf_size as usize);
+    let read = io.data.read_at(io.position, buf);
 
-    if remaining == 0 {
+    if read == 0 {
         return ffi::AVERROR_EOF;
     }
 
-    let to_read = std::cmp::min(remaining, buf_size as usize);
-    ptr::copy_nonoverlapping(io.data[io.position..].as_ptr(), buf, to_read);
-    io.position += to_read;
-    to_read as c_int
+    io.position += read as u64;
+    read as c_int
 }
 
 /// FFmpeg seek callback - enables random access within buffer
@@ -76,7 +162,7 @@ unsafe extern "C" fn seek_packet(opaque: *mut c_void, offset: i64, whence: c_int
         return -1;
     }
 
-    io.position = new_pos as usize;
+    io.position = new_pos as u64;
     new_pos
 }
 
@@ -94,7 +180,8 @@ impl AvioContext {
     /// # Safety
     /// This function is safe to call, but the returned context
     /// contains raw pointers managed by FFmpeg.
-    pub fn new(data: Bytes) -> Result<Self, AvioError> {
+    pub fn new(data: impl Into<VideoData>) -> Result<Self, AvioError> {
+        let data = data.into();
         if data.is_empty() {
             return Err(AvioError::EmptyBuffer);
         }
@@ -155,9 +242,19 @@ impl Drop for AvioContext {
     }
 }
 
-// Safety: InMemoryIO contains only Bytes (which is Send+Sync) and usize
+// Safety: `ctx` and `_buffer` are owned by this value alone and FFmpeg only
+// uses them, and its callbacks only touch `io`, on the thread currently
+// calling into it through `&mut self`. `io` is a read position and a
+// `VideoData`: a length and a `Vec` of `(u64, Bytes)` parts, all `Send`,
+// with no thread-local or borrowed state (checked below). The box keeps its
+// address when the context moves between threads.
 unsafe impl Send for AvioContext {}
 
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<InMemoryIO>();
+};
+
 #[derive(Debug, thiserror::Error)]
 pub enum AvioError {
     #[error("Empty buffer provided")]
@@ -250,6 +347,33 @@ mod tests {
         assert!(matches!(ctx.unwrap_err(), AvioError::EmptyBuffer));
     }
 
+    #[test]
+    fn test_sparse_video_data() {
+        let data = VideoData::sparse(
+            20,
+            vec![
+                (12, Bytes::from_static(b"gop")),
+                (0, Bytes::from_static(b"head")),
+            ],
+        );
+
+        let mut buf = [0xffu8; 16];
+        assert_eq!(data.read_at(2, &mut buf), 16);
+        assert_eq!(&buf, b"ad\0\0\0\0\0\0\0\0gop\0\0\0");
+        assert_eq!(data.read_at(18, &mut buf), 2);
+        assert_eq!(data.read_at(20, &mut buf), 0);
+
+        assert!(data.covers(0..4));
+        assert!(data.covers(13..15));
+        assert!(!data.covers(3..13));
+        assert!(!data.covers(12..16));
+
+        let whole = VideoData::from(Bytes::from_static(b"video"));
+        assert_eq!(whole.len(), 5);
+        assert!(whole.covers(0..5));
+        assert!(!whole.covers(0..6));
+    }
+
     #[test]
     fn test_decode_first_frame() {
         ffmpeg::init().unwrap();
@@ -4,8 +4,13 @@ use ffmpeg_next::format::Pixel;
 use ffmpeg_next::packet::Mut as _;
 use ffmpeg_next::software::scaling::{Context as ScalerContext, Flags};
 use ffmpeg_sys_next::{self as ffi, AVFormatContext};
+use serde::{Deserialize, Serialize};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::time::{Duration, Instant};
 
-use super::avio::{open_format_context, AvioContext, AvioError};
+use super::avio::{open_format_context, AvioContext, AvioError, VideoData};
+use crate::telemetry::{Stage, StageTimer};
 
 /// Decoded video frame ready for JPEG encoding
 #[derive(Debug, Clone)]
@@ -34,6 +39,79 @@ impl DecodedFrame {
     }
 }
 
+/// Work done by the most recent [`Decoder::decode_frame`] call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// Video packets sent to the decoder to reach the target frame
+    pub packets: u32,
+    /// Frames between the last keyframe and the target, in decode order
+    /// (0 for the keyframe itself; `None` if decoding did not start at one)
+    pub gop_distance: Option<u32>,
+    /// Demuxing and decoding, excluding conversion
+    pub decode: Duration,
+    /// Pixel format conversion and scaling
+    pub convert: Duration,
+}
+
+/// Container and stream metadata gathered by [`Decoder::probe`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoInfo {
+    /// Demuxer name, e.g. `mov,mp4,m4a,3gp,3g2,mj2`
+    pub container: String,
+    /// Codec name, e.g. `hevc`
+    pub codec: String,
+    /// Codec profile, e.g. `Main`
+    pub profile: Option<String>,
+    /// Codec level as stored by FFmpeg (HEVC: 30 x level number)
+    pub level: Option<i32>,
+    pub width: u32,
+    pub height: u32,
+    /// Pixel format name, e.g. `yuv420p`
+    pub pixel_format: Option<String>,
+    /// Average frames per second
+    pub frame_rate: Option<f64>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+    /// Number of video packets in the container
+    pub frame_count: u64,
+    /// Number of keyframe (IRAP) packets
+    pub keyframe_count: u64,
+    /// GOP length statistics, in frames
+    pub gop: GopStats,
+}
+
+/// GOP length statistics, in frames
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GopStats {
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+}
+
+impl GopStats {
+    /// Summarise a list of GOP lengths
+    fn from_lengths(lengths: &[u64]) -> Self {
+        if lengths.is_empty() {
+            return Self::default();
+        }
+
+        Self {
+            min: *lengths.iter().min().unwrap(),
+            max: *lengths.iter().max().unwrap(),
+            mean: lengths.iter().sum::<u64>() as f64 / lengths.len() as f64,
+        }
+    }
+}
+
+/// Frame entry of an offsets index, matching `repo-cli`'s sidecar format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameOffset {
+    /// Byte offset of this frame in the file
+    pub offset: u64,
+    /// Byte offset of the IRAP (keyframe) needed to decode this frame
+    pub irap_offset: u64,
+}
+
 /// Decoder error types
 #[derive(Debug, thiserror::Error)]
 pub enum DecoderError {
@@ -88,9 +166,19 @@ pub struct Decoder {
     scaler: ScalerContext,
     width: u32,
     height: u32,
+    out_width: u32,
+    out_height: u32,
+    stats: DecodeStats,
 }
 
 impl Decoder {
+    /// Check that FFmpeg initializes and has an HEVC decoder
+    pub fn check_available() -> Result<(), DecoderError> {
+        ffmpeg::init().map_err(|_| DecoderError::FfmpegInit)?;
+        ffmpeg::decoder::find(ffmpeg::codec::Id::HEVC).ok_or(DecoderError::DecoderNotFound)?;
+        Ok(())
+    }
+
     /// Create decoder by probing video data to detect format
     ///
     /// # Arguments
@@ -99,10 +187,10 @@ impl Decoder {
     /// # Errors
     /// Returns error if FFmpeg init fails, no video stream found, or
     /// HEVC decoder is not available.
-    pub fn new(initial_data: &Bytes) -> Result<Self, DecoderError> {
+    pub fn new(initial_data: impl Into<VideoData>) -> Result<Self, DecoderError> {
         ffmpeg::init().map_err(|_| DecoderError::FfmpegInit)?;
 
-        let mut avio = AvioContext::new(initial_data.clone())?;
+        let mut avio = AvioContext::new(initial_data)?;
 
         unsafe {
             let fmt_ctx = open_format_context(&mut avio)?;
@@ -151,6 +239,9 @@ impl Decoder {
                 scaler,
                 width,
                 height,
+                out_width: width,
+                out_height: height,
+                stats: DecodeStats::default(),
             })
         }
     }
@@ -168,6 +259,143 @@ impl Decoder {
         Err(DecoderError::NoVideoStream)
     }
 
+    /// Probe container and stream metadata without decoding
+    ///
+    /// Reads every packet of the video stream to count frames and
+    /// keyframes, so the cost scales with file size but no frames are
+    /// decoded. Packet contents are never looked at, so `video_data` may
+    /// hold just the container header.
+    ///
+    /// # Errors
+    /// Returns error if the container cannot be opened or has no video stream.
+    pub fn probe(video_data: impl Into<VideoData>) -> Result<VideoInfo, DecoderError> {
+        ffmpeg::init().map_err(|_| DecoderError::FfmpegInit)?;
+
+        let mut avio = AvioContext::new(video_data)?;
+
+        unsafe {
+            let fmt_ctx = open_format_context(&mut avio)?;
+            let info = Self::probe_format(fmt_ctx);
+            ffi::avformat_close_input(&mut (fmt_ctx as *mut _));
+            info
+        }
+    }
+
+    unsafe fn probe_format(fmt_ctx: *mut AVFormatContext) -> Result<VideoInfo, DecoderError> {
+        let (stream_index, codecpar) = Self::find_video_stream(fmt_ctx)?;
+        let stream = *(*fmt_ctx).streams.add(stream_index);
+
+        let codec_id = (*codecpar).codec_id;
+        let level = (*codecpar).level;
+        let format = (*codecpar).format;
+        let pixel_format = pix_fmt_name(format);
+
+        let avg_frame_rate = (*stream).avg_frame_rate;
+        let frame_rate = (avg_frame_rate.num > 0 && avg_frame_rate.den > 0)
+            .then(|| avg_frame_rate.num as f64 / avg_frame_rate.den as f64);
+
+        let time_base = (*stream).time_base;
+        let duration = if (*stream).duration != ffi::AV_NOPTS_VALUE && time_base.den > 0 {
+            Some((*stream).duration as f64 * time_base.num as f64 / time_base.den as f64)
+        } else if (*fmt_ctx).duration != ffi::AV_NOPTS_VALUE {
+            Some((*fmt_ctx).duration as f64 / ffi::AV_TIME_BASE as f64)
+        } else {
+            None
+        };
+
+        // Walk packets to count frames and measure GOP lengths
+        let mut frame_count = 0u64;
+        let mut keyframe_count = 0u64;
+        let mut gop_lengths = Vec::new();
+        let mut current_gop = 0u64;
+
+        Self::for_each_video_packet(fmt_ctx, stream_index, |packet| {
+            frame_count += 1;
+            if packet.is_key() {
+                keyframe_count += 1;
+                if current_gop > 0 {
+                    gop_lengths.push(current_gop);
+                }
+                current_gop = 0;
+            }
+            current_gop += 1;
+        });
+        if current_gop > 0 {
+            gop_lengths.push(current_gop);
+        }
+
+        Ok(VideoInfo {
+            container: cstr_to_string((*(*fmt_ctx).iformat).name).unwrap_or_default(),
+            codec: cstr_to_string(ffi::avcodec_get_name(codec_id)).unwrap_or_default(),
+            profile: cstr_to_string(ffi::avcodec_profile_name(codec_id, (*codecpar).profile)),
+            level: (level > 0).then_some(level),
+            width: (*codecpar).width as u32,
+            height: (*codecpar).height as u32,
+            pixel_format,
+            frame_rate,
+            duration,
+            frame_count,
+            keyframe_count,
+            gop: GopStats::from_lengths(&gop_lengths),
+        })
+    }
+
+    /// Build a frame offsets index by scanning packets
+    ///
+    /// Produces the same entries as `repo-cli convert --extract-offsets`:
+    /// every video packet with a known byte position, paired with the
+    /// offset of the most recent keyframe. Like [`Decoder::probe`], this
+    /// only needs the container header.
+    pub fn index_offsets(
+        video_data: impl Into<VideoData>,
+    ) -> Result<Vec<FrameOffset>, DecoderError> {
+        ffmpeg::init().map_err(|_| DecoderError::FfmpegInit)?;
+
+        let mut avio = AvioContext::new(video_data)?;
+
+        unsafe {
+            let fmt_ctx = open_format_context(&mut avio)?;
+
+            let frames = Self::find_video_stream(fmt_ctx).map(|(stream_index, _)| {
+                let mut frames = Vec::new();
+                let mut irap_offset = 0u64;
+
+                Self::for_each_video_packet(fmt_ctx, stream_index, |packet| {
+                    // Skip if position is unknown (-1)
+                    let Ok(offset) = u64::try_from(packet.position()) else {
+                        return;
+                    };
+                    if packet.is_key() {
+                        irap_offset = offset;
+                    }
+                    frames.push(FrameOffset {
+                        offset,
+                        irap_offset,
+                    });
+                });
+                frames
+            });
+
+            ffi::avformat_close_input(&mut (fmt_ctx as *mut _));
+            frames
+        }
+    }
+
+    /// Read every packet, invoking `f` for those in the video stream
+    unsafe fn for_each_video_packet(
+        fmt_ctx: *mut AVFormatContext,
+        stream_index: usize,
+        mut f: impl FnMut(&ffmpeg::Packet),
+    ) {
+        let mut packet = ffmpeg::Packet::empty();
+        while ffi::av_read_frame(fmt_ctx, packet.as_mut_ptr()) >= 0 {
+            if packet.stream() == stream_index {
+                f(&packet);
+            }
+            ffi::av_packet_unref(packet.as_mut_ptr());
+        }
+    }
+
     /// Decode a single frame at the given byte offset
     ///
     /// Decodes sequentially from the start of the video data until
@@ -175,7 +403,7 @@ impl Decoder {
     /// decoding from the nearest IRAP (keyframe).
     ///
     /// # Arguments
-    /// * `video_data` - Video file data (should start from IRAP for correct decoding)
+    /// * `video_data` - Video file data
     /// * `target_offset` - Byte offset of the target frame in the original file
     ///
     /// # Returns
@@ -183,18 +411,33 @@ impl Decoder {
     ///
     /// # Errors
     /// Returns `FrameNotFound` if no packet matches the target offset.
-    ///
-    /// # Note
-    /// For optimal performance, `video_data` should contain only the GOP
-    /// starting from the relevant IRAP. The `target_offset` should match
-    /// a packet position within that data.
     pub fn decode_frame(
         &mut self,
-        video_data: &Bytes,
+        video_data: impl Into<VideoData>,
         target_offset: u64,
     ) -> Result<DecodedFrame, DecoderError> {
+        self.decode_frame_from(video_data, 0, target_offset)
+    }
+
+    /// Decode the frame at `target_offset`, starting at the IRAP at
+    /// `irap_offset`
+    ///
+    /// Video packets before the IRAP are skipped without being decoded, and
+    /// decoding stops at the first packet `video_data` does not hold, so a
+    /// sparse [`VideoData`] with the container header and the GOP is
+    /// enough.
+    pub fn decode_frame_from(
+        &mut self,
+        video_data: impl Into<VideoData>,
+        irap_offset: u64,
+        target_offset: u64,
+    ) -> Result<DecodedFrame, DecoderError> {
+        let _timer = StageTimer::start(Stage::Decode);
+        let started = Instant::now();
+        self.stats = DecodeStats::default();
         self.decoder.flush();
 
+        let video_data = video_data.into();
         let mut avio = AvioContext::new(video_data.clone())?;
 
         unsafe {
@@ -206,6 +449,7 @@ impl Decoder {
 
             // Track which packet positions have been decoded
             let mut last_decoded_offset: Option<u64> = None;
+            let mut since_keyframe: Option<u32> = None;
 
             while ffi::av_read_frame(fmt_ctx, packet.as_mut_ptr()) >= 0 {
                 if packet.stream() != self.video_stream_index {
@@ -214,17 +458,37 @@ impl Decoder {
                 }
 
                 let packet_offset = packet.position();
+                if packet_offset >= 0 {
+                    let start = packet_offset as u64;
+                    if start < irap_offset {
+                        continue;
+                    }
+                    if !video_data.covers(start..start + packet.size() as u64) {
+                        break;
+                    }
+                }
                 let is_target = packet_offset >= 0 && packet_offset as u64 == target_offset;
+                since_keyframe = if packet.is_key() {
+                    Some(0)
+                } else {
+                    since_keyframe.map(|n| n + 1)
+                };
+                if is_target {
+                    self.stats.gop_distance = since_keyframe;
+                }
 
                 self.decoder
                     .send_packet(&packet)
                     .map_err(|e| DecoderError::SendPacket(e.to_string()))?;
+                self.stats.packets += 1;
 
                 // Receive decoded frames
                 while self.decoder.receive_frame(&mut frame).is_ok() {
                     // If this frame corresponds to our target packet
                     if is_target || last_decoded_offset == Some(target_offset) {
+                        let convert_started = Instant::now();
                         let decoded = self.convert_frame(&frame)?;
+                        self.stats.convert += convert_started.elapsed();
                         target_frame = Some(decoded);
                     }
                     last_decoded_offset = if packet_offset >= 0 {
@@ -237,6 +501,7 @@ impl Decoder {
                 // Early exit if we found our target
                 if target_frame.is_some() {
                     ffi::avformat_close_input(&mut (fmt_ctx as *mut _));
+                    self.stats.decode = started.elapsed().saturating_sub(self.stats.convert);
                     return Ok(target_frame.unwrap());
                 }
 
@@ -252,18 +517,56 @@ impl Decoder {
 
             while self.decoder.receive_frame(&mut frame).is_ok() {
                 if last_decoded_offset == Some(target_offset) {
+                    let convert_started = Instant::now();
                     let decoded = self.convert_frame(&frame)?;
+                    self.stats.convert += convert_started.elapsed();
                     target_frame = Some(decoded);
                     break;
                 }
             }
 
             ffi::avformat_close_input(&mut (fmt_ctx as *mut _));
+            self.stats.decode = started.elapsed().saturating_sub(self.stats.convert);
 
             target_frame.ok_or(DecoderError::FrameNotFound(target_offset))
         }
     }
 
+    /// Packet count and timings of the last `decode_frame` call
+    pub fn last_stats(&self) -> DecodeStats {
+        self.stats
+    }
+
+    /// Scale output frames to `width` pixels wide, preserving aspect ratio
+    ///
+    /// `None` restores the native resolution. Widths larger than the source
+    /// are clamped, so frames are never upscaled.
+    pub fn set_output_width(&mut self, width: Option<u32>) -> Result<(), DecoderError> {
+        let (out_width, out_height) = match width {
+            Some(width) => scaled_dimensions(self.width, self.height, width),
+            None => (self.width, self.height),
+        };
+
+        if (out_width, out_height) == (self.out_width, self.out_height) {
+            return Ok(());
+        }
+
+        self.scaler = ScalerContext::get(
+            self.decoder.format(),
+            self.width,
+            self.height,
+            Pixel::YUV420P,
+            out_width,
+            out_height,
+            Flags::BILINEAR,
+        )
+        .map_err(|_| DecoderError::ScalerInit)?;
+        self.out_width = out_width;
+        self.out_height = out_height;
+
+        Ok(())
+    }
+
     fn convert_frame(
         &mut self,
         frame: &ffmpeg::frame::Video,
@@ -273,20 +576,22 @@ impl Decoder {
             .run(frame, &mut output)
             .map_err(|e| DecoderError::DecodeError(e.to_string()))?;
 
-        let y_size = (self.width * self.height) as usize;
+        let width = self.out_width;
+        let height = self.out_height;
+        let y_size = (width * height) as usize;
         let uv_size = y_size / 4;
         let mut data = Vec::with_capacity(y_size + 2 * uv_size);
 
         // Y plane
-        for row in 0..self.height as usize {
+        for row in 0..height as usize {
             let start = row * output.stride(0);
-            let end = start + self.width as usize;
+            let end = start + width as usize;
             data.extend_from_slice(&output.data(0)[start..end]);
         }
 
         // U plane
-        let uv_height = self.height as usize / 2;
-        let uv_width = self.width as usize / 2;
+        let uv_height = height as usize / 2;
+        let uv_width = width as usize / 2;
         for row in 0..uv_height {
             let start = row * output.stride(1);
             let end = start + uv_width;
@@ -301,15 +606,11 @@ impl Decoder {
         }
 
         Ok(DecodedFrame {
-            width: self.width,
-            height: self.height,
+            width,
+            height,
             pts: frame.pts(),
             data,
-            linesize: [
-                self.width as i32,
-                (self.width / 2) as i32,
-                (self.width / 2) as i32,
-            ],
+            linesize: [width as i32, (width / 2) as i32, (width / 2) as i32],
         })
     }
 
@@ -329,6 +630,37 @@ impl Decoder {
     }
 }
 
+/// Name of a raw pixel format value from codec parameters
+///
+/// The value comes from the container and need not be a variant of the
+/// bindings' `AVPixelFormat`, so it is looked up in FFmpeg's descriptor
+/// table instead of being converted to the enum.
+unsafe fn pix_fmt_name(format: i32) -> Option<String> {
+    let mut desc = ffi::av_pix_fmt_desc_next(std::ptr::null());
+    while !desc.is_null() {
+        if ffi::av_pix_fmt_desc_get_id(desc) as i32 == format {
+            return cstr_to_string((*desc).name);
+        }
+        desc = ffi::av_pix_fmt_desc_next(desc);
+    }
+    None
+}
+
+/// Copy a nullable C string returned by FFmpeg
+unsafe fn cstr_to_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+/// Compute even output dimensions for a target width, preserving aspect ratio
+fn scaled_dimensions(src_width: u32, src_height: u32, target_width: u32) -> (u32, u32) {
+    let width = (target_width.min(src_width) & !1).max(2);
+    let height = (src_height as u64 * width as u64 + src_width as u64 / 2) / src_width as u64;
+    (width, (height as u32 & !1).max(2))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -428,6 +760,26 @@ mod tests {
         assert_eq!(frame.data.len(), expected_size);
     }
 
+    #[test]
+    fn test_gop_distance() {
+        let data = load_test_video();
+        let offsets = Decoder::index_offsets(&data).expect("Indexing failed");
+        let irap = offsets[0].irap_offset;
+        let gop: Vec<_> = offsets
+            .iter()
+            .filter(|frame| frame.irap_offset == irap)
+            .collect();
+        let target = gop[gop.len().min(3) - 1];
+
+        let mut decoder = Decoder::new(&data).expect("Decoder creation failed");
+        decoder
+            .decode_frame_from(&data, irap, target.offset)
+            .expect("Decode failed");
+        let stats = decoder.last_stats();
+        assert_eq!(stats.gop_distance, Some(gop.len().min(3) as u32 - 1));
+        assert!(stats.packets >= gop.len().min(3) as u32);
+    }
+
     #[test]
     fn test_frame_not_found() {
         let data = load_test_video();
@@ -465,6 +817,60 @@ mod tests {
         assert_eq!(frame1.data.len(), frame2.data.len());
     }
 
+    #[test]
+    fn test_probe() {
+        let data = load_test_video();
+        let info = Decoder::probe(&data).expect("Probe failed");
+
+        assert_eq!(info.codec, "hevc");
+        assert!(info.width > 0 && info.height > 0);
+        assert!(info.frame_count > 0);
+        assert!(info.keyframe_count > 0 && info.keyframe_count <= info.frame_count);
+        assert!(info.gop.min <= info.gop.max);
+    }
+
+    #[test]
+    fn test_index_offsets() {
+        let data = load_test_video();
+        let first_offset = get_first_frame_offset();
+
+        let frames = Decoder::index_offsets(&data).expect("Indexing failed");
+
+        assert!(!frames.is_empty());
+        assert_eq!(frames[0].offset, first_offset);
+        assert_eq!(frames[0].irap_offset, first_offset);
+        assert!(frames.iter().all(|f| f.irap_offset <= f.offset));
+    }
+
+    #[test]
+    fn test_gop_stats() {
+        let stats = GopStats::from_lengths(&[30, 30, 12]);
+        assert_eq!(stats.min, 12);
+        assert_eq!(stats.max, 30);
+        assert_eq!(stats.mean, 24.0);
+
+        assert_eq!(GopStats::from_lengths(&[]), GopStats::default());
+    }
+
+    #[test]
+    fn test_pix_fmt_name() {
+        let yuv420p = ffi::AVPixelFormat::AV_PIX_FMT_YUV420P as i32;
+        assert_eq!(unsafe { pix_fmt_name(yuv420p) }.as_deref(), Some("yuv420p"));
+        // Unset, and values FFmpeg does not define
+        assert_eq!(unsafe { pix_fmt_name(-1) }, None);
+        assert_eq!(unsafe { pix_fmt_name(i32::MAX) }, None);
+    }
+
+    #[test]
+    fn test_scaled_dimensions() {
+        assert_eq!(scaled_dimensions(1920, 1080, 640), (640, 360));
+        // Odd targets round down to even
+        assert_eq!(scaled_dimensions(1920, 1080, 641), (640, 360));
+        // Never upscale
+        assert_eq!(scaled_dimensions(1280, 720, 4096), (1280, 720));
+        assert_eq!(scaled_dimensions(1920, 1080, 0), (2, 2));
+    }
+
     #[test]
     fn test_yuv420p_format() {
         let data = load_test_video();
@@ -2,6 +2,7 @@ use anyhow::{Context, Result};
 use turbojpeg::{Compressor, Subsamp, YuvImage};
 
 use super::decoder::DecodedFrame;
+use crate::telemetry::{self, Stage, StageTimer};
 
 /// JPEG encoder using TurboJPEG
 pub struct JpegEncoder {
@@ -39,6 +40,7 @@ impl JpegEncoder {
     /// # Returns
     /// JPEG data as bytes
     pub fn encode(&mut self, frame: &DecodedFrame) -> Result<Vec<u8>> {
+        let _timer = StageTimer::start(Stage::Encode);
         let yuv_image = YuvImage {
             pixels: frame.data.as_slice(),
             width: frame.width as usize,
@@ -47,9 +49,13 @@ impl JpegEncoder {
             subsamp: Subsamp::Sub2x2, // 4:2:0 subsampling
         };
 
-        self.compressor
+        let jpeg = self
+            .compressor
             .compress_yuv_to_vec(yuv_image)
-            .context("JPEG compression failed")
+            .context("JPEG compression failed")?;
+        telemetry::record_frame_size("jpeg", jpeg.len());
+
+        Ok(jpeg)
     }
 
     /// Set encoding quality (1-100)
@@ -1,14 +1,62 @@
 use anyhow::Result;
 use bytes::Bytes;
-use object_store::ObjectStore;
+use object_store::{ObjectMeta, ObjectStore};
+use std::ops::Range;
 use std::sync::Arc;
 
-/// Fetch entire video from storage
-pub async fn fetch_video(store: &Arc<dyn ObjectStore>, path: &str) -> Result<Bytes> {
-    crate::storage::fetch_all(store.as_ref(), path).await
+/// Fetch entire video from storage, given its metadata from [`video_meta`]
+///
+/// Large videos are fetched as concurrent range parts. The read fails if
+/// the video was overwritten since `meta` was read.
+pub async fn fetch_video(store: &Arc<dyn ObjectStore>, meta: &ObjectMeta) -> Result<Bytes> {
+    crate::storage::fetch_sized(store.as_ref(), meta).await
 }
 
-/// Check if video exists in storage
-pub async fn video_exists(store: &Arc<dyn ObjectStore>, path: &str) -> Result<bool> {
-    crate::storage::exists(store.as_ref(), path).await
+/// Fetch a byte range of the video version described by `meta`, e.g. one
+/// GOP
+pub async fn fetch_range(
+    store: &Arc<dyn ObjectStore>,
+    meta: &ObjectMeta,
+    range: Range<u64>,
+) -> Result<Bytes> {
+    crate::storage::fetch_version(store.as_ref(), meta, range).await
+}
+
+/// Fetch several byte ranges of the video version described by `meta` at
+/// once, e.g. the GOPs of a batch
+pub async fn fetch_ranges(
+    store: &Arc<dyn ObjectStore>,
+    meta: &ObjectMeta,
+    ranges: &[Range<u64>],
+) -> Result<Vec<Bytes>> {
+    crate::storage::fetch_ranges(store.as_ref(), meta, ranges).await
+}
+
+/// Get video metadata (size, ETag, last modified), or `None` if missing
+pub async fn video_meta(store: &Arc<dyn ObjectStore>, path: &str) -> Result<Option<ObjectMeta>> {
+    crate::storage::head(store.as_ref(), path).await
+}
+
+/// List up to `count` objects under a directory-style prefix after
+/// `after` that `keep` accepts, sorted by path; see
+/// [`crate::storage::list_page`]
+pub async fn list_page(
+    store: &Arc<dyn ObjectStore>,
+    prefix: Option<&str>,
+    after: Option<&str>,
+    ordered: bool,
+    count: usize,
+    keep: impl FnMut(&ObjectMeta) -> bool,
+) -> Result<Vec<ObjectMeta>> {
+    crate::storage::list_page(store.as_ref(), prefix, after, ordered, count, keep).await
+}
+
+/// Fetch an auxiliary object (e.g. a sidecar), or `None` if missing
+pub async fn fetch_object(store: &Arc<dyn ObjectStore>, path: &str) -> Result<Option<Bytes>> {
+    crate::storage::fetch_optional(store.as_ref(), path).await
+}
+
+/// Write an object back to storage, e.g. to cache a generated index
+pub async fn store_object(store: &Arc<dyn ObjectStore>, path: &str, data: Bytes) -> Result<()> {
+    crate::storage::put(store.as_ref(), path, data).await
 }
@@ -0,0 +1,181 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{field, info_span};
+
+use super::decoder::Decoder;
+use super::encoder::JpegEncoder;
+use super::gop::Gop;
+use crate::server::protocol::{FrameRequest, FrameTimings};
+use crate::telemetry::{self, InFlightFrame, Stage, StageTimer};
+
+/// Image format of encoded frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    #[serde(alias = "jpg")]
+    Jpeg,
+}
+
+impl ImageFormat {
+    /// MIME type for HTTP responses
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    /// File extension used when frames are written to archives
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+/// Output settings applied when encoding a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EncodeOptions {
+    /// Output image format
+    pub format: ImageFormat,
+    /// Encoder quality (1-100)
+    pub quality: u8,
+    /// Output width in pixels, preserving aspect ratio (`None` = native)
+    pub width: Option<u32>,
+}
+
+impl EncodeOptions {
+    /// Native-resolution JPEG at the given quality
+    pub fn jpeg(quality: u8) -> Self {
+        Self {
+            format: ImageFormat::Jpeg,
+            quality,
+            width: None,
+        }
+    }
+}
+
+/// Decode and encode a single frame (runs in blocking context)
+///
+/// Shared by the WebSocket and HTTP frame handlers so both produce
+/// identical output for the same request. Decoding starts at the GOP's
+/// first byte.
+pub fn process_frame(gop: Gop, request: &FrameRequest, options: &EncodeOptions) -> Result<Vec<u8>> {
+    process_frame_timed(gop, request, options).map(|(image, _)| image)
+}
+
+/// [`process_frame`], also reporting decode, convert and encode timings
+///
+/// Queue, fetch and send times are outside the pipeline and left at 0 for
+/// the caller to fill in.
+pub fn process_frame_timed(
+    gop: Gop,
+    request: &FrameRequest,
+    options: &EncodeOptions,
+) -> Result<(Vec<u8>, FrameTimings)> {
+    let _in_flight = InFlightFrame::start();
+    let _timer = StageTimer::start(Stage::Process);
+    let result = decode_and_encode(gop, request, options);
+    telemetry::record_frame(result.is_ok());
+    result
+}
+
+fn decode_and_encode(
+    gop: Gop,
+    request: &FrameRequest,
+    options: &EncodeOptions,
+) -> Result<(Vec<u8>, FrameTimings)> {
+    let decode_span = info_span!(
+        "decode",
+        frame.offset = request.offset,
+        gop.distance = field::Empty,
+    );
+    let (frame, stats) = decode_span.in_scope(|| -> Result<_> {
+        // Create decoder
+        let mut decoder = Decoder::new(&gop.data)?;
+        decoder.set_output_width(options.width)?;
+
+        // Decode frame
+        let frame = decoder.decode_frame_from(gop.data, gop.range.start, request.offset)?;
+        Ok((frame, decoder.last_stats()))
+    })?;
+    if let Some(distance) = stats.gop_distance {
+        decode_span.record("gop.distance", distance);
+    }
+
+    // Encode to requested format
+    let encode_span = info_span!("encode", output.size = field::Empty);
+    let encode_started = Instant::now();
+    let image = encode_span.in_scope(|| -> Result<_> {
+        Ok(match options.format {
+            ImageFormat::Jpeg => JpegEncoder::new(options.quality)?.encode(&frame)?,
+        })
+    })?;
+    encode_span.record("output.size", image.len());
+
+    let timings = FrameTimings {
+        decode_us: stats.decode.as_micros() as u64,
+        packets_decoded: stats.packets,
+        convert_us: stats.convert.as_micros() as u64,
+        encode_us: encode_started.elapsed().as_micros() as u64,
+        ..FrameTimings::default()
+    };
+
+    Ok((image, timings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::span::{Attributes, Id};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    use crate::pipeline::avio::VideoData;
+
+    /// A span's name and its parent's name
+    type Edge = (&'static str, Option<&'static str>);
+
+    /// Records each span's name and its parent's name as it is opened
+    #[derive(Clone, Default)]
+    struct SpanTree(Arc<Mutex<Vec<Edge>>>);
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanTree {
+        fn on_new_span(&self, _: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let span = ctx.span(id).expect("span is open");
+            let parent = span.parent().map(|parent| parent.name());
+            self.0.lock().unwrap().push((span.name(), parent));
+        }
+    }
+
+    #[test]
+    fn test_decode_span_is_child_of_frame() {
+        let tree = SpanTree::default();
+        let subscriber = tracing_subscriber::registry().with(tree.clone());
+        let gop = Gop {
+            data: VideoData::from(bytes::Bytes::from_static(b"not really a video")),
+            range: 0..18,
+        };
+        let request = FrameRequest {
+            offset: 0,
+            irap_offset: 0,
+            index: 0,
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            info_span!("frame").in_scope(|| {
+                assert!(process_frame(gop, &request, &EncodeOptions::jpeg(80)).is_err());
+            });
+        });
+
+        assert_eq!(
+            *tree.0.lock().unwrap(),
+            [("frame", None), ("decode", Some("frame"))]
+        );
+    }
+}
@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::Result;
+use bytes::Bytes;
+use object_store::{ObjectMeta, ObjectStore};
+use tracing::debug;
+
+use super::avio::VideoData;
+use super::decoder::{Decoder, DecoderError};
+use super::fetcher;
+use crate::server::protocol::FrameRequest;
+use crate::storage::cache::Version;
+use crate::storage::error::StorageError;
+
+/// Bytes read from the start of a video to find its top-level boxes
+const PROBE_BYTES: u64 = 64 * 1024;
+
+/// Largest top-level box other than media data loaded as container header
+const MAX_HEADER_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Videos whose layout is kept before the least recently used is dropped
+const MAX_CACHED_LAYOUTS: usize = 64;
+
+/// Top-level boxes whose payload is not needed to demux an MP4
+const SKIPPED_BOXES: [&[u8; 4]; 4] = [b"mdat", b"free", b"skip", b"wide"];
+
+/// How frames of a video can be read
+#[derive(Debug, Clone)]
+pub enum VideoLayout {
+    /// An MP4 whose header is loaded, so each GOP can be fetched on its own
+    Indexed(Arc<GopIndex>),
+    /// Anything else: decoding needs the whole file
+    Whole,
+}
+
+/// The container header of an MP4 and where its GOPs start
+#[derive(Debug)]
+pub struct GopIndex {
+    size: u64,
+    /// Every top-level box but the media data, and the media box headers
+    header: Vec<(u64, Bytes)>,
+    /// Byte offsets of every IRAP, in order
+    iraps: Vec<u64>,
+    /// Payload ranges of the `mdat` boxes
+    media: Vec<Range<u64>>,
+}
+
+impl GopIndex {
+    /// The container header alone, enough to probe or index the video
+    pub fn header(&self) -> VideoData {
+        VideoData::sparse(self.size, self.header.clone())
+    }
+
+    /// Bytes needed to decode the frame at `offset` starting at or before
+    /// `irap_offset`: from the last IRAP at or before both, to the next
+    /// IRAP after `offset` or the end of the media data holding it
+    pub fn gop_range(&self, irap_offset: u64, offset: u64) -> Option<Range<u64>> {
+        let from = irap_offset.min(offset);
+        let first = self.iraps.partition_point(|&irap| irap <= from);
+        let start = self.iraps[..first].last().copied()?;
+        let media_end = self
+            .media
+            .iter()
+            .find(|media| media.contains(&start))
+            .map_or(self.size, |media| media.end);
+        let next = self.iraps.partition_point(|&irap| irap <= offset);
+        let end = self
+            .iraps
+            .get(next)
+            .copied()
+            .unwrap_or(media_end)
+            .min(media_end);
+        (offset < end).then_some(start..end)
+    }
+}
+
+/// The bytes of a video needed to decode one or more frames
+#[derive(Debug, Clone)]
+pub struct Gop {
+    pub data: VideoData,
+    /// Bytes of the file the GOP spans; the whole file when not indexed
+    pub range: Range<u64>,
+}
+
+impl Gop {
+    /// Whether `request` can be decoded from this GOP
+    pub fn holds(&self, request: &FrameRequest) -> bool {
+        self.range.start <= request.irap_offset.min(request.offset)
+            && self.range.contains(&request.offset)
+    }
+}
+
+/// Reads single GOPs of videos instead of whole files
+///
+/// The first read of a video walks its top-level MP4 boxes, loads
+/// everything but the media data, and indexes the IRAPs from that header.
+/// The layout is kept per store and path until a `HEAD` reports another
+/// version of the object, or a read conditional on its ETag fails. Videos that are not MP4, or whose header cannot
+/// be indexed, are read whole as before.
+#[derive(Debug, Default)]
+pub struct GopReader {
+    /// By store address and path
+    layouts: Mutex<HashMap<(usize, String), CachedLayout>>,
+}
+
+#[derive(Debug)]
+struct CachedLayout {
+    version: Version,
+    layout: VideoLayout,
+    used: Instant,
+}
+
+impl GopReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Layout of the video at `path`, given its metadata from
+    /// [`fetcher::video_meta`]
+    pub async fn layout(
+        &self,
+        store: &Arc<dyn ObjectStore>,
+        path: &str,
+        meta: &ObjectMeta,
+    ) -> Result<VideoLayout> {
+        let key = layout_key(store, path);
+        let version = Version::of(meta);
+        if let Some(cached) = self.layouts.lock().unwrap().get_mut(&key) {
+            if cached.version == version {
+                cached.used = Instant::now();
+                return Ok(cached.layout.clone());
+            }
+        }
+
+        let layout = load_layout(store, meta).await?;
+
+        let mut layouts = self.layouts.lock().unwrap();
+        if layouts.len() >= MAX_CACHED_LAYOUTS && !layouts.contains_key(&key) {
+            let oldest = layouts
+                .iter()
+                .min_by_key(|(_, cached)| cached.used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                layouts.remove(&oldest);
+            }
+        }
+        let cached = CachedLayout {
+            version,
+            layout: layout.clone(),
+            used: Instant::now(),
+        };
+        layouts.insert(key, cached);
+        Ok(layout)
+    }
+
+    /// Data to probe or index the video: just the header when indexed
+    pub async fn header(
+        &self,
+        store: &Arc<dyn ObjectStore>,
+        path: &str,
+        meta: &ObjectMeta,
+    ) -> Result<VideoData> {
+        match self.layout(store, path, meta).await? {
+            VideoLayout::Indexed(index) => Ok(index.header()),
+            VideoLayout::Whole => Ok(fetcher::fetch_video(store, meta).await?.into()),
+        }
+    }
+
+    /// Fetch the GOP needed to decode `request`
+    ///
+    /// # Errors
+    /// Returns `DecoderError::FrameNotFound` if no GOP of an indexed video
+    /// holds the requested offset.
+    pub async fn fetch(
+        &self,
+        store: &Arc<dyn ObjectStore>,
+        path: &str,
+        meta: &ObjectMeta,
+        request: &FrameRequest,
+    ) -> Result<Gop> {
+        match self.layout(store, path, meta).await? {
+            VideoLayout::Indexed(index) => {
+                let range = index
+                    .gop_range(request.irap_offset, request.offset)
+                    .ok_or(DecoderError::FrameNotFound(request.offset))?;
+                let bytes = fetcher::fetch_range(store, meta, range.clone())
+                    .await
+                    .inspect_err(|e| self.forget_changed(store, path, e))?;
+                let mut parts = index.header.clone();
+                parts.push((range.start, bytes));
+                Ok(Gop {
+                    data: VideoData::sparse(index.size, parts),
+                    range,
+                })
+            }
+            VideoLayout::Whole => {
+                let data = fetcher::fetch_video(store, meta).await?;
+                Ok(Gop {
+                    data: data.into(),
+                    range: 0..meta.size as u64,
+                })
+            }
+        }
+    }
+
+    /// Drop the layout of `path` if `e` says the object changed since it
+    /// was loaded
+    fn forget_changed(&self, store: &Arc<dyn ObjectStore>, path: &str, e: &anyhow::Error) {
+        let changed = e
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<StorageError>())
+            .any(StorageError::is_precondition);
+        if changed {
+            debug!("{} changed while reading GOPs; dropping its layout", path);
+            self.layouts
+                .lock()
+                .unwrap()
+                .remove(&layout_key(store, path));
+        }
+    }
+
+    /// Fetch the GOPs needed to decode each of `requests` in one read
+    ///
+    /// Each distinct GOP is fetched once, adjacent GOPs are read together,
+    /// and every read is conditional on `meta`'s ETag. A request no GOP
+    /// holds gets `DecoderError::FrameNotFound`; a failed read fails them
+    /// all.
+    pub async fn fetch_many(
+        &self,
+        store: &Arc<dyn ObjectStore>,
+        path: &str,
+        meta: &ObjectMeta,
+        requests: &[FrameRequest],
+    ) -> Result<Vec<Result<Gop>>> {
+        let index = match self.layout(store, path, meta).await? {
+            VideoLayout::Indexed(index) => index,
+            VideoLayout::Whole => {
+                let data = fetcher::fetch_video(store, meta).await?;
+                let gop = Gop {
+                    data: data.into(),
+                    range: 0..meta.size as u64,
+                };
+                return Ok(requests.iter().map(|_| Ok(gop.clone())).collect());
+            }
+        };
+
+        let wanted: Vec<_> = requests
+            .iter()
+            .map(|request| index.gop_range(request.irap_offset, request.offset))
+            .collect();
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        for range in wanted.iter().flatten() {
+            if !ranges.contains(range) {
+                ranges.push(range.clone());
+            }
+        }
+        let fetched = fetcher::fetch_ranges(store, meta, &ranges)
+            .await
+            .inspect_err(|e| self.forget_changed(store, path, e))?;
+
+        Ok(requests
+            .iter()
+            .zip(wanted)
+            .map(|(request, range)| {
+                let range = range.ok_or(DecoderError::FrameNotFound(request.offset))?;
+                let at = ranges
+                    .iter()
+                    .position(|r| *r == range)
+                    .expect("range fetched");
+                let mut parts = index.header.clone();
+                parts.push((range.start, fetched[at].clone()));
+                Ok(Gop {
+                    data: VideoData::sparse(index.size, parts),
+                    range,
+                })
+            })
+            .collect())
+    }
+}
+
+fn layout_key(store: &Arc<dyn ObjectStore>, path: &str) -> (usize, String) {
+    (Arc::as_ptr(store) as *const () as usize, path.to_string())
+}
+
+/// Type, header length and total length of an MP4 box
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BoxHeader {
+    kind: [u8; 4],
+    header_len: u64,
+    len: u64,
+}
+
+/// Parse the box header at `position` of a `size`-byte file, or `None` if
+/// it is not a plausible one
+fn parse_box(head: &[u8], position: u64, size: u64) -> Option<BoxHeader> {
+    let len = u32::from_be_bytes(head.get(0..4)?.try_into().ok()?);
+    let kind: [u8; 4] = head.get(4..8)?.try_into().ok()?;
+    if !kind.iter().all(|b| b.is_ascii_alphanumeric() || *b == b' ') {
+        return None;
+    }
+    let remaining = size - position;
+    let (header_len, len) = match len {
+        0 => (8, remaining),
+        1 => (16, u64::from_be_bytes(head.get(8..16)?.try_into().ok()?)),
+        len => (8, u64::from(len)),
+    };
+    (header_len <= len && len <= remaining).then_some(BoxHeader {
+        kind,
+        header_len,
+        len,
+    })
+}
+
+/// Header parts and media ranges of an MP4, or `None` if the file does not
+/// look like one
+async fn read_boxes(
+    store: &Arc<dyn ObjectStore>,
+    meta: &ObjectMeta,
+) -> Result<Option<(Vec<(u64, Bytes)>, Vec<Range<u64>>)>> {
+    let size = meta.size as u64;
+    let probe = fetcher::fetch_range(store, meta, 0..PROBE_BYTES.min(size)).await?;
+    let read = |range: Range<u64>| {
+        let probe = probe.clone();
+        async move {
+            if range.end <= probe.len() as u64 {
+                Ok(probe.slice(range.start as usize..range.end as usize))
+            } else {
+                fetcher::fetch_range(store, meta, range).await
+            }
+        }
+    };
+
+    let mut header = Vec::new();
+    let mut media = Vec::new();
+    let mut position = 0;
+    while position < size {
+        let head = read(position..(position + 16).min(size)).await?;
+        let Some(found) = parse_box(&head, position, size) else {
+            return Ok(None);
+        };
+        if position == 0 && &found.kind != b"ftyp" {
+            return Ok(None);
+        }
+        let end = position + found.len;
+        if SKIPPED_BOXES.contains(&&found.kind) {
+            header.push((position, head.slice(..found.header_len as usize)));
+            if &found.kind == b"mdat" {
+                media.push(position + found.header_len..end);
+            }
+        } else if found.len > MAX_HEADER_BYTES {
+            return Ok(None);
+        } else {
+            header.push((position, read(position..end).await?));
+        }
+        position = end;
+    }
+    Ok(Some((header, media)))
+}
+
+async fn load_layout(store: &Arc<dyn ObjectStore>, meta: &ObjectMeta) -> Result<VideoLayout> {
+    let (path, size) = (&meta.location, meta.size as u64);
+    let Some((header, media)) = read_boxes(store, meta).await? else {
+        debug!("{} is not an MP4; reading it whole", path);
+        return Ok(VideoLayout::Whole);
+    };
+
+    let data = VideoData::sparse(size, header.clone());
+    let offsets = tokio::task::spawn_blocking(move || Decoder::index_offsets(data))
+        .await
+        .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?;
+    let mut iraps: Vec<u64> = match offsets {
+        Ok(offsets) => offsets.iter().map(|frame| frame.irap_offset).collect(),
+        Err(e) => {
+            debug!("Cannot index {}: {}; reading it whole", path, e);
+            return Ok(VideoLayout::Whole);
+        }
+    };
+    iraps.sort_unstable();
+    iraps.dedup();
+    if iraps.is_empty() {
+        return Ok(VideoLayout::Whole);
+    }
+
+    Ok(VideoLayout::Indexed(Arc::new(GopIndex {
+        size,
+        header,
+        iraps,
+        media,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::{memory::InMemory, path::Path};
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    async fn store_with(data: Vec<u8>) -> Arc<dyn ObjectStore> {
+        let store = InMemory::new();
+        store
+            .put(&Path::from("clip.mp4"), Bytes::from(data).into())
+            .await
+            .unwrap();
+        Arc::new(store)
+    }
+
+    fn index() -> GopIndex {
+        GopIndex {
+            size: 10_000,
+            header: Vec::new(),
+            iraps: vec![100, 1000, 5000, 8500],
+            media: vec![100..8000, 8200..10_000],
+        }
+    }
+
+    #[test]
+    fn test_gop_range() {
+        let index = index();
+
+        assert_eq!(index.gop_range(100, 100), Some(100..1000));
+        assert_eq!(index.gop_range(1000, 4000), Some(1000..5000));
+        // A missing IRAP offset (defaulting to the frame) still finds its GOP
+        assert_eq!(index.gop_range(4000, 4000), Some(1000..5000));
+        // The client's IRAP is honoured even when it is further back
+        assert_eq!(index.gop_range(100, 4000), Some(100..5000));
+        // The last GOP ends with the media data
+        assert_eq!(index.gop_range(5000, 7000), Some(5000..8000));
+        assert_eq!(index.gop_range(50, 50), None);
+        assert_eq!(index.gop_range(5000, 9000), None);
+        assert_eq!(index.gop_range(8500, 9000), Some(8500..10_000));
+    }
+
+    #[test]
+    fn test_gop_holds() {
+        let gop = Gop {
+            data: VideoData::default(),
+            range: 1000..5000,
+        };
+        let request = |irap_offset, offset| FrameRequest {
+            offset,
+            irap_offset,
+            index: 0,
+        };
+
+        assert!(gop.holds(&request(1000, 1000)));
+        assert!(gop.holds(&request(1000, 4999)));
+        assert!(!gop.holds(&request(100, 2000)));
+        assert!(!gop.holds(&request(5000, 5000)));
+    }
+
+    #[test]
+    fn test_parse_box() {
+        let ftyp = mp4_box(b"ftyp", b"isom");
+        assert_eq!(
+            parse_box(&ftyp, 0, 100),
+            Some(BoxHeader {
+                kind: *b"ftyp",
+                header_len: 8,
+                len: 12,
+            })
+        );
+
+        // Size 0 runs to the end of the file; size 1 has a 64-bit length
+        let open = [0, 0, 0, 0, b'm', b'd', b'a', b't'];
+        assert_eq!(parse_box(&open, 40, 100).unwrap().len, 60);
+        let mut large = vec![0, 0, 0, 1, b'm', b'd', b'a', b't'];
+        large.extend_from_slice(&50u64.to_be_bytes());
+        let large = parse_box(&large, 0, 100).unwrap();
+        assert_eq!((large.header_len, large.len), (16, 50));
+
+        // Past the end of the file, or not a box at all
+        assert_eq!(parse_box(&ftyp, 95, 100), None);
+        assert_eq!(parse_box(b"not really a video", 0, 100), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_boxes() {
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend(mp4_box(b"mdat", &[7; 100]));
+        data.extend(mp4_box(b"moov", b"header"));
+        let size = data.len() as u64;
+        let store = store_with(data).await;
+
+        let meta = store.head(&Path::from("clip.mp4")).await.unwrap();
+        assert_eq!(meta.size as u64, size);
+        let (header, media) = read_boxes(&store, &meta).await.unwrap().unwrap();
+
+        let positions: Vec<_> = header.iter().map(|(at, part)| (*at, part.len())).collect();
+        assert_eq!(positions, vec![(0, 12), (12, 8), (120, 14)]);
+        assert_eq!(&header[2].1[8..], b"header");
+        assert_eq!(media, vec![20..120]);
+    }
+
+    #[tokio::test]
+    async fn test_not_mp4_read_whole() {
+        let store = store_with(b"not really a video".to_vec()).await;
+        let meta = store.head(&Path::from("clip.mp4")).await.unwrap();
+        assert!(read_boxes(&store, &meta).await.unwrap().is_none());
+
+        // A first box other than `ftyp`
+        let store = store_with(mp4_box(b"moov", b"header")).await;
+        let meta = store.head(&Path::from("clip.mp4")).await.unwrap();
+        assert!(read_boxes(&store, &meta).await.unwrap().is_none());
+
+        let request = FrameRequest {
+            offset: 8,
+            irap_offset: 8,
+            index: 0,
+        };
+        let reader = GopReader::new();
+        let gop = reader
+            .fetch(&store, "clip.mp4", &meta, &request)
+            .await
+            .unwrap();
+        assert_eq!(gop.range, 0..14);
+        assert_eq!(gop.data.len(), 14);
+
+        // Every frame of a batch shares the whole file
+        let requests = [request.clone(), request];
+        let gops = reader
+            .fetch_many(&store, "clip.mp4", &meta, &requests)
+            .await
+            .unwrap();
+        assert_eq!(gops.len(), 2);
+        assert!(gops
+            .iter()
+            .all(|gop| gop.as_ref().unwrap().range == (0..14)));
+    }
+
+    #[tokio::test]
+    async fn test_changed_video_drops_layout() {
+        let data: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        let store = store_with(data.clone()).await;
+        let meta = store.head(&Path::from("clip.mp4")).await.unwrap();
+
+        let reader = GopReader::new();
+        reader.layouts.lock().unwrap().insert(
+            layout_key(&store, "clip.mp4"),
+            CachedLayout {
+                version: Version::of(&meta),
+                layout: VideoLayout::Indexed(Arc::new(index())),
+                used: Instant::now(),
+            },
+        );
+        let request = |offset| FrameRequest {
+            offset,
+            irap_offset: offset,
+            index: 0,
+        };
+
+        let gops = reader
+            .fetch_many(&store, "clip.mp4", &meta, &[request(100), request(1000)])
+            .await
+            .unwrap();
+        let gop = gops[1].as_ref().unwrap();
+        assert_eq!(gop.range, 1000..5000);
+        assert!(gop.data.covers(1000..5000));
+
+        // Adjacent and overlapping ranges are read together and split apart
+        let ranges = [100..1000, 1000..5000, 200..300];
+        let fetched = fetcher::fetch_ranges(&store, &meta, &ranges).await.unwrap();
+        for (range, bytes) in ranges.iter().zip(fetched) {
+            assert_eq!(bytes, data[range.start as usize..range.end as usize]);
+        }
+
+        store
+            .put(&Path::from("clip.mp4"), Bytes::from(vec![0; 10_000]).into())
+            .await
+            .unwrap();
+        assert!(reader
+            .fetch_many(&store, "clip.mp4", &meta, &[request(100)])
+            .await
+            .is_err());
+        assert!(reader.layouts.lock().unwrap().is_empty());
+    }
+}
@@ -2,4 +2,7 @@ pub mod avio;
 pub mod decoder;
 pub mod encoder;
 pub mod fetcher;
+pub mod frame;
+pub mod gop;
+pub mod prefetch;
 pub mod session;
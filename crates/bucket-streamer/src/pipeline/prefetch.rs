@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use object_store::{path::Path, ObjectStore};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::debug;
+
+use crate::config::Config;
+use crate::telemetry;
+
+/// Clients tracked before the least recently seen is dropped
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// How far to read ahead of a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadAhead {
+    /// GOPs fetched past the one being requested (0 = off)
+    pub gops: u32,
+    /// Cap on the bytes ahead of the requested GOP
+    pub max_bytes: u64,
+}
+
+impl ReadAhead {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            gops: config.prefetch_gops,
+            max_bytes: config.prefetch_max_bytes,
+        }
+    }
+}
+
+/// Read-ahead settings shared by every [`Prefetcher`]
+///
+/// The settings follow config reloads; the number of read-ahead fetches
+/// running at once is capped across all clients, so many clients cannot
+/// each hold `prefetch_max_bytes` in flight.
+#[derive(Debug)]
+pub struct ReadAheadPolicy {
+    read_ahead: RwLock<ReadAhead>,
+    permits: Arc<Semaphore>,
+}
+
+impl ReadAheadPolicy {
+    pub fn new(read_ahead: ReadAhead, max_tasks: usize) -> Self {
+        Self {
+            read_ahead: RwLock::new(read_ahead),
+            permits: Arc::new(Semaphore::new(max_tasks)),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(ReadAhead::from_config(config), config.prefetch_concurrency)
+    }
+
+    /// Apply reloaded read-ahead settings
+    pub fn update(&self, config: &Config) {
+        *self.read_ahead.write().unwrap() = ReadAhead::from_config(config);
+    }
+
+    pub fn read_ahead(&self) -> ReadAhead {
+        *self.read_ahead.read().unwrap()
+    }
+}
+
+/// Reads ahead of one client moving forward through one video
+///
+/// Each request names the IRAP its frame decodes from. Once two requests
+/// step forward to a new GOP, the distance between them is taken as the
+/// GOP length and the next `gops` GOPs are fetched in the background, so
+/// they are in the store's fetch cache when asked for. A step backwards,
+/// or forwards past what was read ahead, is a seek: pending reads are
+/// cancelled and the history starts over. When every read-ahead slot of
+/// the [`ReadAheadPolicy`] is taken, the step is not read ahead.
+#[derive(Debug)]
+pub struct Prefetcher {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    size: u64,
+    policy: Arc<ReadAheadPolicy>,
+    /// Start of the GOP last requested
+    last_gop: Option<u64>,
+    /// End of the bytes read ahead so far
+    ahead_until: Option<u64>,
+    tasks: JoinSet<()>,
+}
+
+impl Prefetcher {
+    pub fn new(
+        store: Arc<dyn ObjectStore>,
+        path: &str,
+        size: u64,
+        policy: Arc<ReadAheadPolicy>,
+    ) -> Self {
+        Self {
+            store,
+            path: Path::from(path),
+            size,
+            policy,
+            last_gop: None,
+            ahead_until: None,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Note a request for a frame of the GOP starting at `irap_offset`
+    pub fn observe(&mut self, irap_offset: u64) {
+        while self.tasks.try_join_next().is_some() {}
+
+        let Some(last) = self.last_gop.replace(irap_offset) else {
+            return;
+        };
+        if irap_offset == last {
+            return;
+        }
+        let sequential =
+            irap_offset > last && self.ahead_until.is_none_or(|end| irap_offset <= end);
+        if !sequential {
+            self.cancel();
+            return;
+        }
+
+        let read_ahead = self.policy.read_ahead();
+        if read_ahead.gops == 0 {
+            return;
+        }
+        let gop = irap_offset - last;
+        let start = (irap_offset + gop).max(self.ahead_until.unwrap_or(0));
+        let end = gop
+            .saturating_mul(u64::from(read_ahead.gops) + 1)
+            .min(read_ahead.max_bytes)
+            .saturating_add(irap_offset)
+            .min(self.size);
+        if start >= end {
+            return;
+        }
+        let Ok(permit) = self.policy.permits.clone().try_acquire_owned() else {
+            debug!("Read-ahead slots taken; not reading ahead of {}", self.path);
+            return;
+        };
+        self.ahead_until = Some(end);
+
+        debug!("Reading ahead {}..{} of {}", start, end, self.path);
+        let store = self.store.clone();
+        let path = self.path.clone();
+        self.tasks.spawn(async move {
+            let _permit = permit;
+            let mut offset = start;
+            while offset < end {
+                let part = offset..(offset + gop).min(end);
+                match store
+                    .get_range(&path, part.start as usize..part.end as usize)
+                    .await
+                {
+                    Ok(bytes) => telemetry::record_prefetch(bytes.len()),
+                    Err(e) => {
+                        debug!("Read-ahead of {} stopped: {}", path, e);
+                        return;
+                    }
+                }
+                offset = part.end;
+            }
+        });
+    }
+
+    /// Abandon pending reads, e.g. after a seek
+    fn cancel(&mut self) {
+        if !self.tasks.is_empty() {
+            telemetry::record_prefetch_cancelled();
+        }
+        // Dropping the set aborts its reads
+        self.tasks = JoinSet::new();
+        self.ahead_until = None;
+    }
+}
+
+/// One [`Prefetcher`] per client, for stateless frame requests
+///
+/// A client switching to another video gets a fresh prefetcher, cancelling
+/// the old one's reads. WebSocket sessions hold their own prefetcher from
+/// [`Prefetchers::session`].
+#[derive(Debug)]
+pub struct Prefetchers {
+    policy: Arc<ReadAheadPolicy>,
+    clients: Mutex<HashMap<String, (Prefetcher, Instant)>>,
+}
+
+impl Prefetchers {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            policy: Arc::new(ReadAheadPolicy::from_config(config)),
+            clients: Mutex::default(),
+        }
+    }
+
+    /// Apply reloaded read-ahead settings to every prefetcher
+    pub fn update(&self, config: &Config) {
+        self.policy.update(config);
+        if config.prefetch_gops == 0 {
+            self.clients.lock().unwrap().clear();
+        }
+    }
+
+    /// A prefetcher for one session's reads of `path`
+    pub fn session(&self, store: Arc<dyn ObjectStore>, path: &str, size: u64) -> Prefetcher {
+        Prefetcher::new(store, path, size, self.policy.clone())
+    }
+
+    /// Note that `client` requested a frame of `path` decoding from
+    /// `irap_offset`
+    pub fn observe(
+        &self,
+        client: &str,
+        store: &Arc<dyn ObjectStore>,
+        path: &str,
+        size: u64,
+        irap_offset: u64,
+    ) {
+        if self.policy.read_ahead().gops == 0 {
+            return;
+        }
+        let mut clients = self.clients.lock().unwrap();
+        let same_video = clients.get(client).is_some_and(|(prefetcher, _)| {
+            prefetcher.path.as_ref() == path && prefetcher.size == size
+        });
+        if !same_video {
+            if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(client) {
+                let oldest = clients
+                    .iter()
+                    .min_by_key(|(_, (_, seen))| *seen)
+                    .map(|(client, _)| client.clone());
+                if let Some(oldest) = oldest {
+                    clients.remove(&oldest);
+                }
+            }
+            let prefetcher = self.session(store.clone(), path, size);
+            clients.insert(client.to_string(), (prefetcher, Instant::now()));
+        }
+
+        let (prefetcher, seen) = clients.get_mut(client).expect("inserted above");
+        *seen = Instant::now();
+        prefetcher.observe(irap_offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use object_store::memory::InMemory;
+
+    use crate::storage::cache::{BlockCache, CachedStore};
+
+    const GOP: u64 = 1000;
+
+    /// A cached store over a 20-GOP video, and the backend behind the cache
+    async fn setup() -> (Arc<dyn ObjectStore>, Arc<InMemory>) {
+        let backend = Arc::new(InMemory::new());
+        let data = Bytes::from(vec![1u8; 20 * GOP as usize]);
+        backend
+            .put(&Path::from("clip.h265"), data.into())
+            .await
+            .unwrap();
+        let cache = BlockCache::new(1 << 20, 100);
+        let store: Arc<dyn ObjectStore> = Arc::new(CachedStore::new(backend.clone(), cache));
+        (store, backend)
+    }
+
+    fn read_ahead(gops: u32) -> Arc<ReadAheadPolicy> {
+        let read_ahead = ReadAhead {
+            gops,
+            max_bytes: 1 << 20,
+        };
+        Arc::new(ReadAheadPolicy::new(read_ahead, 16))
+    }
+
+    async fn wait_for(prefetcher: &mut Prefetcher) {
+        while let Some(result) = prefetcher.tasks.join_next().await {
+            result.unwrap();
+        }
+    }
+
+    /// Whether `range` can be read once the backend is gone
+    async fn cached(store: &Arc<dyn ObjectStore>, range: std::ops::Range<usize>) -> bool {
+        store
+            .get_range(&Path::from("clip.h265"), range)
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_reads_ahead_when_moving_forward() {
+        let (store, backend) = setup().await;
+        let mut prefetcher = Prefetcher::new(store.clone(), "clip.h265", 20 * GOP, read_ahead(2));
+
+        prefetcher.observe(0);
+        prefetcher.observe(0);
+        assert!(prefetcher.tasks.is_empty());
+        prefetcher.observe(GOP);
+        assert_eq!(prefetcher.ahead_until, Some(4 * GOP));
+        wait_for(&mut prefetcher).await;
+
+        backend.delete(&Path::from("clip.h265")).await.unwrap();
+        assert!(cached(&store, 2000..4000).await);
+        assert!(!cached(&store, 4000..4100).await);
+    }
+
+    #[tokio::test]
+    async fn test_read_ahead_capped() {
+        let (store, _) = setup().await;
+        let read_ahead = ReadAhead {
+            gops: 4,
+            max_bytes: 2500,
+        };
+        let policy = Arc::new(ReadAheadPolicy::new(read_ahead, 16));
+        let mut prefetcher = Prefetcher::new(store.clone(), "clip.h265", 20 * GOP, policy.clone());
+
+        prefetcher.observe(0);
+        prefetcher.observe(GOP);
+        assert_eq!(prefetcher.ahead_until, Some(3500));
+
+        // Never past the end of the video
+        let mut prefetcher = Prefetcher::new(store, "clip.h265", 20 * GOP, policy);
+        prefetcher.observe(18 * GOP);
+        prefetcher.observe(19 * GOP);
+        assert!(prefetcher.tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_seek_cancels_read_ahead() {
+        let (store, _) = setup().await;
+        let mut prefetcher = Prefetcher::new(store, "clip.h265", 20 * GOP, read_ahead(2));
+
+        prefetcher.observe(5 * GOP);
+        prefetcher.observe(6 * GOP);
+        assert_eq!(prefetcher.ahead_until, Some(9 * GOP));
+
+        // Backwards
+        prefetcher.observe(2 * GOP);
+        assert!(prefetcher.tasks.is_empty());
+        assert_eq!(prefetcher.ahead_until, None);
+
+        // Forwards again, then past the read-ahead window
+        prefetcher.observe(3 * GOP);
+        assert_eq!(prefetcher.ahead_until, Some(6 * GOP));
+        prefetcher.observe(12 * GOP);
+        assert_eq!(prefetcher.ahead_until, None);
+        assert_eq!(prefetcher.last_gop, Some(12 * GOP));
+    }
+
+    #[tokio::test]
+    async fn test_prefetcher_per_client() {
+        let (store, _) = setup().await;
+        let prefetchers = Prefetchers {
+            policy: read_ahead(1),
+            clients: Mutex::default(),
+        };
+
+        prefetchers.observe("a", &store, "clip.h265", 20 * GOP, 0);
+        prefetchers.observe("b", &store, "clip.h265", 20 * GOP, 5 * GOP);
+        prefetchers.observe("a", &store, "clip.h265", 20 * GOP, GOP);
+        {
+            let clients = prefetchers.clients.lock().unwrap();
+            assert_eq!(clients["a"].0.ahead_until, Some(3 * GOP));
+            assert_eq!(clients["b"].0.ahead_until, None);
+        }
+
+        // Another video starts over
+        prefetchers.observe("a", &store, "other.h265", 20 * GOP, 2 * GOP);
+        let clients = prefetchers.clients.lock().unwrap();
+        assert_eq!(clients["a"].0.last_gop, Some(2 * GOP));
+        assert_eq!(clients["a"].0.ahead_until, None);
+    }
+
+    #[tokio::test]
+    async fn test_read_ahead_slots_shared() {
+        let (store, _) = setup().await;
+        let read_ahead = ReadAhead {
+            gops: 2,
+            max_bytes: 1 << 20,
+        };
+        let policy = Arc::new(ReadAheadPolicy::new(read_ahead, 1));
+        let mut first = Prefetcher::new(store.clone(), "clip.h265", 20 * GOP, policy.clone());
+        let mut second = Prefetcher::new(store, "clip.h265", 20 * GOP, policy.clone());
+
+        first.observe(0);
+        first.observe(GOP);
+        assert_eq!(first.tasks.len(), 1);
+
+        // No slot left: the step is seen but not read ahead
+        second.observe(5 * GOP);
+        second.observe(6 * GOP);
+        assert!(second.tasks.is_empty());
+        assert_eq!(second.ahead_until, None);
+
+        // Once the first read finishes, its slot is free again
+        wait_for(&mut first).await;
+        second.observe(7 * GOP);
+        assert_eq!(second.ahead_until, Some(10 * GOP));
+    }
+
+    #[tokio::test]
+    async fn test_update_read_ahead() {
+        let (store, _) = setup().await;
+        let policy = read_ahead(2);
+        let mut prefetcher = Prefetcher::new(store, "clip.h265", 20 * GOP, policy.clone());
+
+        let config = Config {
+            prefetch_gops: 1,
+            ..Config::default()
+        };
+        policy.update(&config);
+        prefetcher.observe(0);
+        prefetcher.observe(GOP);
+        assert_eq!(prefetcher.ahead_until, Some(3 * GOP));
+    }
+}
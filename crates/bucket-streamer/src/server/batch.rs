@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{debug, field, info_span, warn, Instrument, Span};
+
+use super::auth::Principal;
+use super::error::{client_message, ApiError};
+use super::limits::client_key;
+use super::protocol::FrameRequest;
+use super::router::AppState;
+use crate::pipeline::fetcher;
+use crate::pipeline::frame::{process_frame, EncodeOptions, ImageFormat};
+
+/// Tar block size in bytes
+const TAR_BLOCK: usize = 512;
+
+/// Longest name a ustar header holds; longer ones get a GNU long-name entry
+const TAR_NAME_LEN: usize = 100;
+
+/// Frames whose GOPs are fetched together in one multi-range read
+const FETCH_WINDOW: usize = 16;
+
+/// Body of `POST /batch`
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchRequest {
+    /// Video path in the default store, or a storage URL such as
+    /// `s3://bucket/clip.h265`
+    pub path: String,
+    /// Frames to extract, encoded in order
+    pub frames: Vec<FrameRequest>,
+    /// Output image format
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// Output width in pixels, preserving aspect ratio
+    pub width: Option<u32>,
+    /// Response container
+    #[serde(default)]
+    pub archive: ArchiveFormat,
+}
+
+/// Container used to bundle frames into a single response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    /// `multipart/mixed`, one part per frame
+    #[default]
+    Multipart,
+    /// Uncompressed POSIX tar stream
+    Tar,
+}
+
+/// Per-frame outcome, written as `manifest.json` after all frames
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub index: u32,
+    pub offset: u64,
+    /// Archive entry name (absent on error); a frame requested more than
+    /// once gets a numbered name for each repeat, e.g. `frame_000001_1.jpg`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Encoded size in bytes (absent on error)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u32>,
+    /// Error description (absent on success)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Extract a batch of frames into one streamed HTTP response
+///
+/// Frames are written as they are encoded, followed by a `manifest.json`
+/// entry listing every requested frame and any per-frame error. Intended
+/// for offline jobs that cannot hold a WebSocket open. The GOPs of each
+/// run of [`FETCH_WINDOW`] frames are fetched in one multi-range read. When
+/// the client's frame or byte rate is exceeded the stream is throttled
+/// rather than cut.
+pub async fn post_batch(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(mut batch): Json<BatchRequest>,
+) -> Result<Response, ApiError> {
+    let video = state.stores.resolve(&batch.path)?;
+    principal.authorize(&video.scope_path)?;
+    batch.path = video.scope_path;
+    if batch.frames.is_empty() {
+        return Err(ApiError::BadRequest("frames must not be empty".into()));
+    }
+    if batch.width == Some(0) {
+        return Err(ApiError::BadRequest("width must be greater than 0".into()));
+    }
+    state.limiter.check_request(batch.frames.len())?;
+    let client = client_key(&principal, connect_info.map(|ConnectInfo(addr)| addr));
+
+    let meta = fetcher::video_meta(&video.store, &video.key)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Video not found: {}", batch.path)))?;
+    let (store, key) = (video.store, video.key);
+
+    let options = EncodeOptions {
+        format: batch.format,
+        quality: state.config.current().jpeg_quality,
+        width: batch.width,
+    };
+    let writer = ArchiveWriter::new(batch.archive);
+    let content_type = writer.content_type();
+
+    debug!(
+        "Batch of {} frames from {} as {:?}",
+        batch.frames.len(),
+        batch.path,
+        batch.archive
+    );
+
+    let (tx, rx) = mpsc::channel::<Bytes>(4);
+    let producer = async move {
+        let mut manifest = Vec::with_capacity(batch.frames.len());
+        let mut names = EntryNames::default();
+
+        for window in batch.frames.chunks(FETCH_WINDOW) {
+            let gops = match state.gops.fetch_many(&store, &key, &meta, window).await {
+                Ok(gops) => gops,
+                Err(e) => {
+                    // Fetch each GOP alone so every frame reports its own error
+                    debug!("Batch fetch of {} frames failed: {:#}", window.len(), e);
+                    let mut gops = Vec::with_capacity(window.len());
+                    for request in window {
+                        gops.push(state.gops.fetch(&store, &key, &meta, request).await);
+                    }
+                    gops
+                }
+            };
+
+            for (request, gop) in window.iter().zip(gops) {
+                while let Err(limited) = state.limiter.check_frame(&client) {
+                    debug!("Batch throttled: {}", limited);
+                    tokio::time::sleep(limited.retry_after).await;
+                }
+
+                let request_clone = request.clone();
+                let frame_span = info_span!(
+                    "frame",
+                    video.path = %batch.path,
+                    frame.index = request.index,
+                    frame.offset = request.offset,
+                    frame.irap_offset = request.irap_offset,
+                    output.size = field::Empty,
+                );
+
+                let result = match gop {
+                    Ok(gop) => {
+                        // Process frame in blocking task (FFmpeg is not Send)
+                        let blocking_span = frame_span.clone();
+                        tokio::task::spawn_blocking(move || {
+                            blocking_span.in_scope(|| process_frame(gop, &request_clone, &options))
+                        })
+                        .await
+                    }
+                    Err(e) => Ok(Err(e)),
+                };
+
+                let entry = match result {
+                    Ok(Ok(image)) => {
+                        state.limiter.record_bytes(&client, image.len());
+                        frame_span.record("output.size", image.len());
+                        let name = names.next(request.index, options.format.extension());
+                        let chunk = writer.entry(&name, options.format.content_type(), &image);
+                        if tx.send(chunk).await.is_err() {
+                            debug!("Batch client went away");
+                            return;
+                        }
+                        ManifestEntry {
+                            index: request.index,
+                            offset: request.offset,
+                            name: Some(name),
+                            size: Some(image.len() as u32),
+                            error: None,
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        warn!("Batch frame {} failed: {:#}", request.index, e);
+                        ManifestEntry {
+                            index: request.index,
+                            offset: request.offset,
+                            name: None,
+                            size: None,
+                            error: Some(client_message(&e)),
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Batch frame {} failed: {}", request.index, e);
+                        ManifestEntry {
+                            index: request.index,
+                            offset: request.offset,
+                            name: None,
+                            size: None,
+                            error: Some(format!("Task join error: {}", e)),
+                        }
+                    }
+                };
+                manifest.push(entry);
+            }
+        }
+
+        let manifest = serde_json::to_vec(&serde_json::json!({ "frames": manifest }))
+            .expect("Manifest serialization should not fail");
+        let _ = tx
+            .send(writer.entry("manifest.json", "application/json", &manifest))
+            .await;
+        let _ = tx.send(writer.finish()).await;
+    };
+    tokio::spawn(producer.instrument(Span::current()));
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|chunk| (Ok::<_, std::io::Error>(chunk), rx))
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, content_type)],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Entry names of a batch's frames, unique even when a frame is requested
+/// more than once
+#[derive(Debug, Default)]
+struct EntryNames {
+    /// Times each frame index has been named
+    seen: HashMap<u32, u32>,
+}
+
+impl EntryNames {
+    fn next(&mut self, index: u32, extension: &str) -> String {
+        let repeats = self.seen.entry(index).or_default();
+        let name = match *repeats {
+            0 => format!("frame_{:06}.{}", index, extension),
+            n => format!("frame_{:06}_{}.{}", index, n, extension),
+        };
+        *repeats += 1;
+        name
+    }
+}
+
+/// Serializes archive entries for the chosen container
+#[derive(Debug, Clone)]
+struct ArchiveWriter {
+    format: ArchiveFormat,
+    boundary: String,
+}
+
+impl ArchiveWriter {
+    fn new(format: ArchiveFormat) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Self {
+            format,
+            boundary: format!("bucket-streamer-{:x}", nanos),
+        }
+    }
+
+    fn content_type(&self) -> String {
+        match self.format {
+            ArchiveFormat::Multipart => format!("multipart/mixed; boundary={}", self.boundary),
+            ArchiveFormat::Tar => "application/x-tar".to_string(),
+        }
+    }
+
+    /// Encode one file entry, including any framing around `data`
+    fn entry(&self, name: &str, content_type: &str, data: &[u8]) -> Bytes {
+        match self.format {
+            ArchiveFormat::Multipart => {
+                let mut buf = BytesMut::with_capacity(data.len() + 256);
+                buf.put_slice(
+                    format!(
+                        "--{}\r\nContent-Type: {}\r\nContent-Disposition: attachment; filename=\"{}\"\r\nContent-Length: {}\r\n\r\n",
+                        self.boundary,
+                        content_type,
+                        name,
+                        data.len()
+                    )
+                    .as_bytes(),
+                );
+                buf.put_slice(data);
+                buf.put_slice(b"\r\n");
+                buf.freeze()
+            }
+            ArchiveFormat::Tar => {
+                let mut buf = BytesMut::with_capacity(3 * TAR_BLOCK + data.len());
+                if name.len() > TAR_NAME_LEN {
+                    // GNU tar reads the next entry's name from this one's data
+                    let mut long_name = name.as_bytes().to_vec();
+                    long_name.push(0);
+                    put_tar_file(&mut buf, "././@LongLink", b'L', &long_name);
+                }
+                put_tar_file(&mut buf, name, b'0', data);
+                buf.freeze()
+            }
+        }
+    }
+
+    /// Trailer closing the archive
+    fn finish(&self) -> Bytes {
+        match self.format {
+            ArchiveFormat::Multipart => Bytes::from(format!("--{}--\r\n", self.boundary)),
+            ArchiveFormat::Tar => Bytes::from(vec![0u8; 2 * TAR_BLOCK]),
+        }
+    }
+}
+
+/// Append a tar header of `kind` and `data` padded to whole blocks
+fn put_tar_file(buf: &mut BytesMut, name: &str, kind: u8, data: &[u8]) {
+    let padding = (TAR_BLOCK - data.len() % TAR_BLOCK) % TAR_BLOCK;
+    buf.put_slice(&tar_header(name, kind, data.len() as u64));
+    buf.put_slice(data);
+    buf.put_bytes(0, padding);
+}
+
+/// Build a ustar header block of type `kind`, holding at most the first
+/// [`TAR_NAME_LEN`] bytes of `name`
+fn tar_header(name: &str, kind: u8, size: u64) -> [u8; TAR_BLOCK] {
+    let mut header = [0u8; TAR_BLOCK];
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let name = name.as_bytes();
+    let len = name.len().min(TAR_NAME_LEN);
+    header[..len].copy_from_slice(&name[..len]);
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    header[136..148].copy_from_slice(format!("{:011o}\0", mtime).as_bytes());
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // Checksum is computed with the checksum field filled with spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tar_entry_layout() {
+        let writer = ArchiveWriter::new(ArchiveFormat::Tar);
+        let entry = writer.entry("frame_000001.jpg", "image/jpeg", b"abc");
+
+        assert_eq!(entry.len(), 2 * TAR_BLOCK);
+        assert_eq!(&entry[..16], b"frame_000001.jpg");
+        assert_eq!(&entry[124..136], b"00000000003\0");
+        assert_eq!(&entry[TAR_BLOCK..TAR_BLOCK + 3], b"abc");
+
+        let stored = std::str::from_utf8(&entry[148..154]).unwrap();
+        let mut check = entry[..TAR_BLOCK].to_vec();
+        check[148..156].copy_from_slice(b"        ");
+        let expected: u32 = check.iter().map(|&b| b as u32).sum();
+        assert_eq!(u32::from_str_radix(stored, 8).unwrap(), expected);
+
+        assert_eq!(writer.finish().len(), 2 * TAR_BLOCK);
+    }
+
+    #[test]
+    fn test_tar_long_name() {
+        let writer = ArchiveWriter::new(ArchiveFormat::Tar);
+        let name = format!("{}/frame_000001.jpg", "d".repeat(120));
+        let entry = writer.entry(&name, "image/jpeg", b"abc");
+
+        // Long-name entry, its data block, then the frame's header and data
+        assert_eq!(entry.len(), 4 * TAR_BLOCK);
+        assert_eq!(&entry[..13], b"././@LongLink");
+        assert_eq!(entry[156], b'L');
+        assert_eq!(
+            &entry[124..136],
+            format!("{:011o}\0", name.len() + 1).as_bytes()
+        );
+        assert_eq!(&entry[TAR_BLOCK..TAR_BLOCK + name.len()], name.as_bytes());
+        assert_eq!(entry[TAR_BLOCK + name.len()], 0);
+
+        let header = &entry[2 * TAR_BLOCK..3 * TAR_BLOCK];
+        assert_eq!(header[156], b'0');
+        assert_eq!(&header[..TAR_NAME_LEN], &name.as_bytes()[..TAR_NAME_LEN]);
+        assert_eq!(&entry[3 * TAR_BLOCK..3 * TAR_BLOCK + 3], b"abc");
+    }
+
+    #[test]
+    fn test_entry_names_unique() {
+        let mut names = EntryNames::default();
+        assert_eq!(names.next(1, "jpg"), "frame_000001.jpg");
+        assert_eq!(names.next(2, "jpg"), "frame_000002.jpg");
+        assert_eq!(names.next(1, "jpg"), "frame_000001_1.jpg");
+        assert_eq!(names.next(1, "jpg"), "frame_000001_2.jpg");
+    }
+
+    #[test]
+    fn test_multipart_entry() {
+        let writer = ArchiveWriter::new(ArchiveFormat::Multipart);
+        let entry = writer.entry("manifest.json", "application/json", b"{}");
+        let text = std::str::from_utf8(&entry).unwrap();
+
+        assert!(text.starts_with(&format!("--{}\r\n", writer.boundary)));
+        assert!(text.contains("Content-Type: application/json\r\n"));
+        assert!(text.ends_with("\r\n\r\n{}\r\n"));
+        assert!(writer
+            .content_type()
+            .ends_with(&format!("boundary={}", writer.boundary)));
+    }
+
+    #[test]
+    fn test_manifest_entry_serialization() {
+        let entry = ManifestEntry {
+            index: 3,
+            offset: 2800,
+            name: None,
+            size: None,
+            error: Some("decode_failed".to_string()),
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(json, r#"{"index":3,"offset":2800,"error":"decode_failed"}"#);
+    }
+}
@@ -0,0 +1,224 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use tracing::error;
+
+use super::auth::AuthError;
+use super::limits::{RateLimit, RateLimited};
+use crate::pipeline::decoder::DecoderError;
+use crate::storage::{ErrorKind, PathError, StorageError};
+
+/// Errors returned by HTTP handlers
+///
+/// Rendered as `{"error": "..."}` with a matching status code. Internal
+/// errors are logged in full and reduced to [`client_message`] in the body.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    RateLimited(#[from] RateLimited),
+
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::RateLimited(limited) if limited.limit == RateLimit::FramesPerRequest => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Internal(e) => match storage_error_kind(e) {
+                Some(ErrorKind::NotFound) => StatusCode::NOT_FOUND,
+                Some(ErrorKind::Throttled | ErrorKind::Transient | ErrorKind::Unavailable) => {
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+                Some(ErrorKind::PermissionDenied | ErrorKind::Permanent) => StatusCode::BAD_GATEWAY,
+                None if is_not_found(e) => StatusCode::NOT_FOUND,
+                None => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!("{:#}", self);
+        }
+
+        let message = match &self {
+            ApiError::Internal(e) => client_message(e),
+            _ => self.to_string(),
+        };
+
+        let mut response = match &self {
+            ApiError::RateLimited(limited) => (
+                status,
+                [(
+                    header::RETRY_AFTER,
+                    limited.retry_after.as_secs_f64().ceil().to_string(),
+                )],
+                Json(json!({
+                    "error": message,
+                    "limit": limited.limit,
+                    "retry_after_ms": limited.retry_after.as_millis() as u64,
+                })),
+            )
+                .into_response(),
+            _ => (status, Json(json!({ "error": message }))).into_response(),
+        };
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+        }
+        response
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Forbidden(_) => ApiError::Forbidden(e.to_string()),
+            AuthError::Config(_) => ApiError::Internal(e.into()),
+            _ => ApiError::Unauthorized(e.to_string()),
+        }
+    }
+}
+
+impl From<PathError> for ApiError {
+    fn from(e: PathError) -> Self {
+        ApiError::BadRequest(e.to_string())
+    }
+}
+
+fn storage_error_kind(e: &anyhow::Error) -> Option<ErrorKind> {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<StorageError>())
+        .map(|e| e.kind)
+}
+
+/// Whether an error chain bottoms out in a missing object
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<object_store::Error>(),
+            Some(object_store::Error::NotFound { .. })
+        )
+    })
+}
+
+/// Client-facing description of an internal error
+///
+/// Path, auth, decode and classified storage errors describe the request
+/// and are passed through. Other failures can carry bucket names, local
+/// paths or backend responses, so only a generic message is returned;
+/// callers log the full chain.
+pub fn client_message(e: &anyhow::Error) -> String {
+    for cause in e.chain() {
+        if let Some(e) = cause.downcast_ref::<PathError>() {
+            return e.to_string();
+        }
+        if let Some(e) = cause.downcast_ref::<AuthError>() {
+            return e.to_string();
+        }
+        if let Some(e) = cause.downcast_ref::<DecoderError>() {
+            return e.to_string();
+        }
+        if let Some(e) = cause.downcast_ref::<StorageError>() {
+            return e.to_string();
+        }
+    }
+
+    if is_not_found(e) {
+        "Not found".to_string()
+    } else {
+        "Internal error".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_client_message_hides_storage_details() {
+        let store_error = anyhow::Error::from(object_store::Error::Generic {
+            store: "S3",
+            source: "403 Forbidden: s3://secret-bucket/videos/a.h265".into(),
+        })
+        .context("Failed to get object");
+        assert_eq!(client_message(&store_error), "Internal error");
+
+        let response = axum::http::Response::builder()
+            .status(429)
+            .body("s3://secret-bucket")
+            .unwrap();
+        let source = reqwest::Response::from(response)
+            .error_for_status()
+            .unwrap_err();
+        let throttled = anyhow::Error::from(StorageError::from(object_store::Error::Generic {
+            store: "S3",
+            source: Box::new(source),
+        }))
+        .context("Failed to fetch byte range");
+        assert_eq!(
+            client_message(&throttled),
+            "Storage is throttling requests; retry shortly"
+        );
+        assert_eq!(
+            ApiError::Internal(throttled).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let missing = anyhow::Error::from(object_store::Error::NotFound {
+            path: "/srv/data/videos/a.h265".to_string(),
+            source: "No such file or directory".into(),
+        })
+        .context("Failed to get object");
+        assert_eq!(client_message(&missing), "Not found");
+        assert_eq!(ApiError::Internal(missing).status(), StatusCode::NOT_FOUND);
+
+        let path_error = Err::<(), _>(PathError::Traversal)
+            .context("Invalid path")
+            .unwrap_err();
+        assert_eq!(
+            client_message(&path_error),
+            "Path contains '.' or '..' segments"
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_response() {
+        let response = ApiError::from(RateLimited {
+            limit: RateLimit::FramesPerSecond,
+            retry_after: std::time::Duration::from_millis(1500),
+        })
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+}
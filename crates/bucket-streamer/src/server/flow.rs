@@ -0,0 +1,248 @@
+use std::sync::Mutex;
+
+use tokio::sync::Notify;
+
+/// Quality reduction per slow frame
+const QUALITY_STEP: u8 = 15;
+
+/// Consecutive fast frames before quality is raised again
+const RECOVER_AFTER: u32 = 30;
+
+/// Client-advertised limit on frames in flight
+///
+/// Each sent frame takes one credit and each `Ack` returns one. A window of
+/// 0 disables flow control, and frames sent meanwhile are not counted, so a
+/// later window starts empty. Only the session's frame worker waits on the
+/// window, so a single `Notify` permit is enough to avoid lost wakeups.
+#[derive(Debug)]
+pub struct CreditWindow {
+    state: Mutex<WindowState>,
+    notify: Notify,
+}
+
+#[derive(Debug)]
+struct WindowState {
+    window: u32,
+    in_flight: u32,
+}
+
+impl CreditWindow {
+    pub fn new(window: u32) -> Self {
+        Self {
+            state: Mutex::new(WindowState {
+                window,
+                in_flight: 0,
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Change the window size (`Credit` message)
+    pub fn set_window(&self, window: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.window = window;
+        if window == 0 {
+            state.in_flight = 0;
+        }
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// Return one credit (`Ack` message)
+    pub fn ack(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight = state.in_flight.saturating_sub(1);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// Wait until a frame may be sent and take its credit
+    pub async fn acquire(&self) {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.window == 0 {
+                    return;
+                }
+                if state.in_flight < state.window {
+                    state.in_flight += 1;
+                    return;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Frames sent under a window but not yet acknowledged
+    pub fn in_flight(&self) -> u32 {
+        self.state.lock().unwrap().in_flight
+    }
+}
+
+/// Adapts JPEG quality to how fast a client drains frames
+///
+/// Each slow delivery lowers quality by a step down to `min`; a run of fast
+/// deliveries raises it back one step at a time up to the configured value.
+#[derive(Debug, Clone)]
+pub struct QualityController {
+    max: u8,
+    min: u8,
+    current: u8,
+    fast_streak: u32,
+}
+
+impl QualityController {
+    pub fn new(max: u8, min: u8) -> Self {
+        Self {
+            max,
+            min: min.min(max),
+            current: max,
+            fast_streak: 0,
+        }
+    }
+
+    pub fn current(&self) -> u8 {
+        self.current
+    }
+
+    /// Apply reloaded quality bounds; returns the new quality if it changed
+    pub fn set_bounds(&mut self, max: u8, min: u8) -> Option<u8> {
+        let previous = self.current;
+        if self.max == max && self.min == min.min(max) {
+            return None;
+        }
+        // A client at the old ceiling moves to the new one
+        let at_max = self.current == self.max;
+        self.max = max;
+        self.min = min.min(max);
+        self.current = if at_max {
+            max
+        } else {
+            self.current.clamp(self.min, self.max)
+        };
+        (self.current != previous).then_some(self.current)
+    }
+
+    /// Record one delivery; returns the new quality if it changed
+    pub fn record(&mut self, slow: bool) -> Option<u8> {
+        let previous = self.current;
+
+        if slow {
+            self.fast_streak = 0;
+            self.current = self.current.saturating_sub(QUALITY_STEP).max(self.min);
+        } else {
+            self.fast_streak += 1;
+            if self.fast_streak >= RECOVER_AFTER && self.current < self.max {
+                self.fast_streak = 0;
+                self.current = self.current.saturating_add(QUALITY_STEP).min(self.max);
+            }
+        }
+
+        (self.current != previous).then_some(self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_credit_window_blocks_until_ack() {
+        let credits = Arc::new(CreditWindow::new(2));
+        credits.acquire().await;
+        credits.acquire().await;
+        assert_eq!(credits.in_flight(), 2);
+
+        let waiter = {
+            let credits = credits.clone();
+            tokio::spawn(async move { credits.acquire().await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        credits.ack();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("acquire should complete after ack")
+            .unwrap();
+        assert_eq!(credits.in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_credit_window_resize() {
+        let credits = Arc::new(CreditWindow::new(1));
+        credits.acquire().await;
+
+        let waiter = {
+            let credits = credits.clone();
+            tokio::spawn(async move { credits.acquire().await })
+        };
+        credits.set_window(0);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("window 0 disables flow control")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_credit_window_enabled_later() {
+        let credits = Arc::new(CreditWindow::new(0));
+        for _ in 0..3 {
+            credits.acquire().await;
+        }
+        assert_eq!(credits.in_flight(), 0);
+
+        // Frames sent without a window are never acknowledged
+        credits.set_window(1);
+        tokio::time::timeout(Duration::from_secs(1), credits.acquire())
+            .await
+            .expect("a new window starts empty");
+        assert_eq!(credits.in_flight(), 1);
+
+        let waiter = {
+            let credits = credits.clone();
+            tokio::spawn(async move { credits.acquire().await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+        credits.set_window(0);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(credits.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_quality_controller() {
+        let mut quality = QualityController::new(80, 40);
+        assert_eq!(quality.record(false), None);
+        assert_eq!(quality.record(true), Some(65));
+        assert_eq!(quality.record(true), Some(50));
+        assert_eq!(quality.record(true), Some(40));
+        assert_eq!(quality.record(true), None);
+
+        for _ in 0..RECOVER_AFTER - 1 {
+            assert_eq!(quality.record(false), None);
+        }
+        assert_eq!(quality.record(false), Some(55));
+        assert_eq!(quality.current(), 55);
+    }
+
+    #[test]
+    fn test_quality_bounds_reload() {
+        let mut quality = QualityController::new(80, 40);
+        assert_eq!(quality.set_bounds(80, 40), None);
+
+        // At the ceiling, the client follows it
+        assert_eq!(quality.set_bounds(90, 40), Some(90));
+        // Downgraded clients stay put unless out of range
+        quality.record(true);
+        let downgraded = quality.current();
+        assert_eq!(quality.set_bounds(95, 40), None);
+        assert_eq!(quality.current(), downgraded);
+        assert_eq!(quality.set_bounds(60, 40), Some(60));
+    }
+}
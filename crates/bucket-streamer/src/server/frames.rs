@@ -0,0 +1,338 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use object_store::ObjectMeta;
+use serde::Deserialize;
+use tracing::{debug, Span};
+
+use super::auth::Principal;
+use super::error::ApiError;
+use super::limits::client_key;
+use super::protocol::FrameRequest;
+use super::router::AppState;
+use super::signed_url::{now_secs, SIGNED_URL_SUBJECT};
+use crate::pipeline::decoder::DecoderError;
+use crate::pipeline::fetcher;
+use crate::pipeline::frame::{process_frame, EncodeOptions, ImageFormat};
+use crate::storage::disk_cache::fnv1a64;
+use crate::telemetry;
+
+/// Query parameters for `GET /frames/{path}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrameQuery {
+    /// Byte offset of the frame in the video file
+    pub offset: u64,
+    /// Byte offset of the IRAP to decode from (defaults to `offset`)
+    pub irap_offset: Option<u64>,
+    /// Output image format
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// Output width in pixels, preserving aspect ratio
+    pub width: Option<u32>,
+}
+
+/// Serve a single encoded frame over plain HTTP
+///
+/// Responses carry a strong `ETag` derived from the source object and the
+/// encode settings, so CDNs and browsers can revalidate with
+/// `If-None-Match` without the server decoding anything. Responses are only
+/// cacheable by shared caches when authentication is off.
+pub async fn get_frame(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Path(path): Path<String>,
+    Query(query): Query<FrameQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let path = state.policy.video_path(&path)?;
+    principal.authorize(&path)?;
+    if query.width == Some(0) {
+        return Err(ApiError::BadRequest("width must be greater than 0".into()));
+    }
+
+    let request = FrameRequest {
+        offset: query.offset,
+        irap_offset: query.irap_offset.unwrap_or(query.offset),
+        index: 0,
+    };
+    let config = state.config.current();
+    let options = EncodeOptions {
+        format: query.format,
+        quality: config.jpeg_quality,
+        width: query.width,
+    };
+
+    let meta = fetcher::video_meta(&state.store, &path)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Video not found: {}", path)))?;
+
+    let etag = frame_etag(&meta, &request, &options);
+    let private = state.auth.is_enabled();
+    let mut cache_headers = HeaderMap::new();
+    cache_headers.insert(header::ETAG, header_value(&etag)?);
+    cache_headers.insert(
+        header::CACHE_CONTROL,
+        header_value(&cache_control(
+            config.http_cache_max_age,
+            private,
+            &principal,
+        ))?,
+    );
+    if private {
+        cache_headers.insert(header::VARY, HeaderValue::from_static("authorization"));
+    }
+
+    let not_modified = etag_matches(&headers, &etag);
+    telemetry::record_cache("http_etag", not_modified);
+    if not_modified {
+        debug!("Frame {}@{} not modified", path, request.offset);
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let client = client_key(&principal, connect_info.map(|ConnectInfo(addr)| addr));
+    state.limiter.check_frame(&client)?;
+
+    let offset = request.offset;
+    let gop = state
+        .gops
+        .fetch(&state.store, &path, &meta, &request)
+        .await
+        .map_err(|e| frame_error(e, offset))?;
+    state.prefetch.observe(
+        &client,
+        &state.store,
+        &path,
+        meta.size as u64,
+        gop.range.start,
+    );
+
+    let span = Span::current();
+    let image = tokio::task::spawn_blocking(move || {
+        span.in_scope(|| process_frame(gop, &request, &options))
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+    .map_err(|e| frame_error(e, offset))?;
+    state.limiter.record_bytes(&client, image.len());
+
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(options.format.content_type()),
+        )],
+        cache_headers,
+        image,
+    )
+        .into_response())
+}
+
+/// A missing frame is a 404; anything else is passed on
+fn frame_error(e: anyhow::Error, offset: u64) -> ApiError {
+    match e.downcast_ref::<DecoderError>() {
+        Some(DecoderError::FrameNotFound(_)) => {
+            ApiError::NotFound(format!("Frame at offset {} not found", offset))
+        }
+        _ => ApiError::Internal(e),
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, ApiError> {
+    HeaderValue::from_str(value).map_err(|e| ApiError::Internal(e.into()))
+}
+
+/// `Cache-Control` for a frame response
+///
+/// With authentication on, only the caller's own cache may keep the frame.
+/// A signed URL stops working at its expiry, so nothing may serve it past
+/// that either.
+fn cache_control(max_age: u64, private: bool, principal: &Principal) -> String {
+    let max_age = match principal.expires_at {
+        Some(expires_at) if principal.subject == SIGNED_URL_SUBJECT => {
+            max_age.min(expires_at.saturating_sub(now_secs()))
+        }
+        _ => max_age,
+    };
+    let scope = if private { "private" } else { "public" };
+    format!("{}, max-age={}", scope, max_age)
+}
+
+/// Strong ETag for a frame: changes whenever the source object or the
+/// encode settings change, and stays the same across restarts and builds
+fn frame_etag(meta: &ObjectMeta, request: &FrameRequest, options: &EncodeOptions) -> String {
+    let hash = fnv1a64(&[
+        meta.location.as_ref().as_bytes(),
+        b"\0",
+        &(meta.size as u64).to_le_bytes(),
+        &meta
+            .last_modified
+            .timestamp_nanos_opt()
+            .unwrap_or_default()
+            .to_le_bytes(),
+        meta.e_tag.as_deref().unwrap_or("").as_bytes(),
+        b"\0",
+        &request.offset.to_le_bytes(),
+        &request.irap_offset.to_le_bytes(),
+        options.format.extension().as_bytes(),
+        &[options.quality],
+        &options.width.unwrap_or(0).to_le_bytes(),
+    ]);
+    format!("\"{:016x}\"", hash)
+}
+
+/// Check an `If-None-Match` header against the current ETag
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|candidate| candidate.trim().trim_start_matches("W/"))
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::server::create_router;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    fn test_app() -> (axum::Router, TempDir) {
+        test_app_with(|config| config)
+    }
+
+    fn test_app_with(configure: impl FnOnce(Config) -> Config) -> (axum::Router, TempDir) {
+        let temp = TempDir::new().unwrap();
+        std::fs::write(temp.path().join("clip.h265"), b"not really a video").unwrap();
+
+        let config = configure(Config {
+            local_path: temp.path().to_str().unwrap().to_string(),
+            ..Config::default()
+        });
+        let store = crate::storage::create_store(&config).unwrap();
+        let state = AppState::new(config, store).unwrap();
+        (create_router(state), temp)
+    }
+
+    #[tokio::test]
+    async fn test_missing_video_is_not_found() {
+        let (app, _temp) = test_app();
+
+        let response = app
+            .oneshot(
+                Request::get("/frames/missing.h265?offset=48")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_query_is_bad_request() {
+        let (app, _temp) = test_app();
+
+        for uri in [
+            "/frames/clip.h265",
+            "/frames/clip.h265?offset=48&format=gif",
+            "/frames/clip.h265?offset=48&width=0",
+        ] {
+            let response = app
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    async fn revalidate(app: &axum::Router, uri: &str, key: Option<&str>) -> Response {
+        let mut request = Request::get(uri).header(header::IF_NONE_MATCH, "*");
+        if let Some(key) = key {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", key));
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{}", uri);
+        response
+    }
+
+    #[tokio::test]
+    async fn test_cache_control() {
+        let (app, _temp) = test_app();
+        let response = revalidate(&app, "/frames/clip.h265?offset=48", None).await;
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=86400"
+        );
+        assert!(response.headers().get(header::VARY).is_none());
+
+        let (app, _temp) = test_app_with(|config| Config {
+            auth_api_keys: vec!["key".to_string()],
+            url_signing_key: Some("signing-secret".to_string()),
+            ..config
+        });
+        let response = revalidate(&app, "/frames/clip.h265?offset=48", Some("key")).await;
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "private, max-age=86400"
+        );
+        assert_eq!(response.headers()[header::VARY], "authorization");
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/signed-urls")
+                    .header(header::AUTHORIZATION, "Bearer key")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"path": "clip.h265", "offset": 48, "ttl": 60}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let signed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let response = revalidate(&app, signed["url"].as_str().unwrap(), None).await;
+        let cache_control = response.headers()[header::CACHE_CONTROL].to_str().unwrap();
+        let max_age: u64 = cache_control
+            .strip_prefix("private, max-age=")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((59..=60).contains(&max_age), "{}", cache_control);
+        assert_eq!(response.headers()[header::VARY], "authorization");
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"aaaa\", W/\"bbbb\""),
+        );
+
+        assert!(etag_matches(&headers, "\"aaaa\""));
+        assert!(etag_matches(&headers, "\"bbbb\""));
+        assert!(!etag_matches(&headers, "\"cccc\""));
+        assert!(!etag_matches(&HeaderMap::new(), "\"aaaa\""));
+    }
+}
@@ -0,0 +1,182 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+
+use super::router::AppState;
+use crate::pipeline::decoder::Decoder;
+use crate::pipeline::encoder::JpegEncoder;
+use crate::telemetry;
+
+/// Longest a readiness probe waits on storage
+const STORE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Object probed to check storage connectivity; it need not exist
+const STORE_PROBE_PATH: &str = ".health-check";
+
+/// Codec availability never changes while the process runs
+static DECODER_AVAILABLE: OnceLock<bool> = OnceLock::new();
+static ENCODER_AVAILABLE: OnceLock<bool> = OnceLock::new();
+
+/// State of one dependency checked by `/health/ready`
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ComponentStatus {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ComponentStatus {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            detail: None,
+        }
+    }
+
+    fn ok_with(detail: String) -> Self {
+        Self {
+            ok: true,
+            detail: Some(detail),
+        }
+    }
+
+    fn failed(detail: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Components {
+    pub store: ComponentStatus,
+    pub decoder: ComponentStatus,
+    pub encoder: ComponentStatus,
+    pub workers: ComponentStatus,
+}
+
+/// Body of `/health/ready`
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// `ready`, `unready` or `draining`
+    pub status: &'static str,
+    pub components: Components,
+}
+
+/// Liveness probe: the process is up and serving requests
+pub async fn live() -> impl IntoResponse {
+    (StatusCode::OK, "ok")
+}
+
+/// Readiness probe: storage, codecs and workers can take traffic
+///
+/// Returns 503 while any component is failing or the server is draining.
+pub async fn ready(State(state): State<AppState>) -> impl IntoResponse {
+    let components = Components {
+        store: check_store(&state).await,
+        decoder: check_decoder(),
+        encoder: check_encoder(),
+        workers: check_workers(state.config.current().ready_max_frames_in_flight),
+    };
+
+    let all_ok = components.store.ok
+        && components.decoder.ok
+        && components.encoder.ok
+        && components.workers.ok;
+    let status = if state.shutdown.is_triggered() {
+        "draining"
+    } else if all_ok {
+        "ready"
+    } else {
+        "unready"
+    };
+
+    let code = if status == "ready" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(Readiness { status, components }))
+}
+
+async fn check_store(state: &AppState) -> ComponentStatus {
+    let probe = crate::storage::head(state.store.as_ref(), STORE_PROBE_PATH);
+    match tokio::time::timeout(STORE_TIMEOUT, probe).await {
+        // A missing probe object still proves the store answered
+        Ok(Ok(_)) => ComponentStatus::ok(),
+        Ok(Err(e)) => {
+            tracing::warn!("Readiness: storage check failed: {:#}", e);
+            ComponentStatus::failed("unreachable")
+        }
+        Err(_) => {
+            tracing::warn!(
+                "Readiness: storage check timed out after {:?}",
+                STORE_TIMEOUT
+            );
+            ComponentStatus::failed("timed out")
+        }
+    }
+}
+
+fn check_decoder() -> ComponentStatus {
+    let available = *DECODER_AVAILABLE.get_or_init(|| match Decoder::check_available() {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!("Readiness: decoder unavailable: {}", e);
+            false
+        }
+    });
+    if available {
+        ComponentStatus::ok()
+    } else {
+        ComponentStatus::failed("HEVC decoder unavailable")
+    }
+}
+
+fn check_encoder() -> ComponentStatus {
+    let available = *ENCODER_AVAILABLE.get_or_init(|| match JpegEncoder::new(80) {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("Readiness: encoder unavailable: {}", e);
+            false
+        }
+    });
+    if available {
+        ComponentStatus::ok()
+    } else {
+        ComponentStatus::failed("JPEG encoder unavailable")
+    }
+}
+
+fn check_workers(max_in_flight: usize) -> ComponentStatus {
+    worker_status(telemetry::frames_in_flight(), max_in_flight)
+}
+
+fn worker_status(in_flight: usize, max_in_flight: usize) -> ComponentStatus {
+    let detail = format!("{} frames in flight", in_flight);
+    if max_in_flight > 0 && in_flight >= max_in_flight {
+        ComponentStatus::failed(detail)
+    } else {
+        ComponentStatus::ok_with(detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_saturation() {
+        assert!(worker_status(0, 4).ok);
+        assert!(worker_status(3, 4).ok);
+        assert_eq!(
+            worker_status(4, 4),
+            ComponentStatus::failed("4 frames in flight")
+        );
+        // 0 disables the limit
+        assert!(worker_status(10_000, 0).ok);
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::auth::{Principal, ANONYMOUS_SUBJECT};
+use super::signed_url::SIGNED_URL_SUBJECT;
+use crate::config::Config;
+
+/// Retry hint when the session limit is reached (sessions have no refill rate)
+const SESSION_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Idle clients are forgotten once this many are tracked
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Idle time after which a client's buckets are full again and can be dropped
+const CLIENT_IDLE: Duration = Duration::from_secs(60);
+
+/// Which limit was hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimit {
+    FramesPerRequest,
+    FramesPerSecond,
+    Sessions,
+    BytesPerMinute,
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RateLimit::FramesPerRequest => "frames_per_request",
+            RateLimit::FramesPerSecond => "frames_per_second",
+            RateLimit::Sessions => "sessions",
+            RateLimit::BytesPerMinute => "bytes_per_minute",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A limit was hit; retrying before `retry_after` will fail again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Rate limited ({limit}), retry after {} ms", retry_after.as_millis())]
+pub struct RateLimited {
+    pub limit: RateLimit,
+    pub retry_after: Duration,
+}
+
+/// Token bucket refilled continuously at `rate` tokens per second
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    rate: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(capacity: f64, rate: f64, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            capacity,
+            rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Time until `amount` tokens are available
+    fn wait_for(&self, amount: f64) -> Duration {
+        Duration::from_secs_f64(((amount - self.tokens) / self.rate).max(0.0))
+    }
+}
+
+/// Per-client buckets; `None` when the limit is disabled
+#[derive(Debug)]
+struct ClientBuckets {
+    frames: Option<Bucket>,
+    bytes: Option<Bucket>,
+    seen: Instant,
+}
+
+/// Enforces frame, byte and session limits
+///
+/// Frame and byte rates are tracked per client key (see [`client_key`]).
+/// Byte usage is only known after encoding, so the byte bucket may go into
+/// debt; further frames are refused until it is paid back.
+pub struct RateLimiter {
+    max_frames_per_request: AtomicUsize,
+    frames_per_second: AtomicU32,
+    bytes_per_minute: AtomicU64,
+    max_sessions: AtomicUsize,
+    clients: Mutex<HashMap<String, ClientBuckets>>,
+    sessions: Arc<AtomicUsize>,
+}
+
+impl RateLimiter {
+    pub fn from_config(config: &Config) -> Self {
+        let limiter = Self {
+            max_frames_per_request: AtomicUsize::new(0),
+            frames_per_second: AtomicU32::new(0),
+            bytes_per_minute: AtomicU64::new(0),
+            max_sessions: AtomicUsize::new(0),
+            clients: Mutex::new(HashMap::new()),
+            sessions: Arc::new(AtomicUsize::new(0)),
+        };
+        limiter.update(config);
+        limiter
+    }
+
+    /// Apply reloaded limits
+    ///
+    /// When a rate changes, each client's bucket keeps what it had left, up
+    /// to the new burst, so a reload neither refills nor resets anyone; a
+    /// newly enabled limit starts full. Open sessions are never closed.
+    pub fn update(&self, config: &Config) {
+        self.max_frames_per_request
+            .store(config.max_frames_per_request, Ordering::Relaxed);
+        self.max_sessions
+            .store(config.max_sessions, Ordering::Relaxed);
+
+        let mut clients = self.clients.lock().unwrap();
+        let fps = self
+            .frames_per_second
+            .swap(config.max_fps_per_client, Ordering::Relaxed);
+        let per_minute = self
+            .bytes_per_minute
+            .swap(config.max_bytes_per_minute, Ordering::Relaxed);
+        let frames_changed = fps != config.max_fps_per_client;
+        let bytes_changed = per_minute != config.max_bytes_per_minute;
+        if !frames_changed && !bytes_changed {
+            return;
+        }
+
+        let now = Instant::now();
+        for buckets in clients.values_mut() {
+            if frames_changed {
+                let new = frames_bucket(config.max_fps_per_client, now);
+                buckets.frames = carry_over(buckets.frames.take(), new, now);
+            }
+            if bytes_changed {
+                let new = bytes_bucket(config.max_bytes_per_minute, now);
+                buckets.bytes = carry_over(buckets.bytes.take(), new, now);
+            }
+        }
+    }
+
+    /// Check the size of a single request
+    pub fn check_request(&self, frames: usize) -> Result<(), RateLimited> {
+        let max_frames = self.max_frames_per_request.load(Ordering::Relaxed);
+        if max_frames > 0 && frames > max_frames {
+            return Err(RateLimited {
+                limit: RateLimit::FramesPerRequest,
+                retry_after: Duration::ZERO,
+            });
+        }
+        Ok(())
+    }
+
+    /// Take one frame from the client's allowance
+    pub fn check_frame(&self, client: &str) -> Result<(), RateLimited> {
+        self.with_client(client, |buckets, now| {
+            if let Some(bytes) = &mut buckets.bytes {
+                bytes.refill(now);
+                if bytes.tokens < 0.0 {
+                    return Err(RateLimited {
+                        limit: RateLimit::BytesPerMinute,
+                        retry_after: bytes.wait_for(0.0),
+                    });
+                }
+            }
+
+            if let Some(frames) = &mut buckets.frames {
+                frames.refill(now);
+                if frames.tokens < 1.0 {
+                    return Err(RateLimited {
+                        limit: RateLimit::FramesPerSecond,
+                        retry_after: frames.wait_for(1.0),
+                    });
+                }
+                frames.tokens -= 1.0;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Charge bytes sent to the client
+    pub fn record_bytes(&self, client: &str, bytes: usize) {
+        let _ = self.with_client(client, |buckets, now| {
+            if let Some(bucket) = &mut buckets.bytes {
+                bucket.refill(now);
+                bucket.tokens -= bytes as f64;
+            }
+            Ok(())
+        });
+    }
+
+    /// Reserve a session slot, released when the permit is dropped
+    pub fn open_session(&self) -> Result<SessionPermit, RateLimited> {
+        let active = self.sessions.fetch_add(1, Ordering::SeqCst) + 1;
+        let permit = SessionPermit {
+            sessions: self.sessions.clone(),
+        };
+
+        let max_sessions = self.max_sessions.load(Ordering::Relaxed);
+        if max_sessions > 0 && active > max_sessions {
+            return Err(RateLimited {
+                limit: RateLimit::Sessions,
+                retry_after: SESSION_RETRY_AFTER,
+            });
+        }
+        Ok(permit)
+    }
+
+    /// Number of open sessions
+    pub fn active_sessions(&self) -> usize {
+        self.sessions.load(Ordering::SeqCst)
+    }
+
+    fn with_client<T>(
+        &self,
+        client: &str,
+        f: impl FnOnce(&mut ClientBuckets, Instant) -> Result<T, RateLimited>,
+    ) -> Result<T, RateLimited> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(client) {
+            clients.retain(|_, buckets| now.saturating_duration_since(buckets.seen) < CLIENT_IDLE);
+        }
+
+        let frames_per_second = self.frames_per_second.load(Ordering::Relaxed);
+        let bytes_per_minute = self.bytes_per_minute.load(Ordering::Relaxed);
+        let buckets = clients
+            .entry(client.to_string())
+            .or_insert_with(|| ClientBuckets {
+                frames: frames_bucket(frames_per_second, now),
+                bytes: bytes_bucket(bytes_per_minute, now),
+                seen: now,
+            });
+        buckets.seen = now;
+
+        f(buckets, now)
+    }
+}
+
+/// A full frame bucket, or `None` when the limit is disabled
+fn frames_bucket(frames_per_second: u32, now: Instant) -> Option<Bucket> {
+    (frames_per_second > 0).then(|| {
+        let fps = frames_per_second as f64;
+        Bucket::new(fps, fps, now)
+    })
+}
+
+/// A full byte bucket, or `None` when the limit is disabled
+fn bytes_bucket(bytes_per_minute: u64, now: Instant) -> Option<Bucket> {
+    (bytes_per_minute > 0).then(|| {
+        let per_minute = bytes_per_minute as f64;
+        Bucket::new(per_minute, per_minute / 60.0, now)
+    })
+}
+
+/// `new`, holding what was left in `old` up to its capacity
+fn carry_over(old: Option<Bucket>, new: Option<Bucket>, now: Instant) -> Option<Bucket> {
+    match (old, new) {
+        (Some(mut old), Some(new)) => {
+            old.refill(now);
+            Some(Bucket {
+                tokens: old.tokens.min(new.capacity),
+                ..new
+            })
+        }
+        (_, new) => new,
+    }
+}
+
+/// Held for the lifetime of a session
+#[derive(Debug)]
+pub struct SessionPermit {
+    sessions: Arc<AtomicUsize>,
+}
+
+impl Drop for SessionPermit {
+    fn drop(&mut self) {
+        self.sessions.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Key rate limits are tracked under
+///
+/// Callers identified by an API key or token share one allowance across
+/// connections; anonymous and signed-URL callers are keyed by IP address.
+pub fn client_key(principal: &Principal, addr: Option<SocketAddr>) -> String {
+    let shared = principal.subject == ANONYMOUS_SUBJECT || principal.subject == SIGNED_URL_SUBJECT;
+    match addr {
+        Some(addr) if shared => addr.ip().to_string(),
+        _ => principal.subject.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(configure: impl FnOnce(&mut Config)) -> RateLimiter {
+        let mut config = Config::default();
+        configure(&mut config);
+        RateLimiter::from_config(&config)
+    }
+
+    #[test]
+    fn test_frames_per_request() {
+        let limiter = limiter(|c| c.max_frames_per_request = 2);
+        assert!(limiter.check_request(2).is_ok());
+        assert_eq!(
+            limiter.check_request(3).unwrap_err().limit,
+            RateLimit::FramesPerRequest
+        );
+    }
+
+    #[test]
+    fn test_frames_per_second() {
+        let limiter = limiter(|c| c.max_fps_per_client = 2);
+        assert!(limiter.check_frame("a").is_ok());
+        assert!(limiter.check_frame("a").is_ok());
+
+        let limited = limiter.check_frame("a").unwrap_err();
+        assert_eq!(limited.limit, RateLimit::FramesPerSecond);
+        assert!(limited.retry_after > Duration::ZERO);
+        assert!(limited.retry_after <= Duration::from_millis(500));
+
+        // Other clients have their own allowance
+        assert!(limiter.check_frame("b").is_ok());
+    }
+
+    #[test]
+    fn test_bytes_per_minute() {
+        let limiter = limiter(|c| c.max_bytes_per_minute = 6000);
+        assert!(limiter.check_frame("a").is_ok());
+        limiter.record_bytes("a", 6100);
+
+        let limited = limiter.check_frame("a").unwrap_err();
+        assert_eq!(limited.limit, RateLimit::BytesPerMinute);
+        // 100 bytes of debt at 100 bytes/s
+        assert!(limited.retry_after <= Duration::from_secs(1));
+        assert!(limited.retry_after > Duration::from_millis(900));
+    }
+
+    #[test]
+    fn test_sessions() {
+        let limiter = limiter(|c| c.max_sessions = 1);
+        let permit = limiter.open_session().unwrap();
+        assert_eq!(
+            limiter.open_session().unwrap_err().limit,
+            RateLimit::Sessions
+        );
+        assert_eq!(limiter.active_sessions(), 1);
+
+        drop(permit);
+        assert_eq!(limiter.active_sessions(), 0);
+        assert!(limiter.open_session().is_ok());
+    }
+
+    #[test]
+    fn test_update() {
+        let limiter = limiter(|c| c.max_fps_per_client = 1);
+        assert!(limiter.check_frame("a").is_ok());
+        assert!(limiter.check_frame("a").is_err());
+
+        let _permit = limiter.open_session().unwrap();
+        limiter.update(&Config {
+            max_fps_per_client: 3,
+            max_sessions: 1,
+            ..Config::default()
+        });
+        // A spent allowance stays spent; new clients get the new burst
+        assert!(limiter.check_frame("a").is_err());
+        for _ in 0..3 {
+            assert!(limiter.check_frame("b").is_ok());
+        }
+        assert!(limiter.check_frame("b").is_err());
+        assert_eq!(
+            limiter.open_session().unwrap_err().limit,
+            RateLimit::Sessions
+        );
+    }
+
+    #[test]
+    fn test_update_keeps_bucket_levels() {
+        let limiter = limiter(|c| {
+            c.max_fps_per_client = 10;
+            c.max_bytes_per_minute = 6000;
+        });
+        for _ in 0..4 {
+            assert!(limiter.check_frame("a").is_ok());
+        }
+        limiter.record_bytes("a", 6100);
+
+        // Unchanged rates leave buckets alone, byte debt included
+        limiter.update(&Config {
+            max_fps_per_client: 10,
+            max_bytes_per_minute: 6000,
+            max_sessions: 5,
+            ..Config::default()
+        });
+        assert_eq!(
+            limiter.check_frame("a").unwrap_err().limit,
+            RateLimit::BytesPerMinute
+        );
+
+        // A lower burst clamps what is left
+        limiter.update(&Config {
+            max_fps_per_client: 2,
+            ..Config::default()
+        });
+        assert!(limiter.check_frame("a").is_ok());
+        assert!(limiter.check_frame("a").is_ok());
+        assert_eq!(
+            limiter.check_frame("a").unwrap_err().limit,
+            RateLimit::FramesPerSecond
+        );
+    }
+
+    #[test]
+    fn test_unlimited_by_default() {
+        let limiter = limiter(|c| c.max_frames_per_request = 0);
+        assert!(limiter.check_request(100_000).is_ok());
+        for _ in 0..1000 {
+            assert!(limiter.check_frame("a").is_ok());
+        }
+        limiter.record_bytes("a", usize::MAX);
+        assert!(limiter.check_frame("a").is_ok());
+    }
+
+    #[test]
+    fn test_client_key() {
+        let addr: SocketAddr = "10.0.0.7:5123".parse().unwrap();
+        assert_eq!(client_key(&Principal::anonymous(), Some(addr)), "10.0.0.7");
+        assert_eq!(client_key(&Principal::anonymous(), None), "anonymous");
+
+        let principal = Principal {
+            subject: "alice".to_string(),
+            scopes: vec![],
+            expires_at: None,
+        };
+        assert_eq!(client_key(&principal, Some(addr)), "alice");
+    }
+}
@@ -1,5 +1,16 @@
+pub mod auth;
+pub mod batch;
+pub mod error;
+pub mod flow;
+pub mod frames;
+pub mod health;
+pub mod limits;
 pub mod protocol;
+pub mod reload;
 pub mod router;
+pub mod shutdown;
+pub mod signed_url;
+pub mod videos;
 pub mod websocket;
 
 pub use protocol::{ClientMessage, FrameRequest, ServerMessage};
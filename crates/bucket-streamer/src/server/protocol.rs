@@ -1,10 +1,17 @@
 use serde::{Deserialize, Serialize};
 
+use super::limits::RateLimit;
+use crate::pipeline::decoder::VideoInfo;
+
 /// Messages sent from client to server
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(tag = "type")]
 pub enum ClientMessage {
     /// Set the video source for this session
+    ///
+    /// `path` is a path in the default store, or a storage URL
+    /// (`s3://bucket/clip.h265`, `fs:///srv/videos/clip.h265`) naming the
+    /// default store or one configured under `[[stores]]`.
     SetVideo { path: String },
 
     /// Request frames by byte offset
@@ -12,6 +19,18 @@ pub enum ClientMessage {
         /// List of frames to extract
         frames: Vec<FrameRequest>,
     },
+
+    /// Request metadata for the current video
+    GetInfo,
+
+    /// Frame received; returns one credit to the session's window
+    Ack {
+        /// Frame index (from request)
+        index: u32,
+    },
+
+    /// Set how many frames may be in flight (0 = unlimited)
+    Credit { window: u32 },
 }
 
 /// Individual frame request within a RequestFrames message
@@ -25,13 +44,37 @@ pub struct FrameRequest {
     pub index: u32,
 }
 
+/// Server-side time spent on one frame, in microseconds
+///
+/// Only sent to sessions opened with `debug_timings`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FrameTimings {
+    /// From receipt of the `RequestFrames` message to the start of this frame
+    pub queue_us: u64,
+    /// Reading the frame's GOP from storage (0 when already loaded)
+    pub fetch_us: u64,
+    /// Demuxing and decoding up to the target frame
+    pub decode_us: u64,
+    /// Packets decoded to reach the target frame
+    pub packets_decoded: u32,
+    /// Pixel format conversion and scaling
+    pub convert_us: u64,
+    /// Image encoding
+    pub encode_us: u64,
+    /// Writing the frame to the socket, after any wait for credit
+    pub send_us: u64,
+}
+
 /// Messages sent from server to client
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type")]
 pub enum ServerMessage {
     /// Acknowledgment of SetVideo
     VideoSet { path: String, ok: bool },
 
+    /// Response to GetInfo
+    VideoInfo { path: String, info: VideoInfo },
+
     /// Frame metadata (binary JPEG follows immediately)
     Frame {
         /// Frame index (from request)
@@ -40,6 +83,9 @@ pub enum ServerMessage {
         offset: u64,
         /// Size of JPEG data in bytes
         size: u32,
+        /// Timing breakdown (`debug_timings` sessions only)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timings: Option<FrameTimings>,
     },
 
     /// Frame decode/encode failed
@@ -52,6 +98,24 @@ pub enum ServerMessage {
         error: String,
     },
 
+    /// A rate limit or quota was hit; frames from `index` on were not sent
+    RateLimited {
+        /// Limit that was hit
+        limit: RateLimit,
+        /// Milliseconds to wait before retrying
+        retry_after_ms: u64,
+        /// First frame not processed (absent if the whole request was refused)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        index: Option<u32>,
+    },
+
+    /// The server is stopping: queued frames are still sent, new requests
+    /// are refused, and the connection closes within `deadline_ms`
+    ServerShuttingDown { deadline_ms: u64 },
+
+    /// JPEG quality changed because the client is draining frames slowly
+    QualityChanged { quality: u8 },
+
     /// General error (malformed request, video not found, etc.)
     Error { message: String },
 }
@@ -110,6 +174,37 @@ mod tests {
         assert_eq!(parsed, msg);
     }
 
+    #[test]
+    fn test_get_info_parse() {
+        let msg = ClientMessage::from_json(r#"{"type":"GetInfo"}"#).unwrap();
+        assert_eq!(msg, ClientMessage::GetInfo);
+    }
+
+    #[test]
+    fn test_video_info_response() {
+        let msg = ServerMessage::VideoInfo {
+            path: "videos/test.mp4".to_string(),
+            info: VideoInfo {
+                container: "mov,mp4,m4a,3gp,3g2,mj2".to_string(),
+                codec: "hevc".to_string(),
+                profile: Some("Main".to_string()),
+                level: Some(93),
+                width: 1920,
+                height: 1080,
+                pixel_format: Some("yuv420p".to_string()),
+                frame_rate: Some(30.0),
+                duration: Some(5.0),
+                frame_count: 150,
+                keyframe_count: 5,
+                gop: Default::default(),
+            },
+        };
+        let json = msg.to_json();
+        assert!(json.contains(r#""type":"VideoInfo""#));
+        assert!(json.contains(r#""codec":"hevc""#));
+        assert!(json.contains(r#""frame_count":150"#));
+    }
+
     #[test]
     fn test_video_set_response() {
         let msg = ServerMessage::VideoSet {
@@ -127,10 +222,34 @@ mod tests {
             index: 0,
             offset: 1500,
             size: 45230,
+            timings: None,
         };
         let json = msg.to_json();
         assert!(json.contains(r#""type":"Frame""#));
         assert!(json.contains(r#""size":45230"#));
+        assert!(!json.contains("timings"));
+    }
+
+    #[test]
+    fn test_frame_timings() {
+        let timings = FrameTimings {
+            decode_us: 8200,
+            packets_decoded: 12,
+            encode_us: 2100,
+            ..FrameTimings::default()
+        };
+        let msg = ServerMessage::Frame {
+            index: 0,
+            offset: 1500,
+            size: 45230,
+            timings: Some(timings),
+        };
+        let json = msg.to_json();
+        assert!(json.contains(r#""decode_us":8200"#));
+        assert!(json.contains(r#""packets_decoded":12"#));
+
+        let parsed: ServerMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, msg);
     }
 
     #[test]
@@ -156,4 +275,39 @@ mod tests {
         let result = ClientMessage::from_json(r#"{"type":"Unknown"}"#);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_rate_limited_serialization() {
+        let msg = ServerMessage::RateLimited {
+            limit: RateLimit::FramesPerSecond,
+            retry_after_ms: 250,
+            index: Some(12),
+        };
+        assert_eq!(
+            msg.to_json(),
+            r#"{"type":"RateLimited","limit":"frames_per_second","retry_after_ms":250,"index":12}"#
+        );
+
+        let msg = ServerMessage::RateLimited {
+            limit: RateLimit::FramesPerRequest,
+            retry_after_ms: 0,
+            index: None,
+        };
+        assert!(!msg.to_json().contains("index"));
+    }
+
+    #[test]
+    fn test_flow_control_messages() {
+        let parsed = ClientMessage::from_json(r#"{"type":"Ack","index":7}"#).unwrap();
+        assert_eq!(parsed, ClientMessage::Ack { index: 7 });
+
+        let parsed = ClientMessage::from_json(r#"{"type":"Credit","window":4}"#).unwrap();
+        assert_eq!(parsed, ClientMessage::Credit { window: 4 });
+
+        let json = ServerMessage::QualityChanged { quality: 65 }.to_json();
+        assert_eq!(json, r#"{"type":"QualityChanged","quality":65}"#);
+
+        let json = ServerMessage::ServerShuttingDown { deadline_ms: 30000 }.to_json();
+        assert_eq!(json, r#"{"type":"ServerShuttingDown","deadline_ms":30000}"#);
+    }
 }
@@ -0,0 +1,117 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use super::router::AppState;
+use crate::config::{ConfigSource, ReloadSummary};
+
+/// Modification time and size; a change in either triggers a reload
+type Fingerprint = Option<(SystemTime, u64)>;
+
+/// Reload the config on SIGHUP, and when the `--config` file changes
+///
+/// The file is polled rather than watched so that replaced files and
+/// symlink swaps (as with Kubernetes ConfigMaps) are picked up too.
+pub async fn watch(state: AppState, source: ConfigSource) {
+    let config = state.config.current();
+    let file = config.config_file.clone();
+    let poll = Duration::from_secs(config.config_watch_interval_secs);
+
+    let mut hangup = Hangup::new();
+    let mut ticker = tokio::time::interval(poll.max(Duration::from_secs(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let watch_file = file.as_ref().filter(|_| !poll.is_zero());
+    let mut last = file.as_deref().map(fingerprint).unwrap_or_default();
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                info!("SIGHUP received; reloading configuration");
+            }
+            _ = ticker.tick(), if watch_file.is_some() => {
+                let path = watch_file.expect("checked by select guard");
+                let current = fingerprint(path);
+                if current == last {
+                    continue;
+                }
+                last = current;
+                info!("{} changed; reloading configuration", path.display());
+            }
+        }
+        reload(&state, &source);
+    }
+}
+
+/// Re-read the config and apply what can change without a restart
+pub fn reload(state: &AppState, source: &ConfigSource) {
+    let config = match source.load() {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("Configuration reload failed: {}", e);
+            return;
+        }
+    };
+
+    match state.reload_config(config) {
+        Ok(summary) => log_summary(&summary),
+        Err(e) => warn!(
+            "Configuration reload rejected; keeping current settings: {}",
+            e
+        ),
+    }
+}
+
+fn log_summary(summary: &ReloadSummary) {
+    if summary.is_empty() {
+        info!("Configuration reloaded; nothing changed");
+        return;
+    }
+    for change in &summary.applied {
+        info!("Configuration reloaded: {}", change);
+    }
+    if !summary.restart_required.is_empty() {
+        warn!(
+            "Changes to {} require a restart and were not applied",
+            summary.restart_required.join(", ")
+        );
+    }
+}
+
+fn fingerprint(path: &Path) -> Fingerprint {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// SIGHUP listener; never fires where signals are unsupported
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let signal = signal(SignalKind::hangup())
+                .map_err(|e| tracing::error!("Failed to listen for SIGHUP: {}", e))
+                .ok();
+            Self { signal }
+        }
+
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await
+    }
+}
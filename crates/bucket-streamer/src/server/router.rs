@@ -1,30 +1,158 @@
 use std::sync::Arc;
 
-use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
 use object_store::ObjectStore;
-use tower_http::trace::TraceLayer;
+use tower_http::{compression::CompressionLayer, trace::TraceLayer};
+use tracing::debug_span;
 
-use crate::config::Config;
+use super::auth::{redact_token, require_auth, Authenticator, WS_PATH};
+use super::limits::RateLimiter;
+use super::shutdown::Shutdown;
+use super::signed_url::{verify_signed_url, UrlSigner};
+use super::videos::OffsetsCache;
+use crate::config::{Config, ConfigError, ConfigHandle, ReloadSummary};
+use crate::pipeline::gop::GopReader;
+use crate::pipeline::prefetch::Prefetchers;
+use crate::storage::{cache, PathPolicy, StoreRegistry};
+use crate::telemetry;
 
 /// Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Arc<Config>,
+    /// Current config; reloadable fields may change while running
+    pub config: ConfigHandle,
     pub store: Arc<dyn ObjectStore>,
+    /// The default store plus those clients may address by URL
+    pub stores: Arc<StoreRegistry>,
+    pub auth: Arc<Authenticator>,
+    /// Signs and verifies pre-signed frame URLs, when a key is configured
+    pub url_signer: Option<Arc<UrlSigner>>,
+    /// Validates client-supplied object paths
+    pub policy: Arc<PathPolicy>,
+    /// Frame, byte and session limits
+    pub limiter: Arc<RateLimiter>,
+    /// Reads ahead of clients playing through a video
+    pub prefetch: Arc<Prefetchers>,
+    /// Fetches the GOP a frame needs rather than the whole video
+    pub gops: Arc<GopReader>,
+    /// Offsets indexes generated for videos without a sidecar
+    pub offsets: Arc<OffsetsCache>,
+    /// Set when the server starts draining
+    pub shutdown: Shutdown,
+}
+
+impl AppState {
+    /// Build state from config, loading auth keys
+    pub fn new(config: Config, store: Arc<dyn ObjectStore>) -> anyhow::Result<Self> {
+        let auth = Authenticator::from_config(&config)?;
+        if !auth.is_enabled() {
+            tracing::warn!("Authentication disabled; all paths are accessible");
+        }
+
+        let url_signer = config
+            .url_signing_key
+            .as_deref()
+            .map(|key| Arc::new(UrlSigner::new(key)));
+
+        let stores = StoreRegistry::from_config(&config, store.clone())?;
+        let policy = PathPolicy::from_config(&config);
+        let limiter = RateLimiter::from_config(&config);
+        let prefetch = Prefetchers::from_config(&config);
+
+        Ok(Self {
+            config: ConfigHandle::new(config),
+            store,
+            stores: Arc::new(stores),
+            auth: Arc::new(auth),
+            url_signer,
+            policy: Arc::new(policy),
+            limiter: Arc::new(limiter),
+            prefetch: Arc::new(prefetch),
+            gops: Arc::new(GopReader::new()),
+            offsets: Arc::new(OffsetsCache::new()),
+            shutdown: Shutdown::new(),
+        })
+    }
+
+    /// Validate `config` and apply its reloadable fields to running state
+    pub fn reload_config(&self, config: Config) -> Result<ReloadSummary, ConfigError> {
+        let previous = self.config.current();
+        let summary = self.config.reload(config)?;
+        if !summary.applied.is_empty() {
+            let current = self.config.current();
+            self.limiter.update(&current);
+            self.prefetch.update(&current);
+            if current.fetch_cache_bytes != previous.fetch_cache_bytes {
+                cache::resize_memory_caches(current.fetch_cache_bytes);
+            }
+        }
+        Ok(summary)
+    }
 }
 
 /// Create the Axum router with all routes
+///
+/// Everything except the `/health` probes and `/metrics` requires credentials
+/// when auth is enabled.
+/// Frame routes additionally accept a pre-signed URL instead.
 pub fn create_router(state: AppState) -> Router {
+    let auth = middleware::from_fn_with_state(state.clone(), require_auth);
+
+    let frames = Router::new()
+        .route("/frames/*path", get(super::frames::get_frame))
+        .route_layer(auth.clone())
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            verify_signed_url,
+        ));
+
+    let protected = Router::new()
+        .route(WS_PATH, get(super::websocket::ws_handler))
+        .route("/batch", post(super::batch::post_batch))
+        .route("/signed-urls", post(super::signed_url::post_signed_url))
+        .route(
+            "/videos",
+            get(super::videos::list_videos).layer(CompressionLayer::new()),
+        )
+        .route(
+            "/videos/*path",
+            get(super::videos::get_video_resource).layer(CompressionLayer::new()),
+        )
+        .route_layer(auth);
+
     Router::new()
-        .route("/health", get(health_check))
-        .route("/ws", get(super::websocket::ws_handler))
-        .layer(TraceLayer::new_for_http())
+        .route("/health", get(super::health::live))
+        .route("/health/live", get(super::health::live))
+        .route("/health/ready", get(super::health::ready))
+        .route("/metrics", get(metrics))
+        .merge(frames)
+        .merge(protected)
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|request: &Request<Body>| {
+                debug_span!(
+                    "request",
+                    method = %request.method(),
+                    uri = %redact_token(request.uri()),
+                    version = ?request.version(),
+                )
+            }),
+        )
         .with_state(state)
 }
 
-/// Health check endpoint
-async fn health_check() -> impl IntoResponse {
-    (StatusCode::OK, "ok")
+/// Prometheus scrape endpoint
+async fn metrics() -> Response {
+    match telemetry::render_metrics() {
+        Some(body) => ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response(),
+        None => (StatusCode::NOT_FOUND, "metrics disabled").into_response(),
+    }
 }
 
 #[cfg(test)]
@@ -38,11 +166,8 @@ mod tests {
     async fn test_health_check() {
         let config = Config::default();
         let store = crate::storage::create_store(&config).unwrap();
-        
-        let state = AppState {
-            config: Arc::new(config),
-            store,
-        };
+
+        let state = AppState::new(config, store).unwrap();
         let app = create_router(state);
 
         let response = app
@@ -52,4 +177,154 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            local_path: temp.path().to_str().unwrap().to_string(),
+            auth_api_keys: vec!["key".to_string()],
+            ..Config::default()
+        };
+        let store = crate::storage::create_store(&config).unwrap();
+        let state = AppState::new(config, store).unwrap();
+        let app = create_router(state.clone());
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/health/live").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/health/ready").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["components"]["store"]["ok"], true);
+        assert_eq!(body["components"]["workers"]["ok"], true);
+        let ready = body["status"] == "ready";
+        assert_eq!(status == StatusCode::OK, ready);
+        assert_eq!(
+            ready,
+            body["components"]["decoder"]["ok"] == true
+                && body["components"]["encoder"]["ok"] == true
+        );
+
+        state.shutdown.trigger();
+        let response = app
+            .oneshot(Request::get("/health/ready").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], "draining");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        telemetry::install_metrics();
+        telemetry::record_cache("http_etag", true);
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            local_path: temp.path().to_str().unwrap().to_string(),
+            auth_api_keys: vec!["key".to_string()],
+            ..Config::default()
+        };
+        let store = crate::storage::create_store(&config).unwrap();
+        let app = create_router(AppState::new(config, store).unwrap());
+
+        let response = app
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("bucket_streamer_cache_requests_total"));
+    }
+
+    #[tokio::test]
+    async fn test_auth_required() {
+        let temp = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(temp.path().join("team-a")).unwrap();
+        std::fs::write(temp.path().join("team-a/clip.h265"), b"data").unwrap();
+
+        let config = Config {
+            local_path: temp.path().to_str().unwrap().to_string(),
+            auth_api_keys: vec!["team-key=team-a".to_string()],
+            ..Config::default()
+        };
+        let store = crate::storage::create_store(&config).unwrap();
+        let app = create_router(AppState::new(config, store).unwrap());
+
+        let cases = [
+            ("/health", None, StatusCode::OK),
+            ("/health/live", None, StatusCode::OK),
+            ("/videos", None, StatusCode::UNAUTHORIZED),
+            ("/ws", Some("wrong-key"), StatusCode::UNAUTHORIZED),
+            ("/videos", Some("team-key"), StatusCode::OK),
+            (
+                "/videos/team-b/clip.h265/info",
+                Some("team-key"),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                "/frames/team-a/../x.h265?offset=0",
+                Some("team-key"),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "/frames/team-a/missing.h265?offset=0",
+                Some("team-key"),
+                StatusCode::NOT_FOUND,
+            ),
+        ];
+        for (uri, key, expected) in cases {
+            let mut request = Request::get(uri);
+            if let Some(key) = key {
+                request = request.header("authorization", format!("Bearer {}", key));
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{}", uri);
+        }
+
+        // Query tokens are only read on the WebSocket upgrade
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/videos?access_token=team-key")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app
+            .oneshot(
+                Request::get("/ws?access_token=team-key")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_ne!(response.status(), StatusCode::UNAUTHORIZED);
+    }
 }
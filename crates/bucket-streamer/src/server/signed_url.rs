@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    middleware::Next,
+    response::Response,
+    Extension, Json,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::auth::Principal;
+use super::error::ApiError;
+use super::router::AppState;
+use crate::pipeline::frame::ImageFormat;
+
+/// Query parameter carrying the signature
+pub const SIGNATURE_PARAM: &str = "sig";
+
+/// Query parameter carrying the expiry (Unix seconds)
+pub const EXPIRY_PARAM: &str = "exp";
+
+/// Subject of principals created from a verified signed URL
+pub const SIGNED_URL_SUBJECT: &str = "signed-url";
+
+/// Lifetime of a signed URL when the request does not give one (seconds)
+const DEFAULT_TTL: u64 = 3600;
+
+/// Characters escaped in a query key or value: all but RFC 3986 unreserved
+const QUERY_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Characters escaped in a path segment (`/` is kept as the separator)
+const PATH_SEGMENT: &AsciiSet = &QUERY_COMPONENT.remove(b'/');
+
+/// Signs and verifies frame URLs with HMAC-SHA256
+///
+/// The signature covers the video path and every query parameter except
+/// `sig`, so neither the frame nor the encode settings can be changed.
+pub struct UrlSigner {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+}
+
+impl UrlSigner {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+        }
+    }
+
+    /// Signature for `path` with the given query parameters
+    pub fn sign(&self, path: &str, params: &BTreeMap<String, String>) -> String {
+        jsonwebtoken::crypto::sign(
+            canonical(path, params).as_bytes(),
+            &self.encoding_key,
+            Algorithm::HS256,
+        )
+        .expect("HMAC signing should not fail")
+    }
+
+    /// Check `signature` in constant time
+    pub fn verify(&self, path: &str, params: &BTreeMap<String, String>, signature: &str) -> bool {
+        jsonwebtoken::crypto::verify(
+            signature,
+            canonical(path, params).as_bytes(),
+            &self.decoding_key,
+            Algorithm::HS256,
+        )
+        .unwrap_or(false)
+    }
+}
+
+/// Message that is signed: the path, then sorted `key=value` pairs
+///
+/// Everything is percent-encoded, so a `&` or `=` inside a value cannot
+/// make two different sets of parameters sign the same.
+fn canonical(path: &str, params: &BTreeMap<String, String>) -> String {
+    let signed = params
+        .iter()
+        .filter(|(key, _)| key.as_str() != SIGNATURE_PARAM);
+    format!(
+        "{}\n{}",
+        utf8_percent_encode(path.trim_start_matches('/'), PATH_SEGMENT),
+        encode_query(signed)
+    )
+}
+
+/// `key=value` pairs joined with `&`, each key and value percent-encoded
+fn encode_query<'a>(params: impl Iterator<Item = (&'a String, &'a String)>) -> String {
+    let pairs: Vec<String> = params
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, QUERY_COMPONENT),
+                utf8_percent_encode(value, QUERY_COMPONENT)
+            )
+        })
+        .collect();
+    pairs.join("&")
+}
+
+/// Current time in Unix seconds
+pub(super) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Body of `POST /signed-urls`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SignRequest {
+    /// Video path in the object store
+    pub path: String,
+    pub offset: u64,
+    pub irap_offset: Option<u64>,
+    pub format: Option<ImageFormat>,
+    pub width: Option<u32>,
+    /// Lifetime in seconds (default 3600, capped by `SIGNED_URL_MAX_TTL`)
+    pub ttl: Option<u64>,
+}
+
+/// Response of `POST /signed-urls`
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedUrl {
+    /// Path and query, relative to the server root
+    pub url: String,
+    /// Expiry as a Unix timestamp (seconds)
+    pub expires_at: u64,
+}
+
+/// Mint a time-limited URL for one frame
+///
+/// The caller must be allowed to read the video; whoever holds the URL can
+/// then fetch that exact frame without credentials until it expires.
+pub async fn post_signed_url(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<SignRequest>,
+) -> Result<Json<SignedUrl>, ApiError> {
+    let signer = state
+        .url_signer
+        .as_ref()
+        .ok_or_else(|| ApiError::BadRequest("URL signing is not configured".into()))?;
+    let path = state.policy.video_path(&request.path)?;
+    principal.authorize(&path)?;
+
+    let ttl = request.ttl.unwrap_or(DEFAULT_TTL);
+    let max_ttl = state.config.current().signed_url_max_ttl;
+    if ttl == 0 || ttl > max_ttl {
+        return Err(ApiError::BadRequest(format!(
+            "ttl must be between 1 and {}",
+            max_ttl
+        )));
+    }
+    let expires_at = now_secs()
+        .checked_add(ttl)
+        .ok_or_else(|| ApiError::BadRequest("ttl is too large".into()))?;
+
+    let mut params = BTreeMap::new();
+    params.insert("offset".to_string(), request.offset.to_string());
+    if let Some(irap_offset) = request.irap_offset {
+        params.insert("irap_offset".to_string(), irap_offset.to_string());
+    }
+    if let Some(format) = request.format {
+        params.insert("format".to_string(), format.extension().to_string());
+    }
+    if let Some(width) = request.width {
+        params.insert("width".to_string(), width.to_string());
+    }
+    params.insert(EXPIRY_PARAM.to_string(), expires_at.to_string());
+
+    let signature = signer.sign(&path, &params);
+    params.insert(SIGNATURE_PARAM.to_string(), signature);
+
+    let url = format!(
+        "/frames/{}?{}",
+        utf8_percent_encode(&path, PATH_SEGMENT),
+        encode_query(params.iter())
+    );
+
+    Ok(Json(SignedUrl { url, expires_at }))
+}
+
+/// Layer in front of the frame routes accepting signed URLs in place of
+/// credentials
+///
+/// A valid, unexpired signature attaches a [`Principal`] scoped to the one
+/// video, which the auth middleware then accepts as is. Requests without a
+/// `sig` parameter pass through untouched.
+pub async fn verify_signed_url(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    Query(params): Query<BTreeMap<String, String>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let (Some(signer), Some(signature)) = (&state.url_signer, params.get(SIGNATURE_PARAM)) else {
+        return Ok(next.run(request).await);
+    };
+
+    if !signer.verify(&path, &params, signature) {
+        return Err(ApiError::Forbidden("Invalid URL signature".into()));
+    }
+    let expires_at: u64 = params
+        .get(EXPIRY_PARAM)
+        .and_then(|exp| exp.parse().ok())
+        .ok_or_else(|| ApiError::Forbidden("Invalid URL signature".into()))?;
+    if expires_at <= now_secs() {
+        return Err(ApiError::Forbidden("Signed URL expired".into()));
+    }
+
+    debug!("Signed URL for {} valid until {}", path, expires_at);
+    request.extensions_mut().insert(Principal {
+        subject: SIGNED_URL_SUBJECT.to_string(),
+        scopes: vec![path],
+        expires_at: Some(expires_at),
+    });
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::server::create_router;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    fn params(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = UrlSigner::new("key");
+        let query = params(&[("offset", "48"), ("exp", "1900000000")]);
+        let sig = signer.sign("clips/a.h265", &query);
+
+        assert!(signer.verify("clips/a.h265", &query, &sig));
+        assert!(signer.verify("/clips/a.h265", &query, &sig));
+        assert!(!signer.verify("clips/b.h265", &query, &sig));
+        assert!(!signer.verify(
+            "clips/a.h265",
+            &params(&[("offset", "49"), ("exp", "1900000000")]),
+            &sig
+        ));
+        assert!(!UrlSigner::new("other").verify("clips/a.h265", &query, &sig));
+    }
+
+    #[test]
+    fn test_canonical_is_unambiguous() {
+        let joined = params(&[("exp", "1900000000&offset=48")]);
+        let split = params(&[("exp", "1900000000"), ("offset", "48")]);
+        assert_ne!(canonical("a.h265", &joined), canonical("a.h265", &split));
+
+        let newline = canonical("a.h265\nexp=1", &params(&[]));
+        assert_ne!(newline, canonical("a.h265", &params(&[("exp", "1")])));
+    }
+
+    #[tokio::test]
+    async fn test_signed_url_bypasses_auth() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            local_path: temp.path().to_str().unwrap().to_string(),
+            auth_api_keys: vec!["key".to_string()],
+            url_signing_key: Some("signing-secret".to_string()),
+            signed_url_max_ttl: u64::MAX,
+            ..Config::default()
+        };
+        let store = crate::storage::create_store(&config).unwrap();
+        let app = create_router(AppState::new(config, store).unwrap());
+
+        // An expiry past the end of time is refused rather than wrapped
+        let body = format!(r#"{{"path": "a.h265", "offset": 48, "ttl": {}}}"#, u64::MAX);
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/signed-urls")
+                    .header("authorization", "Bearer key")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/signed-urls")
+                    .header("authorization", "Bearer key")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"path": "clips/a b.h265", "offset": 48}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let signed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let url = signed["url"].as_str().unwrap().to_string();
+        assert!(url.starts_with("/frames/clips/a%20b.h265?exp="));
+
+        let expired = {
+            let signer = UrlSigner::new("signing-secret");
+            let query = params(&[("offset", "48"), ("exp", "1000")]);
+            let sig = signer.sign("clips/a b.h265", &query);
+            format!("/frames/clips/a%20b.h265?exp=1000&offset=48&sig={}", sig)
+        };
+
+        // Signed URLs reach the handler (404: the video does not exist)
+        let cases = [
+            (url.clone(), StatusCode::NOT_FOUND),
+            (url.replace("offset=48", "offset=49"), StatusCode::FORBIDDEN),
+            (expired, StatusCode::FORBIDDEN),
+            (
+                "/frames/clips/a%20b.h265?offset=48".to_string(),
+                StatusCode::UNAUTHORIZED,
+            ),
+        ];
+        for (uri, expected) in cases {
+            let response = app
+                .clone()
+                .oneshot(Request::get(&uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{}", uri);
+        }
+    }
+}
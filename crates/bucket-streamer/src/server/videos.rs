@@ -0,0 +1,528 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use bytes::Bytes;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use object_store::ObjectMeta;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::auth::Principal;
+use super::error::ApiError;
+use super::router::AppState;
+use crate::pipeline::decoder::{Decoder, FrameOffset, VideoInfo};
+use crate::pipeline::fetcher;
+use crate::storage::cache::Version;
+use crate::telemetry;
+
+/// Suffix `repo-cli convert --extract-offsets` appends to the video path
+pub const OFFSETS_SUFFIX: &str = ".offsets.json";
+
+/// Offsets sidecar document, as written by `repo-cli convert --extract-offsets`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OffsetsIndex {
+    /// Storage URL (`s3://`, `gs://`, `az://` or `fs://`) for the video file
+    pub video_url: String,
+    /// All frames with their IRAP offsets
+    pub frames: Vec<FrameOffset>,
+}
+
+/// Default page size for `GET /videos`
+const DEFAULT_LIST_LIMIT: usize = 100;
+
+/// Maximum page size for `GET /videos`
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// Offsets sidecars looked up at a time when listing with `offsets=true`
+const SIDECAR_LOOKUPS: usize = 16;
+
+/// Generated offsets indexes kept before the least recently used is dropped
+const MAX_CACHED_OFFSETS: usize = 64;
+
+/// Offsets indexes generated for videos without a sidecar
+///
+/// Each is kept per path until a `HEAD` reports another version of the
+/// video, so without `offsets_write_back` the packet scan runs once per
+/// version and process rather than on every request.
+#[derive(Debug, Default)]
+pub struct OffsetsCache {
+    indexes: Mutex<HashMap<String, CachedOffsets>>,
+}
+
+#[derive(Debug)]
+struct CachedOffsets {
+    version: Version,
+    json: Bytes,
+    used: Instant,
+}
+
+impl OffsetsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index generated for the version of `path` described by `meta`
+    fn get(&self, path: &str, meta: &ObjectMeta) -> Option<Bytes> {
+        let mut indexes = self.indexes.lock().unwrap();
+        let cached = indexes.get_mut(path)?;
+        if cached.version != Version::of(meta) {
+            return None;
+        }
+        cached.used = Instant::now();
+        Some(cached.json.clone())
+    }
+
+    fn insert(&self, path: &str, meta: &ObjectMeta, json: Bytes) {
+        let mut indexes = self.indexes.lock().unwrap();
+        if indexes.len() >= MAX_CACHED_OFFSETS && !indexes.contains_key(path) {
+            let oldest = indexes
+                .iter()
+                .min_by_key(|(_, cached)| cached.used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                indexes.remove(&oldest);
+            }
+        }
+        let cached = CachedOffsets {
+            version: Version::of(meta),
+            json,
+            used: Instant::now(),
+        };
+        indexes.insert(path.to_string(), cached);
+    }
+}
+
+/// Query parameters for `GET /videos`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ListQuery {
+    /// Directory-style prefix to list under
+    pub prefix: Option<String>,
+    /// Comma-separated extensions to keep, e.g. `h265,mp4`
+    pub ext: Option<String>,
+    /// Page size (default 100, max 1000)
+    pub limit: Option<usize>,
+    /// Resume after this path (the previous page's `next_cursor`)
+    pub cursor: Option<String>,
+    /// Include the path of each video's offsets sidecar, if present
+    #[serde(default)]
+    pub offsets: bool,
+    /// Include size and last-modified time
+    #[serde(default)]
+    pub metadata: bool,
+}
+
+/// One page of `GET /videos`
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoList {
+    pub videos: Vec<VideoEntry>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// A video object in the store
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoEntry {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// RFC 3339 timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// Path of the offsets sidecar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offsets: Option<String>,
+}
+
+/// List videos in the object store, sorted by path
+///
+/// Offsets sidecars are never listed as videos themselves, and videos
+/// outside the caller's scopes are skipped. Listing starts after `cursor`
+/// and, on backends that list keys in order, stops once the page and one
+/// more video are found; other backends read the rest of the listing but
+/// keep only that many entries.
+pub async fn list_videos(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<VideoList>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if limit == 0 || limit > MAX_LIST_LIMIT {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_LIST_LIMIT
+        )));
+    }
+
+    let extensions: Option<Vec<String>> = query.ext.as_ref().map(|ext| {
+        ext.split(',')
+            .map(|e| e.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect()
+    });
+
+    let prefix = match &query.prefix {
+        Some(prefix) => Some(state.policy.list_prefix(prefix)?),
+        None => None,
+    };
+
+    let is_video = |meta: &ObjectMeta| {
+        let path = meta.location.as_ref();
+        !path.ends_with(OFFSETS_SUFFIX)
+            && state.policy.allows(path)
+            && principal.allows(path)
+            && match &extensions {
+                Some(extensions) => meta
+                    .location
+                    .extension()
+                    .is_some_and(|ext| extensions.contains(&ext.to_ascii_lowercase())),
+                None => true,
+            }
+    };
+    let ordered = state.config.current().storage_backend.lists_in_order();
+    let mut objects = fetcher::list_page(
+        &state.store,
+        prefix.as_deref(),
+        query.cursor.as_deref(),
+        ordered,
+        limit + 1,
+        is_video,
+    )
+    .await?;
+    let more = objects.len() > limit;
+    objects.truncate(limit);
+
+    let lookups: Vec<String> = objects
+        .iter()
+        .filter(|_| query.offsets)
+        .map(|meta| format!("{}{}", meta.location, OFFSETS_SUFFIX))
+        .collect();
+    let store = state.store.clone();
+    let mut sidecars: Vec<Option<String>> = stream::iter(lookups)
+        .map(move |sidecar| {
+            let store = store.clone();
+            async move {
+                let found = fetcher::video_meta(&store, &sidecar).await?;
+                Ok::<_, anyhow::Error>(found.map(|_| sidecar))
+            }
+        })
+        .buffered(SIDECAR_LOOKUPS)
+        .try_collect()
+        .await?;
+    sidecars.resize(objects.len(), None);
+
+    let videos: Vec<VideoEntry> = objects
+        .iter()
+        .zip(sidecars)
+        .map(|(meta, offsets)| VideoEntry {
+            path: meta.location.to_string(),
+            size: query.metadata.then_some(meta.size as u64),
+            last_modified: query.metadata.then(|| meta.last_modified.to_rfc3339()),
+            offsets,
+        })
+        .collect();
+
+    let next_cursor = videos.last().filter(|_| more).map(|v| v.path.clone());
+
+    Ok(Json(VideoList {
+        videos,
+        next_cursor,
+    }))
+}
+
+/// Dispatch `GET /videos/{path}/{resource}`
+///
+/// Video paths contain slashes, so the route captures everything after
+/// `/videos/` and the resource is taken from the final segment.
+pub async fn get_video_resource(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(path): Path<String>,
+) -> Result<Response, ApiError> {
+    if let Some(video) = path.strip_suffix("/info") {
+        let video = state.policy.video_path(video)?;
+        principal.authorize(&video)?;
+        return Ok(Json(video_info(&state, &video).await?).into_response());
+    }
+
+    if let Some(video) = path.strip_suffix("/offsets") {
+        let video = state.policy.video_path(video)?;
+        principal.authorize(&video)?;
+        let json = video_offsets(&state, &video).await?;
+        return Ok(([(header::CONTENT_TYPE, "application/json")], json).into_response());
+    }
+
+    Err(ApiError::NotFound(format!(
+        "Unknown video resource: {}",
+        path
+    )))
+}
+
+/// Probe container and stream metadata for a video
+async fn video_info(state: &AppState, path: &str) -> Result<VideoInfo, ApiError> {
+    let meta = fetcher::video_meta(&state.store, path)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Video not found: {}", path)))?;
+    let data = state.gops.header(&state.store, path, &meta).await?;
+
+    let info = tokio::task::spawn_blocking(move || Decoder::probe(data))
+        .await
+        .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+        .map_err(anyhow::Error::from)?;
+
+    Ok(info)
+}
+
+/// Load the offsets sidecar stored next to a video
+///
+/// When no sidecar exists the index is generated by scanning packets and
+/// kept in the [`OffsetsCache`]. With `offsets_write_back` it is also
+/// written back to the store, so later requests and other replicas are
+/// served directly.
+async fn video_offsets(state: &AppState, path: &str) -> Result<Bytes, ApiError> {
+    let sidecar = format!("{}{}", path, OFFSETS_SUFFIX);
+    let cached = fetcher::fetch_object(&state.store, &sidecar).await?;
+    telemetry::record_cache("offsets_sidecar", cached.is_some());
+    if let Some(json) = cached {
+        return Ok(json);
+    }
+
+    let meta = fetcher::video_meta(&state.store, path)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Video not found: {}", path)))?;
+    let generated = state.offsets.get(path, &meta);
+    telemetry::record_cache("offsets_index", generated.is_some());
+    if let Some(json) = generated {
+        return Ok(json);
+    }
+    let data = state.gops.header(&state.store, path, &meta).await?;
+
+    info!("Generating offsets index for {}", path);
+    let frames = tokio::task::spawn_blocking(move || Decoder::index_offsets(data))
+        .await
+        .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+        .map_err(anyhow::Error::from)?;
+
+    let config = state.config.current();
+    let index = OffsetsIndex {
+        video_url: crate::storage::object_url(&config, path),
+        frames,
+    };
+    let json = Bytes::from(serde_json::to_vec_pretty(&index).map_err(anyhow::Error::from)?);
+    state.offsets.insert(path, &meta, json.clone());
+
+    if !config.offsets_write_back {
+        return Ok(json);
+    }
+    // A read-only store should not fail the request
+    if let Err(e) = fetcher::store_object(&state.store, &sidecar, json.clone()).await {
+        warn!("Failed to cache offsets index {}: {:#}", sidecar, e);
+    }
+
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::server::create_router;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    fn test_app() -> (axum::Router, TempDir) {
+        let temp = TempDir::new().unwrap();
+        let config = Config {
+            local_path: temp.path().to_str().unwrap().to_string(),
+            ..Config::default()
+        };
+        let store = crate::storage::create_store(&config).unwrap();
+        let state = AppState::new(config, store).unwrap();
+        (create_router(state), temp)
+    }
+
+    async fn get_json(app: axum::Router, uri: &str) -> serde_json::Value {
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn write_files(temp: &TempDir, files: &[&str]) {
+        for file in files {
+            let path = temp.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"data").unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_videos() {
+        let (app, temp) = test_app();
+        write_files(
+            &temp,
+            &[
+                "a.h265",
+                "a.h265.offsets.json",
+                "b.MP4",
+                "notes.txt",
+                "clips/c.h265",
+            ],
+        );
+
+        let json = get_json(app.clone(), "/videos?ext=h265,mp4&offsets=true").await;
+        let videos = json["videos"].as_array().unwrap();
+        let paths: Vec<&str> = videos.iter().map(|v| v["path"].as_str().unwrap()).collect();
+        assert_eq!(paths, ["a.h265", "b.MP4", "clips/c.h265"]);
+        assert_eq!(videos[0]["offsets"], "a.h265.offsets.json");
+        assert!(videos[1].get("offsets").is_none());
+        assert!(videos[0].get("size").is_none());
+        assert!(json.get("next_cursor").is_none());
+
+        let json = get_json(app, "/videos?prefix=clips&metadata=true").await;
+        assert_eq!(json["videos"][0]["path"], "clips/c.h265");
+        assert_eq!(json["videos"][0]["size"], 4);
+        assert!(json["videos"][0]["last_modified"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_list_videos_pagination() {
+        let (app, temp) = test_app();
+        write_files(&temp, &["1.h265", "2.h265", "3.h265"]);
+
+        let json = get_json(app.clone(), "/videos?limit=2").await;
+        assert_eq!(json["videos"].as_array().unwrap().len(), 2);
+        assert_eq!(json["next_cursor"], "2.h265");
+
+        let json = get_json(app, "/videos?limit=2&cursor=2.h265").await;
+        assert_eq!(json["videos"][0]["path"], "3.h265");
+        assert!(json.get("next_cursor").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_offsets_sidecar_gzip() {
+        let (app, temp) = test_app();
+        write_files(&temp, &["clips/a.h265"]);
+        let sidecar = serde_json::json!({
+            "video_url": "fs:///data/clips/a.h265",
+            "frames": [
+                { "offset": 48, "irap_offset": 48 },
+                { "offset": 12591, "irap_offset": 48 },
+            ],
+        });
+        std::fs::write(
+            temp.path().join("clips/a.h265.offsets.json"),
+            sidecar.to_string(),
+        )
+        .unwrap();
+
+        let json = get_json(app.clone(), "/videos/clips/a.h265/offsets").await;
+        assert_eq!(json, sidecar);
+
+        let response = app
+            .oneshot(
+                Request::get("/videos/clips/a.h265/offsets")
+                    .header(header::ACCEPT_ENCODING, "gzip")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    }
+
+    #[test]
+    fn test_offsets_cache() {
+        let meta = |path: &str, e_tag: &str| ObjectMeta {
+            location: path.into(),
+            last_modified: Default::default(),
+            size: 4,
+            e_tag: Some(e_tag.to_string()),
+            version: None,
+        };
+        let cache = OffsetsCache::new();
+        assert_eq!(cache.get("a.h265", &meta("a.h265", "1")), None);
+
+        cache.insert("a.h265", &meta("a.h265", "1"), Bytes::from_static(b"{}"));
+        assert_eq!(
+            cache.get("a.h265", &meta("a.h265", "1")),
+            Some(Bytes::from_static(b"{}"))
+        );
+        // Another version of the video is indexed again
+        assert_eq!(cache.get("a.h265", &meta("a.h265", "2")), None);
+
+        // The least recently used index makes room
+        for i in 1..MAX_CACHED_OFFSETS {
+            let path = format!("{}.h265", i);
+            cache.insert(&path, &meta(&path, "1"), Bytes::new());
+        }
+        assert!(cache.get("a.h265", &meta("a.h265", "1")).is_some());
+        cache.insert("new.h265", &meta("new.h265", "1"), Bytes::new());
+        assert!(cache.get("a.h265", &meta("a.h265", "1")).is_some());
+        assert!(cache.get("1.h265", &meta("1.h265", "1")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_offsets_missing_video() {
+        let (app, _temp) = test_app();
+
+        let response = app
+            .oneshot(
+                Request::get("/videos/missing.h265/offsets")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_info_missing_video() {
+        let (app, _temp) = test_app();
+
+        let response = app
+            .oneshot(
+                Request::get("/videos/clips/missing.h265/info")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_resource() {
+        let (app, _temp) = test_app();
+
+        let response = app
+            .oneshot(
+                Request::get("/videos/clips/a.h265/thumbnail")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,192 +1,627 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        ConnectInfo, Query, State,
     },
-    response::IntoResponse,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
 };
-use bytes::Bytes;
+use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
-use tracing::{debug, error, info, warn};
+use object_store::{ObjectMeta, ObjectStore};
+use serde::Deserialize;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
 
-use super::protocol::{ClientMessage, FrameRequest, ServerMessage};
+use super::auth::Principal;
+use super::error::client_message;
+use super::flow::{CreditWindow, QualityController};
+use super::limits::{client_key, RateLimited, SessionPermit};
+use super::protocol::{ClientMessage, FrameRequest, FrameTimings, ServerMessage};
 use super::router::AppState;
-use crate::pipeline::{decoder::Decoder, encoder::JpegEncoder, fetcher};
+use crate::pipeline::avio::VideoData;
+use crate::pipeline::decoder::Decoder;
+use crate::pipeline::fetcher;
+use crate::pipeline::frame::{process_frame_timed, EncodeOptions};
+use crate::pipeline::gop::{Gop, VideoLayout};
+use crate::pipeline::prefetch::Prefetcher;
+use crate::telemetry::{self, QueueSlot, SESSIONS_ACTIVE};
+
+/// Queue label for pending `RequestFrames` messages
+const REQUEST_QUEUE: &str = "ws_requests";
+
+/// `RequestFrames` messages queued per session before new ones are refused
+const MAX_QUEUED_REQUESTS: usize = 8;
+
+/// How long a disconnect notice may take to reach a slow client
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Write half of the socket, shared by the reader and the frame worker
+type SharedSender = Arc<Mutex<SplitSink<WebSocket, Message>>>;
+
+/// Per-session options given as query parameters on `/ws`
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct SessionParams {
+    /// Include a server-side timing breakdown in every `Frame` message
+    #[serde(default)]
+    pub debug_timings: bool,
+}
+
+/// The video opened by `SetVideo`
+struct OpenVideo {
+    store: Arc<dyn ObjectStore>,
+    key: String,
+    /// Path as the client named it
+    path: String,
+    meta: ObjectMeta,
+    /// Enough of the file to probe it
+    header: VideoData,
+    /// The whole file, for videos whose GOPs cannot be read on their own
+    whole: Option<Gop>,
+}
+
+/// Frames queued by one `RequestFrames` message
+struct FrameJob {
+    video: Arc<OpenVideo>,
+    frames: Vec<FrameRequest>,
+    /// When the request was read from the socket
+    received: Instant,
+    /// Counted in the queue depth gauge until the worker picks it up
+    queued: QueueSlot,
+}
 
 /// WebSocket upgrade handler
-pub async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_session(socket, state))
+///
+/// Credentials are checked by the auth middleware before the upgrade; the
+/// resulting principal limits which videos the session may open.
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Query(params): Query<SessionParams>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+) -> Response {
+    if state.shutdown.is_triggered() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down").into_response();
+    }
+
+    let client = client_key(&principal, connect_info.map(|ConnectInfo(addr)| addr));
+    let permit = state.limiter.open_session();
+
+    ws.on_upgrade(move |socket| async move {
+        telemetry::record_session(permit.is_ok());
+        match permit {
+            Ok(permit) => handle_session(socket, state, principal, client, params, permit).await,
+            Err(limited) => reject_session(socket, limited).await,
+        }
+    })
+    .into_response()
+}
+
+/// Tell a client over the session limit when to retry, then close
+async fn reject_session(mut socket: WebSocket, limited: RateLimited) {
+    warn!("Rejecting WebSocket session: {}", limited);
+    let response = ServerMessage::RateLimited {
+        limit: limited.limit,
+        retry_after_ms: limited.retry_after.as_millis() as u64,
+        index: None,
+    };
+    let _ = socket.send(Message::Text(response.to_json())).await;
+    let _ = socket.send(Message::Close(None)).await;
 }
 
 /// Handle a WebSocket session
-async fn handle_session(socket: WebSocket, state: AppState) {
-    let (mut sender, mut receiver) = socket.split();
+///
+/// Incoming messages are handled on this task while a separate worker
+/// decodes and sends frames, so `Ack`/`Credit` messages are read even
+/// while the client is slow to drain frames.
+async fn handle_session(
+    socket: WebSocket,
+    state: AppState,
+    principal: Principal,
+    client: String,
+    params: SessionParams,
+    _permit: SessionPermit,
+) {
+    let (sender, receiver) = socket.split();
+    let sender: SharedSender = Arc::new(Mutex::new(sender));
+    metrics::gauge!(SESSIONS_ACTIVE).increment(1.0);
 
-    info!("WebSocket client connected");
+    // Parent of all frame spans; `video.path` is filled in by SetVideo
+    let span = info_span!(
+        "ws_session",
+        client = %client,
+        subject = %principal.subject,
+        video.path = field::Empty,
+    );
 
-    // Session state
-    let mut video_path: Option<String> = None;
-    let mut video_data: Option<Bytes> = None;
+    info!(parent: &span,
+        "WebSocket client connected: {} ({} active)",
+        principal.subject,
+        state.limiter.active_sessions()
+    );
 
-    while let Some(msg_result) = receiver.next().await {
-        let msg = match msg_result {
-            Ok(m) => m,
-            Err(e) => {
-                warn!("WebSocket error: {}", e);
-                break;
-            }
-        };
+    let credits = Arc::new(CreditWindow::new(
+        state.config.current().default_credit_window,
+    ));
+    let (jobs_tx, jobs_rx) = mpsc::channel(MAX_QUEUED_REQUESTS);
+    let mut worker = tokio::spawn(
+        frame_worker(
+            jobs_rx,
+            state.clone(),
+            client,
+            params,
+            sender.clone(),
+            credits.clone(),
+        )
+        .instrument(span.clone()),
+    );
 
-        match msg {
-            Message::Text(text) => {
-                debug!("Received: {}", text);
-
-                match ClientMessage::from_json(&text) {
-                    Ok(client_msg) => {
-                        match handle_message(
-                            client_msg,
-                            &mut video_path,
-                            &mut video_data,
-                            &state,
-                            &mut sender,
-                        )
-                        .await
-                        {
-                            Ok(()) => {}
+    let shutdown = state.shutdown.clone();
+    let expires_at = principal.expires_at;
+    let mut connection = Connection {
+        state,
+        principal,
+        sender: sender.clone(),
+        jobs: Some(jobs_tx),
+        credits,
+        video: None,
+    };
+
+    tokio::select! {
+        _ = connection.receive(receiver).instrument(span) => {}
+        result = &mut worker => match result {
+            Ok(Err(e)) => warn!("Closing session: {:#}", e),
+            Err(e) => error!("Frame worker failed: {}", e),
+            Ok(Ok(())) => {}
+        },
+        _ = credential_expiry(expires_at) => {
+            info!("Closing session: credentials expired");
+            let notice = ServerMessage::Error {
+                message: "Token expired".to_string(),
+            };
+            let _ = send_json(&sender, &notice).await;
+            let _ = sender.lock().await.send(Message::Close(None)).await;
+        }
+    }
+    worker.abort();
+
+    if shutdown.is_triggered() {
+        let _ = sender.lock().await.send(Message::Close(None)).await;
+    }
+
+    metrics::gauge!(SESSIONS_ACTIVE).decrement(1.0);
+    info!("WebSocket client disconnected");
+}
+
+/// Resolves once a credential expiring at `expires_at` (Unix seconds) is no
+/// longer valid; never for credentials without an expiry
+async fn credential_expiry(expires_at: Option<u64>) {
+    let Some(expires_at) = expires_at else {
+        return std::future::pending().await;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    tokio::time::sleep(Duration::from_secs(expires_at.saturating_sub(now))).await;
+}
+
+/// Per-connection state owned by the reader side of a session
+struct Connection {
+    state: AppState,
+    principal: Principal,
+    sender: SharedSender,
+    /// Dropped on shutdown so the worker exits once its queue is empty
+    jobs: Option<mpsc::Sender<FrameJob>>,
+    credits: Arc<CreditWindow>,
+    video: Option<Arc<OpenVideo>>,
+}
+
+impl Connection {
+    /// Read messages until the client disconnects
+    ///
+    /// Reading continues while draining on shutdown so `Ack`s still arrive.
+    async fn receive(&mut self, mut receiver: SplitStream<WebSocket>) {
+        let shutdown = self.state.shutdown.clone();
+
+        loop {
+            let msg_result = tokio::select! {
+                _ = shutdown.triggered(), if self.jobs.is_some() => {
+                    if self.begin_drain().await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                msg_result = receiver.next() => match msg_result {
+                    Some(msg_result) => msg_result,
+                    None => break,
+                },
+            };
+
+            let msg = match msg_result {
+                Ok(m) => m,
+                Err(e) => {
+                    warn!("WebSocket error: {}", e);
+                    break;
+                }
+            };
+
+            match msg {
+                Message::Text(text) => {
+                    debug!("Received: {}", text);
+
+                    let error_msg = match ClientMessage::from_json(&text) {
+                        Ok(client_msg) => match self.handle_message(client_msg).await {
+                            Ok(()) => None,
                             Err(e) => {
-                                let error_msg = ServerMessage::Error {
-                                    message: e.to_string(),
-                                };
-                                if sender
-                                    .send(Message::Text(error_msg.to_json().into()))
-                                    .await
-                                    .is_err()
-                                {
-                                    break;
-                                }
+                                warn!("Request failed: {:#}", e);
+                                Some(ServerMessage::Error {
+                                    message: client_message(&e),
+                                })
                             }
-                        }
-                    }
-                    Err(e) => {
-                        let error_msg = ServerMessage::Error {
+                        },
+                        Err(e) => Some(ServerMessage::Error {
                             message: format!("Invalid message: {}", e),
-                        };
-                        if sender
-                            .send(Message::Text(error_msg.to_json().into()))
-                            .await
-                            .is_err()
-                        {
+                        }),
+                    };
+
+                    if let Some(error_msg) = error_msg {
+                        if send_json(&self.sender, &error_msg).await.is_err() {
                             break;
                         }
                     }
                 }
-            }
-            Message::Ping(data) => {
-                if sender.send(Message::Pong(data)).await.is_err() {
+                Message::Ping(data) => {
+                    if self
+                        .sender
+                        .lock()
+                        .await
+                        .send(Message::Pong(data))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Message::Pong(_) => {}
+                Message::Close(_) => {
+                    info!("Client closed connection");
                     break;
                 }
+                _ => {}
             }
-            Message::Pong(_) => {}
-            Message::Close(_) => {
-                info!("Client closed connection");
-                break;
-            }
-            _ => {}
         }
     }
 
-    info!("WebSocket client disconnected");
-}
+    /// Tell the client the server is stopping and refuse further work
+    async fn begin_drain(&mut self) -> Result<(), axum::Error> {
+        info!("Server shutting down; draining session");
+        self.jobs = None;
 
-async fn handle_message(
-    msg: ClientMessage,
-    video_path: &mut Option<String>,
-    video_data: &mut Option<Bytes>,
-    state: &AppState,
-    sender: &mut futures_util::stream::SplitSink<WebSocket, Message>,
-) -> anyhow::Result<()> {
-    match msg {
-        ClientMessage::SetVideo { path } => {
-            info!("Setting video: {}", path);
-
-            // Check if video exists
-            if !fetcher::video_exists(&state.store, &path).await? {
-                let response = ServerMessage::VideoSet {
-                    path: path.clone(),
-                    ok: false,
+        let deadline = Duration::from_secs(self.state.config.current().shutdown_timeout_secs);
+        let notice = ServerMessage::ServerShuttingDown {
+            deadline_ms: deadline.as_millis() as u64,
+        };
+        send_json(&self.sender, &notice).await
+    }
+
+    async fn handle_message(&mut self, msg: ClientMessage) -> anyhow::Result<()> {
+        let state = &self.state;
+
+        match msg {
+            ClientMessage::SetVideo { path } => {
+                info!("Setting video: {}", path);
+                let video = state.stores.resolve(&path)?;
+                self.principal.authorize(&video.scope_path)?;
+
+                // Check if video exists
+                let Some(meta) = fetcher::video_meta(&video.store, &video.key).await? else {
+                    let response = ServerMessage::VideoSet {
+                        path: path.clone(),
+                        ok: false,
+                    };
+                    send_json(&self.sender, &response).await?;
+                    return Ok(());
                 };
-                sender
-                    .send(Message::Text(response.to_json().into()))
-                    .await?;
-                return Ok(());
+
+                // Frames are read a GOP at a time; other videos are
+                // loaded whole up front
+                let layout = state.gops.layout(&video.store, &video.key, &meta).await?;
+                let (header, whole) = match layout {
+                    VideoLayout::Indexed(index) => (index.header(), None),
+                    VideoLayout::Whole => {
+                        let data = fetcher::fetch_video(&video.store, &meta).await?;
+                        let whole = Gop {
+                            data: data.into(),
+                            range: 0..meta.size as u64,
+                        };
+                        (whole.data.clone(), Some(whole))
+                    }
+                };
+
+                Span::current().record("video.path", video.scope_path.as_str());
+                self.video = Some(Arc::new(OpenVideo {
+                    store: video.store,
+                    key: video.key,
+                    path: video.scope_path,
+                    meta,
+                    header,
+                    whole,
+                }));
+
+                let response = ServerMessage::VideoSet { path, ok: true };
+                send_json(&self.sender, &response).await?;
             }
 
-            // Fetch video data
-            let data = fetcher::fetch_video(&state.store, &path).await?;
+            ClientMessage::RequestFrames { frames } => {
+                let Some(jobs) = &self.jobs else {
+                    anyhow::bail!("Server is shutting down");
+                };
+                let Some(video) = self.video.clone() else {
+                    anyhow::bail!("No video set. Send SetVideo first.");
+                };
 
-            *video_path = Some(path.clone());
-            *video_data = Some(data);
+                if let Err(limited) = state.limiter.check_request(frames.len()) {
+                    send_rate_limited(&self.sender, limited, None).await?;
+                    return Ok(());
+                }
 
-            let response = ServerMessage::VideoSet { path, ok: true };
-            sender
-                .send(Message::Text(response.to_json().into()))
-                .await?;
+                let job = FrameJob {
+                    video,
+                    frames,
+                    received: Instant::now(),
+                    queued: QueueSlot::new(REQUEST_QUEUE),
+                };
+                if jobs.try_send(job).is_err() {
+                    anyhow::bail!(
+                        "Too many pending requests (max {}). Wait for frames before sending more.",
+                        MAX_QUEUED_REQUESTS
+                    );
+                }
+            }
+
+            ClientMessage::GetInfo => {
+                let Some(video) = &self.video else {
+                    anyhow::bail!("No video set. Send SetVideo first.");
+                };
+
+                let data = video.header.clone();
+                let info = tokio::task::spawn_blocking(move || Decoder::probe(data)).await??;
+
+                let response = ServerMessage::VideoInfo {
+                    path: video.path.clone(),
+                    info,
+                };
+                send_json(&self.sender, &response).await?;
+            }
+
+            ClientMessage::Ack { index } => {
+                debug!("Frame {} acknowledged", index);
+                self.credits.ack();
+            }
+
+            ClientMessage::Credit { window } => {
+                debug!("Credit window set to {}", window);
+                self.credits.set_window(window);
+            }
         }
 
-        ClientMessage::RequestFrames { frames } => {
-            if video_path.is_none() {
-                anyhow::bail!("No video set. Send SetVideo first.");
+        Ok(())
+    }
+}
+
+/// What the frame worker keeps between frames of one video
+struct Playback {
+    video: Arc<OpenVideo>,
+    prefetcher: Prefetcher,
+    /// The GOP last decoded from, reused while requests stay inside it
+    gop: Option<Gop>,
+}
+
+/// Decode queued frames and send them within the client's credit window
+///
+/// Each frame is decoded from its GOP alone, fetched when the request
+/// leaves the previous one, and the session's [`Prefetcher`] reads ahead
+/// as playback moves forward. A frame waits for credit before it is sent;
+/// the wait is the client's to choose and is not timed. Sends slower than
+/// `slow_client_threshold_ms` lower JPEG quality; one that cannot complete
+/// within `slow_client_timeout_ms` ends the session with an error.
+async fn frame_worker(
+    mut jobs: mpsc::Receiver<FrameJob>,
+    state: AppState,
+    client: String,
+    params: SessionParams,
+    sender: SharedSender,
+    credits: Arc<CreditWindow>,
+) -> anyhow::Result<()> {
+    let config = state.config.current();
+    let mut quality = QualityController::new(config.jpeg_quality, config.min_jpeg_quality);
+    let mut playback: Option<Playback> = None;
+
+    while let Some(job) = jobs.recv().await {
+        let FrameJob {
+            video,
+            frames,
+            received,
+            queued,
+        } = job;
+        drop(queued);
+
+        if !playback
+            .as_ref()
+            .is_some_and(|playback| Arc::ptr_eq(&playback.video, &video))
+        {
+            playback = Some(Playback {
+                prefetcher: state.prefetch.session(
+                    video.store.clone(),
+                    &video.key,
+                    video.meta.size as u64,
+                ),
+                gop: video.whole.clone(),
+                video: video.clone(),
+            });
+        }
+        let playback = playback.as_mut().expect("set above");
+
+        for request in frames {
+            if let Err(limited) = state.limiter.check_frame(&client) {
+                send_rate_limited(&sender, limited, Some(request.index)).await?;
+                break;
             }
 
-            // Process frames in blocking task
-            let video_data_clone = video_data.as_ref().unwrap().clone();
-            let jpeg_quality = state.config.jpeg_quality;
-
-            for request in frames {
-                let video_data_inner = video_data_clone.clone();
-                let request_clone = request.clone();
-
-                // Process frame in blocking task (FFmpeg is not Send)
-                let result = tokio::task::spawn_blocking(move || {
-                    process_frame(video_data_inner, request_clone, jpeg_quality)
-                })
-                .await;
-
-                match result {
-                    Ok(Ok(jpeg_data)) => {
-                        // Send frame metadata
-                        let frame_msg = ServerMessage::Frame {
-                            index: request.index,
-                            offset: request.offset,
-                            size: jpeg_data.len() as u32,
-                        };
-                        sender
-                            .send(Message::Text(frame_msg.to_json().into()))
-                            .await?;
+            // Reloaded settings apply from the next frame on
+            let config = state.config.current();
+            let slow_threshold = Duration::from_millis(config.slow_client_threshold_ms);
+            let slow_timeout = Duration::from_millis(config.slow_client_timeout_ms);
+            if let Some(new_quality) =
+                quality.set_bounds(config.jpeg_quality, config.min_jpeg_quality)
+            {
+                send_json(
+                    &sender,
+                    &ServerMessage::QualityChanged {
+                        quality: new_quality,
+                    },
+                )
+                .await?;
+            }
 
-                        // Send binary JPEG data
-                        sender.send(Message::Binary(jpeg_data.into())).await?;
-                    }
-                    Ok(Err(e)) => {
-                        let error_msg = ServerMessage::FrameError {
-                            index: request.index,
-                            offset: request.offset,
-                            error: e.to_string(),
-                        };
-                        sender
-                            .send(Message::Text(error_msg.to_json().into()))
-                            .await?;
-                    }
-                    Err(e) => {
-                        let error_msg = ServerMessage::FrameError {
-                            index: request.index,
-                            offset: request.offset,
-                            error: format!("Task join error: {}", e),
-                        };
-                        sender
-                            .send(Message::Text(error_msg.to_json().into()))
-                            .await?;
+            let queue_wait = received.elapsed();
+            let request_clone = request.clone();
+            let options = EncodeOptions::jpeg(quality.current());
+
+            let frame_span = info_span!(
+                "frame",
+                video.path = %video.path,
+                frame.index = request.index,
+                frame.offset = request.offset,
+                frame.irap_offset = request.irap_offset,
+                output.size = field::Empty,
+            );
+
+            let fetch_started = Instant::now();
+            let gop = match playback.gop.as_ref().filter(|gop| gop.holds(&request)) {
+                Some(gop) => Ok(gop.clone()),
+                None => {
+                    state
+                        .gops
+                        .fetch(&video.store, &video.key, &video.meta, &request)
+                        .instrument(frame_span.clone())
+                        .await
+                }
+            };
+            let fetch_us = fetch_started.elapsed().as_micros() as u64;
+
+            let result = match gop {
+                Ok(gop) => {
+                    playback.prefetcher.observe(gop.range.start);
+                    playback.gop = Some(gop.clone());
+
+                    // Process frame in blocking task (FFmpeg is not Send)
+                    let blocking_span = frame_span.clone();
+                    tokio::task::spawn_blocking(move || {
+                        blocking_span
+                            .in_scope(|| process_frame_timed(gop, &request_clone, &options))
+                    })
+                    .await
+                }
+                Err(e) => Ok(Err(e)),
+            };
+
+            // Encoded frame, or the FrameError to send instead
+            let outcome = match result {
+                Ok(Ok((jpeg_data, timings))) => {
+                    state.limiter.record_bytes(&client, jpeg_data.len());
+                    frame_span.record("output.size", jpeg_data.len());
+                    Ok((
+                        jpeg_data,
+                        FrameTimings {
+                            fetch_us,
+                            ..timings
+                        },
+                    ))
+                }
+                Ok(Err(e)) => {
+                    warn!("Frame at offset {} failed: {:#}", request.offset, e);
+                    Err(ServerMessage::FrameError {
+                        index: request.index,
+                        offset: request.offset,
+                        error: client_message(&e),
+                    })
+                }
+                Err(e) => Err(ServerMessage::FrameError {
+                    index: request.index,
+                    offset: request.offset,
+                    error: format!("Task join error: {}", e),
+                }),
+            };
+            let is_frame = outcome.is_ok();
+
+            // Errors do not count against the credit window
+            if is_frame {
+                if credits.in_flight() > 0 {
+                    debug!("Waiting for credit ({} in flight)", credits.in_flight());
+                }
+                credits.acquire().instrument(frame_span.clone()).await;
+            }
+
+            let started = Instant::now();
+            let delivery = async {
+                let (jpeg_data, timings) = match outcome {
+                    Ok(frame) => frame,
+                    Err(error_msg) => {
+                        let mut sender = sender.lock().await;
+                        return sender.send(Message::Text(error_msg.to_json())).await;
                     }
+                };
+
+                let mut sender = sender.lock().await;
+
+                let timings = params.debug_timings.then(|| FrameTimings {
+                    queue_us: queue_wait.as_micros() as u64,
+                    send_us: started.elapsed().as_micros() as u64,
+                    ..timings
+                });
+
+                // Frame metadata, then binary JPEG data
+                let frame_msg = ServerMessage::Frame {
+                    index: request.index,
+                    offset: request.offset,
+                    size: jpeg_data.len() as u32,
+                    timings,
+                };
+                sender.send(Message::Text(frame_msg.to_json())).await?;
+                sender.send(Message::Binary(jpeg_data)).await
+            }
+            .instrument(frame_span);
+
+            if slow_timeout.is_zero() {
+                delivery.await?;
+            } else if let Ok(sent) = tokio::time::timeout(slow_timeout, delivery).await {
+                sent?;
+            } else {
+                disconnect_slow_client(&sender, slow_timeout).await;
+                anyhow::bail!("Client did not drain frames within {:?}", slow_timeout);
+            }
+
+            if is_frame {
+                if let Some(new_quality) = quality.record(started.elapsed() > slow_threshold) {
+                    info!(
+                        "Client {} is slow; JPEG quality now {}",
+                        client, new_quality
+                    );
+                    send_json(
+                        &sender,
+                        &ServerMessage::QualityChanged {
+                            quality: new_quality,
+                        },
+                    )
+                    .await?;
                 }
             }
         }
@@ -195,21 +630,39 @@ async fn handle_message(
     Ok(())
 }
 
-/// Process a single frame (runs in blocking context)
-fn process_frame(
-    video_data: Bytes,
-    request: FrameRequest,
-    jpeg_quality: u8,
-) -> anyhow::Result<Vec<u8>> {
-    // Create decoder
-    let mut decoder = Decoder::new(&video_data)?;
-
-    // Decode frame
-    let frame = decoder.decode_frame(&video_data, request.offset)?;
+/// Best-effort notice before dropping a client that stopped reading
+async fn disconnect_slow_client(sender: &SharedSender, timeout: Duration) {
+    let notice = ServerMessage::Error {
+        message: format!(
+            "Disconnecting: frames not consumed within {} ms",
+            timeout.as_millis()
+        ),
+    };
+    let _ = tokio::time::timeout(CLOSE_TIMEOUT, async {
+        let mut sender = sender.lock().await;
+        let _ = sender.send(Message::Text(notice.to_json())).await;
+        let _ = sender.send(Message::Close(None)).await;
+    })
+    .await;
+}
 
-    // Create encoder and encode
-    let mut encoder = JpegEncoder::new(jpeg_quality)?;
-    let jpeg = encoder.encode(&frame)?;
+/// Send a server message as a text frame
+async fn send_json(sender: &SharedSender, msg: &ServerMessage) -> Result<(), axum::Error> {
+    sender.lock().await.send(Message::Text(msg.to_json())).await
+}
 
-    Ok(jpeg)
+/// Report a limit hit; frames from `index` on are dropped
+async fn send_rate_limited(
+    sender: &SharedSender,
+    limited: RateLimited,
+    index: Option<u32>,
+) -> anyhow::Result<()> {
+    debug!("{}", limited);
+    let response = ServerMessage::RateLimited {
+        limit: limited.limit,
+        retry_after_ms: limited.retry_after.as_millis() as u64,
+        index,
+    };
+    send_json(sender, &response).await?;
+    Ok(())
 }
@@ -1,12 +1,64 @@
 use anyhow::{Context, Result};
+use axum::http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
 use bytes::Bytes;
-use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, path::Path, ObjectStore};
+use futures_util::TryStreamExt;
+use object_store::{
+    aws::AmazonS3Builder,
+    azure::{AzureConfigKey, MicrosoftAzureBuilder},
+    gcp::GoogleCloudStorageBuilder,
+    http::HttpBuilder,
+    local::LocalFileSystem,
+    path::Path,
+    ClientOptions, GetOptions, ObjectMeta, ObjectStore, RetryConfig,
+};
+use std::collections::BTreeMap;
+use std::ops::Range;
 use std::sync::Arc;
 
+use super::cache::CachedStore;
+use super::credentials;
+use super::disk_cache::DiskCache;
+use super::error::StorageError;
+use super::parallel::{merge_ranges, ParallelStore, RangeReads};
+use super::resilience::{ReadPolicy, ResilientStore};
 use crate::config::{Config, StorageBackend};
+use crate::telemetry::{self, Stage, StageTimer};
 
 /// Create an ObjectStore instance based on configuration
+///
+/// Reads go through a [`ResilientStore`] applying the configured timeouts,
+/// retries and circuit breaker, then the [`DiskCache`] when
+/// `disk_cache_dir` is set and the in-memory [`CachedStore`], wrapped in a
+/// [`ParallelStore`] so each part of a split read is cached and retried on
+/// its own. The memory tier is always present so that `fetch_cache_bytes`
+/// can be reloaded; at 0 it passes reads straight through.
 pub fn create_store(config: &Config) -> Result<Arc<dyn ObjectStore>> {
+    let store = create_backend(config)?;
+    let mut store: Arc<dyn ObjectStore> =
+        Arc::new(ResilientStore::new(store, ReadPolicy::from_config(config)));
+    if let Some(dir) = &config.disk_cache_dir {
+        let cache = DiskCache::from_config(config, dir)
+            .with_context(|| format!("Failed to open disk cache in {}", dir))?;
+        store = Arc::new(CachedStore::new(store, cache));
+    }
+    store = Arc::new(CachedStore::in_memory(store, config));
+    Ok(Arc::new(ParallelStore::new(
+        store,
+        RangeReads::from_config(config),
+    )))
+}
+
+/// Retry settings for object_store's own clients
+///
+/// Retries are left to [`ResilientStore`], so that the two don't compound.
+fn single_attempt() -> RetryConfig {
+    RetryConfig {
+        max_retries: 0,
+        ..RetryConfig::default()
+    }
+}
+
+fn create_backend(config: &Config) -> Result<Arc<dyn ObjectStore>> {
     match config.storage_backend {
         StorageBackend::Local => {
             let store = LocalFileSystem::new_with_prefix(&config.local_path)
@@ -14,11 +66,12 @@ pub fn create_store(config: &Config) -> Result<Arc<dyn ObjectStore>> {
             Ok(Arc::new(store))
         }
         StorageBackend::S3 => {
-            let mut builder = AmazonS3Builder::new()
+            let builder = AmazonS3Builder::new()
                 .with_bucket_name(&config.s3_bucket)
                 .with_region(&config.s3_region)
-                .with_access_key_id(&config.s3_access_key)
-                .with_secret_access_key(&config.s3_secret_key);
+                .with_retry(single_attempt());
+            let (mut builder, source) = credentials::with_credentials(builder, config)?;
+            tracing::info!("S3 credentials from {}", source);
 
             if let Some(endpoint) = &config.s3_endpoint {
                 builder = builder.with_endpoint(endpoint);
@@ -29,6 +82,196 @@ pub fn create_store(config: &Config) -> Result<Arc<dyn ObjectStore>> {
             let store = builder.build().context("Failed to create S3 store")?;
             Ok(Arc::new(store))
         }
+        StorageBackend::Gcs => {
+            let store = gcs_builder(config)?
+                .build()
+                .context("Failed to create GCS store")?;
+            Ok(Arc::new(store))
+        }
+        StorageBackend::Azure => {
+            let store = azure_builder(config)
+                .build()
+                .context("Failed to create Azure store")?;
+            Ok(Arc::new(store))
+        }
+        StorageBackend::Http => {
+            let store = http_builder(config)?
+                .build()
+                .context("Failed to create HTTP store")?;
+            Ok(Arc::new(store))
+        }
+    }
+}
+
+/// GCS store settings
+///
+/// Without a service account, application default credentials are used
+/// (`GOOGLE_APPLICATION_CREDENTIALS`, gcloud user credentials, or the
+/// metadata server on GCE/GKE).
+fn gcs_builder(config: &Config) -> Result<GoogleCloudStorageBuilder> {
+    let builder = GoogleCloudStorageBuilder::new()
+        .with_bucket_name(&config.gcs_bucket)
+        .with_retry(single_attempt());
+
+    let Some(endpoint) = &config.gcs_endpoint else {
+        return Ok(if let Some(path) = &config.gcs_service_account_path {
+            builder.with_service_account_path(path)
+        } else if let Some(key) = &config.gcs_service_account_key {
+            builder.with_service_account_key(key)
+        } else {
+            builder
+        });
+    };
+
+    // object_store only takes a custom base URL from a service account key,
+    // so a configured key gets the endpoint added and keeps OAuth
+    let key = if let Some(path) = &config.gcs_service_account_path {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read GCS service account {}", path))?
+    } else if let Some(key) = &config.gcs_service_account_key {
+        key.clone()
+    } else {
+        // No credentials: an emulator such as fake-gcs-server
+        let key = serde_json::json!({
+            "gcs_base_url": endpoint,
+            "disable_oauth": true,
+            "client_email": "",
+            "private_key": "",
+        });
+        return Ok(builder.with_service_account_key(key.to_string()));
+    };
+    Ok(builder.with_service_account_key(with_base_url(&key, endpoint)?))
+}
+
+/// Service account `key` with its GCS base URL set to `endpoint`
+fn with_base_url(key: &str, endpoint: &str) -> Result<String> {
+    let mut key: serde_json::Value =
+        serde_json::from_str(key).context("GCS service account key is not valid JSON")?;
+    let fields = key
+        .as_object_mut()
+        .context("GCS service account key is not a JSON object")?;
+    fields.insert("gcs_base_url".to_string(), endpoint.into());
+    Ok(key.to_string())
+}
+
+/// Azure store settings
+///
+/// Credentials are tried in the order: access key, SAS token, workload
+/// identity, client secret, then managed identity.
+fn azure_builder(config: &Config) -> MicrosoftAzureBuilder {
+    let mut builder = MicrosoftAzureBuilder::new()
+        .with_container_name(&config.azure_container)
+        .with_use_emulator(config.azure_use_emulator)
+        .with_retry(single_attempt());
+
+    if !config.azure_account.is_empty() {
+        builder = builder.with_account(&config.azure_account);
+    }
+    if let Some(endpoint) = &config.azure_endpoint {
+        builder = builder.with_endpoint(endpoint.clone());
+    }
+
+    if let Some(key) = &config.azure_access_key {
+        builder = builder.with_access_key(key);
+    } else if let Some(sas) = &config.azure_sas_token {
+        builder = builder.with_config(AzureConfigKey::SasKey, sas.trim_start_matches('?'));
+    }
+    if let Some(client_id) = &config.azure_client_id {
+        builder = builder.with_client_id(client_id);
+    }
+    if let Some(tenant_id) = &config.azure_tenant_id {
+        builder = builder.with_tenant_id(tenant_id);
+    }
+    if let Some(secret) = &config.azure_client_secret {
+        builder = builder.with_client_secret(secret);
+    }
+    if let Some(token_file) = &config.azure_federated_token_file {
+        builder = builder.with_federated_token_file(token_file);
+    }
+    builder
+}
+
+/// HTTP(S) origin settings
+///
+/// Sizes come from `HEAD` and reads are `Range` GETs; an origin that answers
+/// a range request with the whole file fails the read instead of silently
+/// downloading it. Listing needs WebDAV (`PROPFIND`), which most CDNs lack.
+fn http_builder(config: &Config) -> Result<HttpBuilder> {
+    let mut headers = HeaderMap::new();
+    for header in &config.http_headers {
+        let (name, value) = parse_header(header)?;
+        headers.insert(name, value);
+    }
+    if let Some(token) = &config.http_bearer_token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .context("Invalid http_bearer_token")?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    let options = ClientOptions::new()
+        .with_default_headers(headers)
+        .with_allow_http(config.http_url.starts_with("http://"));
+    // Object paths are appended as segments; a trailing slash would double up
+    Ok(HttpBuilder::new()
+        .with_url(config.http_url.trim_end_matches('/'))
+        .with_client_options(options)
+        .with_retry(single_attempt()))
+}
+
+/// Parse a `Name: value` header; values are marked sensitive so they are
+/// left out of debug output
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue)> {
+    let (name, value) = header
+        .split_once(':')
+        .with_context(|| format!("'{}' is not a `Name: value` header", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .with_context(|| format!("Invalid header name '{}'", name.trim()))?;
+    let mut value = HeaderValue::from_str(value.trim())
+        .with_context(|| format!("Invalid value for header {}", name))?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// Check that `store` answers a range request with just that range
+///
+/// Run at startup against an HTTP origin's `http_range_probe` object, so an
+/// origin that ignores `Range` is reported before clients hit it.
+pub async fn check_range_support(store: &dyn ObjectStore, path: &str) -> Result<()> {
+    let bytes = store
+        .get_range(&Path::from(path), 0..1)
+        .await
+        .with_context(|| {
+            format!(
+                "Range request for '{}' failed; the origin must answer `Range` with 206",
+                path
+            )
+        })?;
+    anyhow::ensure!(
+        bytes.len() == 1,
+        "Range request for '{}' returned {} bytes instead of 1",
+        path,
+        bytes.len()
+    );
+    Ok(())
+}
+
+/// Storage URL for an object, in the format `repo-cli` writes to sidecars
+///
+/// `s3://bucket/path` for S3, `gs://bucket/path` for GCS,
+/// `az://container/path` for Azure, `fs:///absolute/path` for local storage,
+/// and the object's own URL for HTTP origins.
+pub fn object_url(config: &Config, path: &str) -> String {
+    match config.storage_backend {
+        StorageBackend::Local => {
+            let root = std::fs::canonicalize(&config.local_path)
+                .unwrap_or_else(|_| std::path::PathBuf::from(&config.local_path));
+            format!("fs://{}", root.join(path).display())
+        }
+        StorageBackend::S3 => format!("s3://{}/{}", config.s3_bucket, path),
+        StorageBackend::Gcs => format!("gs://{}/{}", config.gcs_bucket, path),
+        StorageBackend::Azure => format!("az://{}/{}", config.azure_container, path),
+        StorageBackend::Http => format!("{}/{}", config.http_url.trim_end_matches('/'), path),
     }
 }
 
@@ -42,52 +285,284 @@ pub fn create_store(config: &Config) -> Result<Arc<dyn ObjectStore>> {
 ///
 /// # Returns
 /// The requested byte range as `Bytes`
+#[tracing::instrument(
+    name = "fetch",
+    skip_all,
+    fields(video.path = path, fetch.start = start, fetch.end = end, fetch.bytes)
+)]
 pub async fn fetch_range(
     store: &dyn ObjectStore,
     path: &str,
     start: u64,
     end: u64,
 ) -> Result<Bytes> {
+    let _timer = StageTimer::start(Stage::Fetch);
     let path = Path::from(path);
     let range = (start as usize)..(end as usize);
 
     let bytes = store
         .get_range(&path, range)
+        .await
+        .map_err(StorageError::from)
+        .context("Failed to fetch byte range")?;
+    telemetry::record_fetch("range", bytes.len());
+    tracing::Span::current().record("fetch.bytes", bytes.len());
+
+    Ok(bytes)
+}
+
+/// Fetch a byte range of the version of an object described by `meta`
+///
+/// The read carries `meta`'s ETag as `If-Match`, so it fails rather than
+/// returning bytes of an object overwritten since `meta` was read.
+#[tracing::instrument(
+    name = "fetch",
+    skip_all,
+    fields(
+        video.path = %meta.location,
+        fetch.start = range.start,
+        fetch.end = range.end,
+        fetch.bytes,
+    )
+)]
+pub async fn fetch_version(
+    store: &dyn ObjectStore,
+    meta: &ObjectMeta,
+    range: Range<u64>,
+) -> Result<Bytes> {
+    let _timer = StageTimer::start(Stage::Fetch);
+    let options = GetOptions {
+        if_match: meta.e_tag.clone(),
+        range: Some((range.start as usize..range.end as usize).into()),
+        ..GetOptions::default()
+    };
+    let bytes = get_bytes(store, &meta.location, options)
         .await
         .context("Failed to fetch byte range")?;
+    telemetry::record_fetch("range", bytes.len());
+    tracing::Span::current().record("fetch.bytes", bytes.len());
+
+    Ok(bytes)
+}
+
+/// Fetch several byte ranges of the version of an object described by
+/// `meta`, e.g. from [`head`]
+///
+/// Overlapping and adjacent ranges are read together, and each read is
+/// conditional on the object's ETag like [`fetch_version`].
+#[tracing::instrument(
+    name = "fetch",
+    skip_all,
+    fields(video.path = %meta.location, fetch.ranges = ranges.len(), fetch.bytes)
+)]
+pub async fn fetch_ranges(
+    store: &dyn ObjectStore,
+    meta: &ObjectMeta,
+    ranges: &[Range<u64>],
+) -> Result<Vec<Bytes>> {
+    let _timer = StageTimer::start(Stage::Fetch);
+    let ranges: Vec<_> = ranges
+        .iter()
+        .map(|range| range.start as usize..range.end as usize)
+        .collect();
+    let merged = merge_ranges(&ranges, 0);
+    let fetched = futures_util::future::try_join_all(merged.iter().map(|range| {
+        let options = GetOptions {
+            if_match: meta.e_tag.clone(),
+            range: Some(range.clone().into()),
+            ..GetOptions::default()
+        };
+        get_bytes(store, &meta.location, options)
+    }))
+    .await
+    .context("Failed to fetch byte ranges")?;
+    let len = fetched.iter().map(Bytes::len).sum::<usize>();
+    telemetry::record_fetch("range", len);
+    tracing::Span::current().record("fetch.bytes", len);
+
+    Ok(ranges
+        .iter()
+        .map(|range| {
+            let i = merged.partition_point(|m| m.start <= range.start) - 1;
+            let start = range.start - merged[i].start;
+            let end = (range.end - merged[i].start).min(fetched[i].len());
+            fetched[i].slice(start.min(end)..end)
+        })
+        .collect())
+}
+
+/// Fetch the whole version of an object described by `meta`, e.g. from
+/// [`head`]
+///
+/// Reads the object as a range, so objects larger than the part size are
+/// fetched as concurrent parts rather than one stream, each part checked
+/// against `meta`'s ETag.
+#[tracing::instrument(
+    name = "fetch",
+    skip_all,
+    fields(video.path = %meta.location, fetch.bytes = meta.size)
+)]
+pub async fn fetch_sized(store: &dyn ObjectStore, meta: &ObjectMeta) -> Result<Bytes> {
+    if meta.size == 0 {
+        return Ok(Bytes::new());
+    }
+    let _timer = StageTimer::start(Stage::Fetch);
+    let options = GetOptions {
+        if_match: meta.e_tag.clone(),
+        range: Some((0..meta.size).into()),
+        ..GetOptions::default()
+    };
+    let bytes = get_bytes(store, &meta.location, options)
+        .await
+        .context("Failed to get object")?;
+    telemetry::record_fetch("full", bytes.len());
 
     Ok(bytes)
 }
 
+async fn get_bytes(
+    store: &dyn ObjectStore,
+    path: &Path,
+    options: GetOptions,
+) -> Result<Bytes, StorageError> {
+    let result = store.get_opts(path, options).await?;
+    Ok(result.bytes().await?)
+}
+
 /// Fetch entire file from storage
+#[tracing::instrument(name = "fetch", skip_all, fields(video.path = path, fetch.bytes))]
 pub async fn fetch_all(store: &dyn ObjectStore, path: &str) -> Result<Bytes> {
+    let _timer = StageTimer::start(Stage::Fetch);
     let path = Path::from(path);
-    let result = store.get(&path).await.context("Failed to get object")?;
+    let result = store
+        .get(&path)
+        .await
+        .map_err(StorageError::from)
+        .context("Failed to get object")?;
 
     let bytes = result
         .bytes()
         .await
+        .map_err(StorageError::from)
         .context("Failed to read object bytes")?;
+    telemetry::record_fetch("full", bytes.len());
+    tracing::Span::current().record("fetch.bytes", bytes.len());
 
     Ok(bytes)
 }
 
+/// Fetch entire file, or `None` if the object does not exist
+pub async fn fetch_optional(store: &dyn ObjectStore, path: &str) -> Result<Option<Bytes>> {
+    let path = Path::from(path);
+    let result = match store.get(&path).await {
+        Ok(result) => result,
+        Err(object_store::Error::NotFound { .. }) => return Ok(None),
+        Err(e) => return Err(StorageError::from(e)).context("Failed to get object"),
+    };
+
+    let bytes = result
+        .bytes()
+        .await
+        .map_err(StorageError::from)
+        .context("Failed to read object bytes")?;
+
+    Ok(Some(bytes))
+}
+
+/// Write an entire object, replacing any existing one
+pub async fn put(store: &dyn ObjectStore, path: &str, data: Bytes) -> Result<()> {
+    let path = Path::from(path);
+    store
+        .put(&path, data.into())
+        .await
+        .map_err(StorageError::from)
+        .context("Failed to write object")?;
+
+    Ok(())
+}
+
 /// Check if object exists
 pub async fn exists(store: &dyn ObjectStore, path: &str) -> Result<bool> {
     let path = Path::from(path);
     match store.head(&path).await {
         Ok(_) => Ok(true),
         Err(object_store::Error::NotFound { .. }) => Ok(false),
-        Err(e) => Err(e).context("Failed to check object existence"),
+        Err(e) => Err(StorageError::from(e)).context("Failed to check object existence"),
+    }
+}
+
+/// Get full object metadata, or `None` if the object does not exist
+pub async fn head(store: &dyn ObjectStore, path: &str) -> Result<Option<ObjectMeta>> {
+    let path = Path::from(path);
+    match store.head(&path).await {
+        Ok(meta) => Ok(Some(meta)),
+        Err(object_store::Error::NotFound { .. }) => Ok(None),
+        Err(e) => Err(StorageError::from(e)).context("Failed to get object metadata"),
     }
 }
 
+/// List all objects under a directory-style prefix
+///
+/// `prefix` is matched on whole path segments (`videos` matches
+/// `videos/a.h265` but not `videos2/a.h265`). Results are not ordered.
+pub async fn list(store: &dyn ObjectStore, prefix: Option<&str>) -> Result<Vec<ObjectMeta>> {
+    let prefix = prefix.map(Path::from);
+    store
+        .list(prefix.as_ref())
+        .try_collect()
+        .await
+        .map_err(StorageError::from)
+        .context("Failed to list objects")
+}
+
+/// The first `count` objects after `after`, in key order, that `keep`
+/// accepts
+///
+/// With `ordered` the listing is trusted to come back sorted and stops as
+/// soon as `count` are found. Otherwise the whole listing past `after` is
+/// read, holding only the `count` smallest keys.
+pub async fn list_page(
+    store: &dyn ObjectStore,
+    prefix: Option<&str>,
+    after: Option<&str>,
+    ordered: bool,
+    count: usize,
+    mut keep: impl FnMut(&ObjectMeta) -> bool,
+) -> Result<Vec<ObjectMeta>> {
+    let prefix = prefix.map(Path::from);
+    let mut listing = match after {
+        Some(after) => store.list_with_offset(prefix.as_ref(), &Path::from(after)),
+        None => store.list(prefix.as_ref()),
+    };
+
+    let mut page = BTreeMap::new();
+    while let Some(meta) = listing
+        .try_next()
+        .await
+        .map_err(StorageError::from)
+        .context("Failed to list objects")?
+    {
+        if !keep(&meta) {
+            continue;
+        }
+        page.insert(meta.location.clone(), meta);
+        if page.len() > count {
+            page.pop_last();
+        }
+        if ordered && page.len() == count {
+            break;
+        }
+    }
+    Ok(page.into_values().collect())
+}
+
 /// Get object metadata (size)
 pub async fn get_size(store: &dyn ObjectStore, path: &str) -> Result<u64> {
     let path = Path::from(path);
     let meta = store
         .head(&path)
         .await
+        .map_err(StorageError::from)
         .context("Failed to get object metadata")?;
 
     Ok(meta.size as u64)
@@ -139,6 +614,80 @@ mod tests {
         assert_eq!(&bytes[..], b"0123456789ABCDEF");
     }
 
+    #[tokio::test]
+    async fn test_fetch_sized_in_parts() {
+        let temp = TempDir::new().unwrap();
+        std::fs::write(temp.path().join("test.bin"), b"0123456789ABCDEF").unwrap();
+        let config = Config {
+            storage_part_size: 5,
+            fetch_cache_bytes: 0,
+            ..create_test_config(temp.path())
+        };
+        let store = create_store(&config).unwrap();
+
+        let meta = head(&*store, "test.bin").await.unwrap().unwrap();
+        let bytes = fetch_sized(&*store, &meta).await.unwrap();
+        assert_eq!(&bytes[..], b"0123456789ABCDEF");
+        let empty = ObjectMeta {
+            size: 0,
+            ..meta.clone()
+        };
+        assert!(fetch_sized(&*store, &empty).await.unwrap().is_empty());
+
+        // Parts of another version of the object are refused
+        std::fs::write(temp.path().join("test.bin"), b"FEDCBA9876543210!").unwrap();
+        assert!(fetch_sized(&*store, &meta).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_optional() {
+        let (store, _temp) = setup_local_store().await;
+
+        let bytes = fetch_optional(&*store, "test.bin").await.unwrap();
+        assert_eq!(bytes.as_deref(), Some(&b"0123456789ABCDEF"[..]));
+        assert!(fetch_optional(&*store, "nonexistent.bin")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_put() {
+        let (store, _temp) = setup_local_store().await;
+
+        put(&*store, "out/new.json", Bytes::from_static(b"{}"))
+            .await
+            .unwrap();
+        let bytes = fetch_all(&*store, "out/new.json").await.unwrap();
+        assert_eq!(&bytes[..], b"{}");
+    }
+
+    #[test]
+    fn test_object_url() {
+        let config = Config {
+            storage_backend: StorageBackend::S3,
+            s3_bucket: "my-bucket".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(
+            object_url(&config, "videos/a.h265"),
+            "s3://my-bucket/videos/a.h265"
+        );
+
+        let config = Config {
+            storage_backend: StorageBackend::Gcs,
+            gcs_bucket: "footage".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(object_url(&config, "a.h265"), "gs://footage/a.h265");
+
+        let temp = TempDir::new().unwrap();
+        let config = create_test_config(temp.path());
+        let url = object_url(&config, "a.h265");
+        assert!(url.starts_with("fs:///"));
+        assert!(url.ends_with("/a.h265"));
+    }
+
     #[tokio::test]
     async fn test_exists() {
         let (store, _temp) = setup_local_store().await;
@@ -147,6 +696,53 @@ mod tests {
         assert!(!exists(&*store, "nonexistent.bin").await.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_head() {
+        let (store, _temp) = setup_local_store().await;
+
+        let meta = head(&*store, "test.bin").await.unwrap().unwrap();
+        assert_eq!(meta.size, 16);
+        assert!(head(&*store, "nonexistent.bin").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list() {
+        let (store, temp) = setup_local_store().await;
+        std::fs::create_dir(temp.path().join("sub")).unwrap();
+        std::fs::write(temp.path().join("sub/nested.bin"), b"x").unwrap();
+
+        let all = list(&*store, None).await.unwrap();
+        assert_eq!(all.len(), 2);
+
+        let nested = list(&*store, Some("sub")).await.unwrap();
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].location.as_ref(), "sub/nested.bin");
+    }
+
+    #[tokio::test]
+    async fn test_list_page() {
+        let store = object_store::memory::InMemory::new();
+        for key in ["a", "b", "c", "d", "e"] {
+            store
+                .put(&Path::from(key), Bytes::from_static(b"x").into())
+                .await
+                .unwrap();
+        }
+        let skip_c = |meta: &ObjectMeta| meta.location.as_ref() != "c";
+
+        for ordered in [true, false] {
+            let page = list_page(&store, None, Some("a"), ordered, 2, skip_c)
+                .await
+                .unwrap();
+            let keys: Vec<_> = page.iter().map(|meta| meta.location.as_ref()).collect();
+            assert_eq!(keys, ["b", "d"]);
+        }
+        assert!(list_page(&store, None, Some("e"), true, 2, |_| true)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn test_get_size() {
         let (store, _temp) = setup_local_store().await;
@@ -154,4 +750,155 @@ mod tests {
         let size = get_size(&*store, "test.bin").await.unwrap();
         assert_eq!(size, 16);
     }
+
+    const ORIGIN_CONTENT: &[u8] = b"0123456789ABCDEF";
+
+    /// Serve `ORIGIN_CONTENT` at `/videos/clip.bin` to requests carrying the
+    /// `x-origin-key` header; `ranges` = false ignores `Range` like some CDNs
+    async fn spawn_origin(ranges: bool) -> String {
+        use axum::http::{header, HeaderMap, StatusCode};
+        use axum::response::IntoResponse;
+
+        let app = axum::Router::new().route(
+            "/videos/clip.bin",
+            axum::routing::get(move |headers: HeaderMap| async move {
+                if headers.get("x-origin-key").map(|v| v.as_bytes()) != Some(b"secret") {
+                    return StatusCode::UNAUTHORIZED.into_response();
+                }
+                let range = headers
+                    .get(header::RANGE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.strip_prefix("bytes="))
+                    .and_then(|v| v.split_once('-'))
+                    .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)));
+                match range {
+                    Some((start, end)) if ranges => (
+                        StatusCode::PARTIAL_CONTENT,
+                        [(
+                            header::CONTENT_RANGE,
+                            format!("bytes {}-{}/{}", start, end, ORIGIN_CONTENT.len()),
+                        )],
+                        &ORIGIN_CONTENT[start..=end],
+                    )
+                        .into_response(),
+                    _ => ORIGIN_CONTENT.into_response(),
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/videos/", addr)
+    }
+
+    fn http_config(url: String, headers: &[&str]) -> Config {
+        Config {
+            storage_backend: StorageBackend::Http,
+            http_url: url,
+            http_headers: headers.iter().map(|h| h.to_string()).collect(),
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_origin() {
+        let url = spawn_origin(true).await;
+        let config = http_config(url.clone(), &["x-origin-key: secret"]);
+        let store = create_store(&config).unwrap();
+
+        assert_eq!(get_size(&*store, "clip.bin").await.unwrap(), 16);
+        let bytes = fetch_range(&*store, "clip.bin", 10, 16).await.unwrap();
+        assert_eq!(&bytes[..], b"ABCDEF");
+        assert!(exists(&*store, "clip.bin").await.unwrap());
+        assert!(!exists(&*store, "missing.bin").await.unwrap());
+        check_range_support(&*store, "clip.bin").await.unwrap();
+        assert_eq!(object_url(&config, "clip.bin"), format!("{}clip.bin", url));
+
+        // Without the header the origin refuses the request
+        let store = create_store(&http_config(url, &[])).unwrap();
+        assert!(fetch_range(&*store, "clip.bin", 0, 4).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_origin_without_ranges() {
+        let url = spawn_origin(false).await;
+        let store = create_store(&http_config(url, &["x-origin-key: secret"])).unwrap();
+
+        assert!(fetch_range(&*store, "clip.bin", 0, 4).await.is_err());
+        assert!(check_range_support(&*store, "clip.bin").await.is_err());
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let (name, value) = parse_header("Authorization: Basic dXNlcjpwYXNz").unwrap();
+        assert_eq!(name, AUTHORIZATION);
+        assert_eq!(value, "Basic dXNlcjpwYXNz");
+        assert!(value.is_sensitive());
+        assert!(parse_header("no-colon").is_err());
+        assert!(parse_header("bad name: x").is_err());
+    }
+
+    #[test]
+    fn test_gcs_endpoint_keeps_credentials() {
+        let key = r#"{"client_email": "sa@project.iam", "private_key": "pem"}"#;
+        let key: serde_json::Value =
+            serde_json::from_str(&with_base_url(key, "https://gcs.internal").unwrap()).unwrap();
+        assert_eq!(key["gcs_base_url"], "https://gcs.internal");
+        assert_eq!(key["client_email"], "sa@project.iam");
+        assert!(key.get("disable_oauth").is_none());
+        assert!(with_base_url("[]", "https://gcs.internal").is_err());
+
+        let missing = Config {
+            storage_backend: StorageBackend::Gcs,
+            gcs_bucket: "footage".to_string(),
+            gcs_endpoint: Some("https://gcs.internal".to_string()),
+            gcs_service_account_path: Some("/nonexistent/sa.json".to_string()),
+            ..Config::default()
+        };
+        assert!(gcs_builder(&missing).is_err());
+    }
+
+    /// Write, range-read and list through a cloud store
+    async fn roundtrip(config: Config) {
+        let store = create_store(&config).unwrap();
+        let path = "emulator-test/clip.bin";
+
+        put(&*store, path, Bytes::from_static(b"0123456789"))
+            .await
+            .unwrap();
+        let bytes = fetch_range(&*store, path, 2, 5).await.unwrap();
+        assert_eq!(&bytes[..], b"234");
+
+        let listed = list(&*store, Some("emulator-test")).await.unwrap();
+        assert!(listed.iter().any(|meta| meta.location.as_ref() == path));
+    }
+
+    // Emulator tests need `docker compose --profile emulators up -d`, then:
+    // cargo test -p bucket-streamer -- --ignored emulator
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_gcs_emulator() {
+        let endpoint = std::env::var("GCS_EMULATOR_URL")
+            .unwrap_or_else(|_| "http://localhost:4443".to_string());
+        roundtrip(Config {
+            storage_backend: StorageBackend::Gcs,
+            gcs_bucket: "footage".to_string(),
+            gcs_endpoint: Some(endpoint),
+            ..Config::default()
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_azure_emulator() {
+        roundtrip(Config {
+            storage_backend: StorageBackend::Azure,
+            azure_container: "footage".to_string(),
+            azure_use_emulator: true,
+            ..Config::default()
+        })
+        .await;
+    }
 }
@@ -0,0 +1,642 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures_util::stream::BoxStream;
+use object_store::{
+    path::Path, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+    PutMultipartOpts, PutOptions, PutPayload, PutResult, Result,
+};
+
+use super::parallel::{bounded_range, ranged_result};
+use crate::config::Config;
+use crate::telemetry;
+
+/// Paths remembered without any cached blocks before the idle ones are
+/// forgotten
+const MAX_IDLE_PATHS: usize = 10_000;
+
+/// Block `index` of an object
+pub type BlockKey = (Path, u64);
+
+/// Where a [`CachedStore`] keeps blocks
+///
+/// Blocks are aligned to `block_size`; the last block of an object may be
+/// shorter. A tier drops a path's blocks when a `HEAD` reports a different
+/// size, ETag or modification time than the one they were read under.
+#[async_trait]
+pub trait BlockTier: fmt::Debug + Send + Sync + 'static {
+    /// Label for cache metrics
+    const NAME: &'static str;
+
+    fn block_size(&self) -> u64;
+
+    /// A cached block, marking it recently used
+    async fn get(&self, path: &Path, index: u64) -> Option<Bytes>;
+
+    /// Cache a block, evicting older blocks to make room
+    async fn insert(&self, path: &Path, index: u64, data: Bytes);
+
+    /// Drop every block of `path`, e.g. after it was overwritten
+    fn invalidate(&self, path: &Path);
+
+    /// Note the current version of an object, dropping blocks of an older one
+    fn validate(&self, meta: &ObjectMeta);
+
+    /// Metadata of `path` as of its last `HEAD`
+    fn known_meta(&self, path: &Path) -> Option<ObjectMeta>;
+
+    /// Whether reads should go through the tier at all
+    fn is_enabled(&self) -> bool {
+        true
+    }
+}
+
+#[async_trait]
+impl<T: BlockTier> BlockTier for Arc<T> {
+    const NAME: &'static str = T::NAME;
+
+    fn block_size(&self) -> u64 {
+        self.as_ref().block_size()
+    }
+
+    async fn get(&self, path: &Path, index: u64) -> Option<Bytes> {
+        self.as_ref().get(path, index).await
+    }
+
+    async fn insert(&self, path: &Path, index: u64, data: Bytes) {
+        self.as_ref().insert(path, index, data).await
+    }
+
+    fn invalidate(&self, path: &Path) {
+        self.as_ref().invalidate(path)
+    }
+
+    fn validate(&self, meta: &ObjectMeta) {
+        self.as_ref().validate(meta)
+    }
+
+    fn known_meta(&self, path: &Path) -> Option<ObjectMeta> {
+        self.as_ref().known_meta(path)
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.as_ref().is_enabled()
+    }
+}
+
+/// What identifies one version of an object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub size: usize,
+    pub e_tag: Option<String>,
+    pub last_modified: i64,
+}
+
+impl Version {
+    pub fn of(meta: &ObjectMeta) -> Self {
+        Self {
+            size: meta.size,
+            e_tag: meta.e_tag.clone(),
+            last_modified: meta.last_modified.timestamp_micros(),
+        }
+    }
+}
+
+/// Cached blocks in least recently used order, and the version of each
+/// path they belong to
+///
+/// `V` is what a tier keeps per block: the data itself, or where to find it.
+#[derive(Debug)]
+pub struct BlockIndex<V> {
+    blocks: HashMap<BlockKey, IndexedBlock<V>>,
+    /// Block keys by last use, oldest first
+    recency: BTreeMap<u64, BlockKey>,
+    paths: HashMap<Path, PathState>,
+    bytes: u64,
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct IndexedBlock<V> {
+    value: V,
+    size: u64,
+    used: u64,
+}
+
+#[derive(Debug, Default)]
+struct PathState {
+    version: Option<Version>,
+    meta: Option<ObjectMeta>,
+    blocks: usize,
+}
+
+impl<V: Clone> BlockIndex<V> {
+    pub fn new() -> Self {
+        Self {
+            blocks: HashMap::new(),
+            recency: BTreeMap::new(),
+            paths: HashMap::new(),
+            bytes: 0,
+            clock: 0,
+        }
+    }
+
+    /// The value kept for `key`, marking it recently used
+    pub fn get(&mut self, key: &BlockKey) -> Option<V> {
+        self.clock += 1;
+        let now = self.clock;
+        let block = self.blocks.get_mut(key)?;
+        let previous = std::mem::replace(&mut block.used, now);
+        let value = block.value.clone();
+        self.recency.remove(&previous);
+        self.recency.insert(now, key.clone());
+        Some(value)
+    }
+
+    /// Add a block of `size` bytes as the most recently used, returning the
+    /// blocks evicted to stay within `capacity`
+    pub fn insert(&mut self, key: BlockKey, value: V, size: u64, capacity: u64) -> Vec<V> {
+        self.clock += 1;
+        let used = self.clock;
+        let block = IndexedBlock { value, size, used };
+        if let Some(old) = self.blocks.insert(key.clone(), block) {
+            self.recency.remove(&old.used);
+            self.bytes -= old.size;
+        } else {
+            self.paths.entry(key.0.clone()).or_default().blocks += 1;
+        }
+        self.recency.insert(used, key);
+        self.bytes += size;
+        self.evict_to(capacity)
+    }
+
+    /// Drop least recently used blocks until at most `capacity` bytes are
+    /// held, returning them
+    pub fn evict_to(&mut self, capacity: u64) -> Vec<V> {
+        let mut evicted = Vec::new();
+        while self.bytes > capacity {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            evicted.extend(self.remove(&key));
+        }
+        evicted
+    }
+
+    pub fn contains(&self, key: &BlockKey) -> bool {
+        self.blocks.contains_key(key)
+    }
+
+    pub fn remove(&mut self, key: &BlockKey) -> Option<V> {
+        let block = self.blocks.remove(key)?;
+        self.recency.remove(&block.used);
+        self.bytes -= block.size;
+        if let Some(path) = self.paths.get_mut(&key.0) {
+            path.blocks -= 1;
+            if path.blocks == 0 && path.version.is_none() {
+                self.paths.remove(&key.0);
+            }
+        }
+        Some(block.value)
+    }
+
+    /// Remove every block of `path`, returning them
+    pub fn drop_path(&mut self, path: &Path) -> Vec<V> {
+        let keys: Vec<_> = self
+            .blocks
+            .keys()
+            .filter(|(p, _)| p == path)
+            .cloned()
+            .collect();
+        let removed = keys.iter().filter_map(|key| self.remove(key)).collect();
+        self.paths.remove(path);
+        removed
+    }
+
+    /// Record the current version of `meta.location`, returning the blocks
+    /// removed because they belong to another version
+    pub fn validate(&mut self, meta: &ObjectMeta) -> Vec<V> {
+        let version = Version::of(meta);
+        let stale = self
+            .paths
+            .get(&meta.location)
+            .and_then(|path| path.version.as_ref())
+            .is_some_and(|v| *v != version);
+        let removed = if stale {
+            self.drop_path(&meta.location)
+        } else {
+            Vec::new()
+        };
+        if self.paths.len() >= MAX_IDLE_PATHS && !self.paths.contains_key(&meta.location) {
+            self.paths.retain(|_, path| path.blocks > 0);
+        }
+        let path = self.paths.entry(meta.location.clone()).or_default();
+        path.version = Some(version);
+        path.meta = Some(meta.clone());
+        removed
+    }
+
+    pub fn version(&self, path: &Path) -> Option<&Version> {
+        self.paths.get(path)?.version.as_ref()
+    }
+
+    pub fn meta(&self, path: &Path) -> Option<&ObjectMeta> {
+        self.paths.get(path)?.meta.as_ref()
+    }
+
+    /// Bytes held by the indexed blocks
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+/// Memory tiers built by [`CachedStore::in_memory`], resized together when
+/// `fetch_cache_bytes` is reloaded
+static MEMORY_CACHES: Mutex<Vec<Weak<BlockCache>>> = Mutex::new(Vec::new());
+
+/// Set the capacity of every live memory tier, evicting blocks past it
+///
+/// A capacity of 0 turns the tiers off until they are resized again.
+pub fn resize_memory_caches(capacity: u64) {
+    let mut caches = MEMORY_CACHES.lock().unwrap();
+    caches.retain(|cache| match cache.upgrade() {
+        Some(cache) => {
+            cache.resize(capacity);
+            true
+        }
+        None => false,
+    });
+}
+
+/// Blocks of objects held in memory
+#[derive(Debug)]
+pub struct BlockCache {
+    capacity: AtomicU64,
+    block_size: u64,
+    index: Mutex<BlockIndex<Bytes>>,
+}
+
+impl BlockCache {
+    pub fn new(capacity: u64, block_size: u64) -> Self {
+        Self {
+            capacity: AtomicU64::new(capacity),
+            block_size: block_size.max(1),
+            index: Mutex::new(BlockIndex::new()),
+        }
+    }
+
+    /// Bytes currently cached
+    pub fn size(&self) -> u64 {
+        self.index.lock().unwrap().bytes()
+    }
+
+    /// Change the capacity, evicting the least recently used blocks past it
+    pub fn resize(&self, capacity: u64) {
+        let mut index = self.index.lock().unwrap();
+        self.capacity.store(capacity, Ordering::Relaxed);
+        index.evict_to(capacity);
+    }
+
+    fn capacity(&self) -> u64 {
+        self.capacity.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl BlockTier for BlockCache {
+    const NAME: &'static str = "fetch_block";
+
+    fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    async fn get(&self, path: &Path, index: u64) -> Option<Bytes> {
+        self.index.lock().unwrap().get(&(path.clone(), index))
+    }
+
+    async fn insert(&self, path: &Path, index: u64, data: Bytes) {
+        let size = data.len() as u64;
+        let mut index_lock = self.index.lock().unwrap();
+        let capacity = self.capacity();
+        if size > capacity {
+            return;
+        }
+        index_lock.insert((path.clone(), index), data, size, capacity);
+    }
+
+    fn invalidate(&self, path: &Path) {
+        self.index.lock().unwrap().drop_path(path);
+    }
+
+    fn validate(&self, meta: &ObjectMeta) {
+        self.index.lock().unwrap().validate(meta);
+    }
+
+    fn known_meta(&self, path: &Path) -> Option<ObjectMeta> {
+        self.index.lock().unwrap().meta(path).cloned()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.capacity() > 0
+    }
+}
+
+/// A store whose range reads are served from a [`BlockTier`]
+///
+/// A read is cut into cache blocks; blocks already cached are used as-is
+/// and each run of missing blocks is fetched with one range request.
+/// Full `get`s bypass the cache. Writes through this store invalidate the
+/// paths they touch.
+#[derive(Debug)]
+pub struct CachedStore<T = BlockCache> {
+    inner: Arc<dyn ObjectStore>,
+    cache: T,
+}
+
+impl<T: BlockTier> CachedStore<T> {
+    pub fn new(inner: Arc<dyn ObjectStore>, cache: T) -> Self {
+        Self { inner, cache }
+    }
+}
+
+impl CachedStore<Arc<BlockCache>> {
+    /// A store caching `inner` in `fetch_cache_bytes` of memory
+    ///
+    /// The tier is built even when `fetch_cache_bytes` is 0, so that
+    /// [`resize_memory_caches`] can turn it on without a restart.
+    pub fn in_memory(inner: Arc<dyn ObjectStore>, config: &Config) -> Self {
+        let cache = Arc::new(BlockCache::new(
+            config.fetch_cache_bytes,
+            config.fetch_cache_block_size,
+        ));
+        MEMORY_CACHES.lock().unwrap().push(Arc::downgrade(&cache));
+        Self::new(inner, cache)
+    }
+}
+
+impl<T> fmt::Display for CachedStore<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[async_trait]
+impl<T: BlockTier> ObjectStore for CachedStore<T> {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.cache.invalidate(location);
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.cache.invalidate(location);
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    /// Range reads of the version the cache holds are served like
+    /// [`ObjectStore::get_range`]; any other read goes to the inner store
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let Some(range) = bounded_range(&options).filter(|_| self.cache.is_enabled()) else {
+            return self.inner.get_opts(location, options).await;
+        };
+        match self.cache.known_meta(location) {
+            Some(meta) if options.if_match.is_none() || options.if_match == meta.e_tag => {
+                let data = self.get_range(location, range.clone()).await?;
+                Ok(ranged_result(meta, range, data))
+            }
+            _ => {
+                let result = self.inner.get_opts(location, options).await?;
+                self.cache.validate(&result.meta);
+                Ok(result)
+            }
+        }
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        if range.is_empty() || !self.cache.is_enabled() {
+            return self.inner.get_range(location, range).await;
+        }
+        let block_size = self.cache.block_size() as usize;
+        let first = range.start / block_size;
+        let last = (range.end - 1) / block_size;
+
+        let mut blocks: Vec<Option<Bytes>> = Vec::with_capacity(last - first + 1);
+        for index in first..=last {
+            let block = self.cache.get(location, index as u64).await;
+            telemetry::record_cache(T::NAME, block.is_some());
+            blocks.push(block);
+        }
+
+        // Without a known size, stop at the end of the read: stores differ on
+        // whether a range past the end of the object is an error
+        let meta = self.cache.known_meta(location);
+        let size = meta.as_ref().map(|meta| meta.size);
+        let limit = size.unwrap_or(range.end);
+        // Blocks are only fetched from the version they are cached under
+        let if_match = meta.and_then(|meta| meta.e_tag);
+
+        let mut i = 0;
+        while i < blocks.len() {
+            if blocks[i].is_some() {
+                i += 1;
+                continue;
+            }
+            let run_end = (i..blocks.len())
+                .find(|&j| blocks[j].is_some())
+                .unwrap_or(blocks.len());
+            let fetch_start = (first + i) * block_size;
+            let fetch_end = ((first + run_end) * block_size).min(limit.max(range.end));
+            let data = match &if_match {
+                Some(e_tag) => {
+                    let options = GetOptions {
+                        if_match: Some(e_tag.clone()),
+                        range: Some((fetch_start..fetch_end).into()),
+                        ..GetOptions::default()
+                    };
+                    self.inner
+                        .get_opts(location, options)
+                        .await?
+                        .bytes()
+                        .await?
+                }
+                None => {
+                    self.inner
+                        .get_range(location, fetch_start..fetch_end)
+                        .await?
+                }
+            };
+            for (j, start) in (i..run_end).zip((0..data.len()).step_by(block_size)) {
+                let end = (start + block_size).min(data.len());
+                let block = data.slice(start..end);
+                // Only whole blocks, or the object's last block, are cached
+                if block.len() == block_size || size == Some(fetch_start + end) {
+                    self.cache
+                        .insert(location, (first + j) as u64, block.clone())
+                        .await;
+                }
+                blocks[j] = Some(block);
+            }
+            i = run_end;
+        }
+
+        let offset = range.start - first * block_size;
+        let blocks: Vec<Bytes> = blocks.into_iter().flatten().collect();
+        let data = match blocks.as_slice() {
+            [block] => block.clone(),
+            _ => {
+                let mut data = BytesMut::with_capacity(blocks.iter().map(Bytes::len).sum());
+                for block in &blocks {
+                    data.extend_from_slice(block);
+                }
+                data.freeze()
+            }
+        };
+        let end = (offset + range.len()).min(data.len());
+        Ok(data.slice(offset.min(end)..end))
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        let meta = self.inner.head(location).await?;
+        self.cache.validate(&meta);
+        Ok(meta)
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.cache.invalidate(location);
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.cache.invalidate(to);
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.cache.invalidate(to);
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    fn path() -> Path {
+        Path::from("clip.h265")
+    }
+
+    async fn cached_store(capacity: u64) -> (CachedStore, Arc<InMemory>, Bytes) {
+        let data: Bytes = (0..=255u8).cycle().take(1000).collect::<Vec<_>>().into();
+        let backend = Arc::new(InMemory::new());
+        backend.put(&path(), data.clone().into()).await.unwrap();
+        let store = CachedStore::new(backend.clone(), BlockCache::new(capacity, 100));
+        (store, backend, data)
+    }
+
+    #[tokio::test]
+    async fn test_reads_served_from_cache() {
+        let (store, backend, data) = cached_store(10_000).await;
+        store.head(&path()).await.unwrap();
+
+        let bytes = store.get_range(&path(), 150..420).await.unwrap();
+        assert_eq!(bytes, data.slice(150..420));
+        assert_eq!(store.cache.size(), 400);
+
+        // Remove the object underneath; cached blocks still answer
+        backend.delete(&path()).await.unwrap();
+        let bytes = store.get_range(&path(), 100..400).await.unwrap();
+        assert_eq!(bytes, data.slice(100..400));
+        assert!(store.get_range(&path(), 0..50).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_short_last_block() {
+        let (store, _, data) = cached_store(10_000).await;
+
+        // Before a HEAD the size is unknown: read no further than asked,
+        // and keep only whole blocks
+        let bytes = store.get_range(&path(), 0..50).await.unwrap();
+        assert_eq!(bytes, data.slice(0..50));
+        assert_eq!(store.cache.size(), 0);
+
+        let bytes = store.get_range(&path(), 950..1000).await.unwrap();
+        assert_eq!(bytes, data.slice(950..1000));
+        let bytes = store.get_range(&path(), 880..990).await.unwrap();
+        assert_eq!(bytes, data.slice(880..990));
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_evicted() {
+        let (store, backend, _) = cached_store(300).await;
+        store.head(&path()).await.unwrap();
+
+        store.get_range(&path(), 0..300).await.unwrap();
+        // Touch block 0, so block 1 is the oldest
+        store.get_range(&path(), 0..10).await.unwrap();
+        store.get_range(&path(), 500..510).await.unwrap();
+        assert_eq!(store.cache.size(), 300);
+
+        backend.delete(&path()).await.unwrap();
+        assert!(store.get_range(&path(), 0..10).await.is_ok());
+        assert!(store.get_range(&path(), 200..210).await.is_ok());
+        assert!(store.get_range(&path(), 100..110).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resize() {
+        let (store, backend, _) = cached_store(1000).await;
+        store.head(&path()).await.unwrap();
+        store.get_range(&path(), 0..500).await.unwrap();
+
+        // Shrinking evicts the oldest blocks straight away
+        store.cache.resize(200);
+        assert_eq!(store.cache.size(), 200);
+
+        // At 0 reads go to the backend
+        store.cache.resize(0);
+        assert_eq!(store.cache.size(), 0);
+        backend.delete(&path()).await.unwrap();
+        assert!(store.get_range(&path(), 300..400).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_changed_object_invalidates_blocks() {
+        let (store, backend, _) = cached_store(10_000).await;
+
+        store.head(&path()).await.unwrap();
+        store.get_range(&path(), 0..100).await.unwrap();
+
+        let replaced = Bytes::from(vec![7u8; 1000]);
+        backend.put(&path(), replaced.clone().into()).await.unwrap();
+        store.head(&path()).await.unwrap();
+        assert_eq!(store.cache.size(), 0);
+        let bytes = store.get_range(&path(), 0..100).await.unwrap();
+        assert_eq!(bytes, replaced.slice(0..100));
+
+        // Writes through the store drop the path's blocks at once
+        store.put(&path(), Bytes::new().into()).await.unwrap();
+        assert_eq!(store.cache.size(), 0);
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use object_store::aws::{AmazonS3Builder, AmazonS3ConfigKey};
+
+use crate::config::Config;
+
+/// Profile used when neither `s3_profile` nor `AWS_PROFILE` is set
+const DEFAULT_PROFILE: &str = "default";
+
+/// Where S3 credentials come from
+///
+/// Tried in this order, following the AWS SDKs. The last three are
+/// refreshed by the store itself as they expire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialSource {
+    /// `s3_access_key` / `s3_secret_key` from flags, env or config file
+    Config,
+    /// `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY`
+    Environment,
+    /// A profile in the shared credentials or config file
+    Profile(String),
+    /// `AWS_WEB_IDENTITY_TOKEN_FILE` + `AWS_ROLE_ARN` (EKS, IRSA)
+    WebIdentity,
+    /// `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI` (ECS task roles)
+    Container,
+    /// EC2 instance metadata (IMDS)
+    InstanceMetadata,
+}
+
+impl fmt::Display for CredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialSource::Config => f.write_str("configured access key"),
+            CredentialSource::Environment => f.write_str("AWS_ACCESS_KEY_ID"),
+            CredentialSource::Profile(name) => write!(f, "profile '{}'", name),
+            CredentialSource::WebIdentity => f.write_str("web identity token"),
+            CredentialSource::Container => f.write_str("container credentials"),
+            CredentialSource::InstanceMetadata => f.write_str("instance metadata"),
+        }
+    }
+}
+
+/// An access key, with a session token for temporary credentials
+#[derive(Clone, PartialEq, Eq)]
+struct StaticCredentials {
+    key_id: String,
+    secret_key: String,
+    token: Option<String>,
+}
+
+/// Add credentials to `builder` from the first source in the chain that has them
+pub fn with_credentials(
+    builder: AmazonS3Builder,
+    config: &Config,
+) -> Result<(AmazonS3Builder, CredentialSource)> {
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let (credentials, source) = resolve(config, env)?;
+
+    let builder = match credentials {
+        Some(credentials) => {
+            let builder = builder
+                .with_access_key_id(credentials.key_id)
+                .with_secret_access_key(credentials.secret_key);
+            match credentials.token {
+                Some(token) => builder.with_token(token),
+                None => builder,
+            }
+        }
+        // The store reads the web identity variables itself, but only sees
+        // the container URI when it is passed in
+        None => match env("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI") {
+            Some(uri) => {
+                builder.with_config(AmazonS3ConfigKey::ContainerCredentialsRelativeUri, uri)
+            }
+            None => builder,
+        },
+    };
+    Ok((builder, source))
+}
+
+/// Pick the credential source; static credentials are returned when found
+fn resolve(
+    config: &Config,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<(Option<StaticCredentials>, CredentialSource)> {
+    if let (Some(key_id), Some(secret_key)) = (&config.s3_access_key, &config.s3_secret_key) {
+        let credentials = StaticCredentials {
+            key_id: key_id.clone(),
+            secret_key: secret_key.clone(),
+            token: config.s3_session_token.clone(),
+        };
+        return Ok((Some(credentials), CredentialSource::Config));
+    }
+
+    if let (Some(key_id), Some(secret_key)) =
+        (env("AWS_ACCESS_KEY_ID"), env("AWS_SECRET_ACCESS_KEY"))
+    {
+        let credentials = StaticCredentials {
+            key_id,
+            secret_key,
+            token: env("AWS_SESSION_TOKEN"),
+        };
+        return Ok((Some(credentials), CredentialSource::Environment));
+    }
+
+    let explicit_profile = config.s3_profile.clone().or_else(|| env("AWS_PROFILE"));
+    let profile = explicit_profile
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    if let Some(credentials) = profile_credentials(&profile, &env) {
+        return Ok((Some(credentials), CredentialSource::Profile(profile)));
+    }
+    if explicit_profile.is_some() {
+        bail!(
+            "AWS profile '{}' has no access key in the shared credentials or config file",
+            profile
+        );
+    }
+
+    let source = if env("AWS_WEB_IDENTITY_TOKEN_FILE").is_some() && env("AWS_ROLE_ARN").is_some() {
+        CredentialSource::WebIdentity
+    } else if env("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI").is_some() {
+        CredentialSource::Container
+    } else {
+        CredentialSource::InstanceMetadata
+    };
+    Ok((None, source))
+}
+
+/// Static keys for `profile`, from `~/.aws/credentials` then `~/.aws/config`
+///
+/// Only `aws_access_key_id` / `aws_secret_access_key` / `aws_session_token`
+/// are read; SSO and `role_arn` profiles are not supported.
+fn profile_credentials(
+    profile: &str,
+    env: &impl Fn(&str) -> Option<String>,
+) -> Option<StaticCredentials> {
+    let aws_dir = env("HOME").map(|home| PathBuf::from(home).join(".aws"));
+    let credentials_file = env("AWS_SHARED_CREDENTIALS_FILE")
+        .map(PathBuf::from)
+        .or_else(|| aws_dir.as_ref().map(|dir| dir.join("credentials")));
+    let config_file = env("AWS_CONFIG_FILE")
+        .map(PathBuf::from)
+        .or_else(|| aws_dir.as_ref().map(|dir| dir.join("config")));
+
+    // The config file prefixes every profile but the default with `profile `
+    let config_section = match profile {
+        DEFAULT_PROFILE => DEFAULT_PROFILE.to_string(),
+        name => format!("profile {}", name),
+    };
+
+    [
+        (credentials_file, profile.to_string()),
+        (config_file, config_section),
+    ]
+    .into_iter()
+    .find_map(|(file, section)| {
+        let text = std::fs::read_to_string(file?).ok()?;
+        parse_profile(&text, &section)
+    })
+}
+
+/// Read the keys of one `[section]` of an INI-style AWS file
+fn parse_profile(text: &str, section: &str) -> Option<StaticCredentials> {
+    let mut in_section = false;
+    let mut key_id = None;
+    let mut secret_key = None;
+    let mut token = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_section = name.trim() == section;
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = Some(value.trim().to_string());
+        match key.trim() {
+            "aws_access_key_id" => key_id = value,
+            "aws_secret_access_key" => secret_key = value,
+            "aws_session_token" => token = value,
+            _ => {}
+        }
+    }
+
+    Some(StaticCredentials {
+        key_id: key_id?,
+        secret_key: secret_key?,
+        token,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn source(config: &Config, vars: &[(&str, &str)]) -> CredentialSource {
+        resolve(config, env(vars)).unwrap().1
+    }
+
+    #[test]
+    fn test_credential_chain_order() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let credentials = dir.path().join("credentials");
+        std::fs::write(
+            &credentials,
+            "[default]\naws_access_key_id = AKIADEFAULT\naws_secret_access_key = s\n",
+        )
+        .unwrap();
+        let credentials = credentials.to_str().unwrap();
+
+        let configured = Config {
+            s3_access_key: Some("AKIACONFIG".to_string()),
+            s3_secret_key: Some("secret".to_string()),
+            ..Config::default()
+        };
+        let chain = Config::default();
+        let env_keys = [
+            ("AWS_ACCESS_KEY_ID", "AKIAENV"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+        ];
+
+        assert_eq!(source(&configured, &env_keys), CredentialSource::Config);
+        assert_eq!(source(&chain, &env_keys), CredentialSource::Environment);
+        assert_eq!(
+            source(&chain, &[("AWS_SHARED_CREDENTIALS_FILE", credentials)]),
+            CredentialSource::Profile("default".to_string())
+        );
+        assert_eq!(
+            source(
+                &chain,
+                &[
+                    ("AWS_WEB_IDENTITY_TOKEN_FILE", "/var/run/token"),
+                    ("AWS_ROLE_ARN", "arn:aws:iam::1:role/r"),
+                ]
+            ),
+            CredentialSource::WebIdentity
+        );
+        assert_eq!(
+            source(
+                &chain,
+                &[("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI", "/v2/creds")]
+            ),
+            CredentialSource::Container
+        );
+        assert_eq!(source(&chain, &[]), CredentialSource::InstanceMetadata);
+
+        // A named profile that does not exist is an error, not a fallthrough
+        assert!(resolve(
+            &chain,
+            env(&[
+                ("AWS_SHARED_CREDENTIALS_FILE", credentials),
+                ("AWS_PROFILE", "missing")
+            ])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let text = "\
+# comment
+[default]
+aws_access_key_id = AKIADEFAULT
+aws_secret_access_key = default-secret
+
+[profile ci]
+region = eu-west-1
+aws_access_key_id=AKIACI
+aws_secret_access_key=ci-secret
+aws_session_token = token
+";
+        let ci = parse_profile(text, "profile ci").unwrap();
+        assert_eq!(ci.key_id, "AKIACI");
+        assert_eq!(ci.secret_key, "ci-secret");
+        assert_eq!(ci.token.as_deref(), Some("token"));
+
+        let default = parse_profile(text, "default").unwrap();
+        assert_eq!(default.key_id, "AKIADEFAULT");
+        assert_eq!(default.token, None);
+
+        assert!(parse_profile(text, "other").is_none());
+    }
+}
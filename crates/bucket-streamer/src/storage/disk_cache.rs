@@ -0,0 +1,712 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use object_store::{path::Path, ObjectMeta};
+use tracing::{debug, info, warn};
+
+use super::backend::object_url;
+use super::cache::{BlockIndex, BlockTier, Version};
+use crate::config::Config;
+use crate::telemetry;
+
+/// Marks a block file written by this cache
+const MAGIC: &[u8; 4] = b"BSDC";
+
+/// Block file layout version
+const FORMAT: u8 = 1;
+
+/// Magic, format, block size, index, version, CRC, length and path length
+const HEADER_LEN: usize = 4 + 1 + 8 + 8 + 8 + 4 + 4 + 2;
+
+/// Distinguishes temporary files of concurrent writes
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Blocks of objects kept in files under a local directory
+///
+/// Each block is one file, named after a hash of its object's path and its
+/// index, holding a header with the object path, the version of the object
+/// it was read from and a CRC32 of the data. Blocks are written to a
+/// temporary file and renamed into place, and the directory is scanned on
+/// startup, so cached blocks survive restarts; file modification times
+/// carry the least recently used order across them. Two blocks whose names
+/// collide share a file, which holds whichever was written last.
+///
+/// A block is only served once a `HEAD` since startup has confirmed the
+/// object is still the version it was read from. Blocks that fail their
+/// integrity check are deleted and read again from the store.
+#[derive(Debug)]
+pub struct DiskCache {
+    root: PathBuf,
+    capacity: u64,
+    block_size: u64,
+    index: Arc<Mutex<BlockIndex<PathBuf>>>,
+}
+
+/// Indexes the block files left by earlier runs
+#[derive(Debug)]
+struct Scan {
+    root: PathBuf,
+    capacity: u64,
+    block_size: u64,
+    index: Arc<Mutex<BlockIndex<PathBuf>>>,
+}
+
+/// Why a block file can't be served
+#[derive(Debug)]
+enum ReadError {
+    Io(io::Error),
+    /// Holds another object's block, after a hash collision
+    Mismatch,
+    /// Read from an older version of the object
+    Stale,
+    Corrupt(&'static str),
+}
+
+/// What the header of a block file records
+#[derive(Debug, PartialEq, Eq)]
+struct BlockHeader {
+    block_size: u64,
+    index: u64,
+    version: u64,
+    crc: u32,
+    len: u32,
+    path: String,
+}
+
+impl DiskCache {
+    /// A cache for the store `config` describes, under its own
+    /// subdirectory of `dir`
+    ///
+    /// Blocks from earlier runs are picked up on a blocking thread, and are
+    /// read again from the store until it has found them.
+    pub fn from_config(config: &Config, dir: &str) -> io::Result<Self> {
+        let store = fnv1a64(&[object_url(config, "").as_bytes()]);
+        let cache = Self::create(
+            FsPath::new(dir).join(format!("{:016x}", store)),
+            config.disk_cache_bytes,
+            config.fetch_cache_block_size,
+        )?;
+        let scan = cache.scan();
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(move || {
+                    if let Err(e) = scan.run() {
+                        warn!("Failed to scan disk cache {}: {}", scan.root.display(), e);
+                    }
+                });
+            }
+            Err(_) => scan.run()?,
+        }
+        Ok(cache)
+    }
+
+    /// Open the cache in `root`, picking up blocks from earlier runs
+    pub fn open(root: impl Into<PathBuf>, capacity: u64, block_size: u64) -> io::Result<Self> {
+        let cache = Self::create(root, capacity, block_size)?;
+        cache.scan().run()?;
+        Ok(cache)
+    }
+
+    fn create(root: impl Into<PathBuf>, capacity: u64, block_size: u64) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root,
+            capacity,
+            block_size: block_size.max(1),
+            index: Arc::new(Mutex::new(BlockIndex::new())),
+        })
+    }
+
+    /// Bytes of block files currently cached
+    pub fn size(&self) -> u64 {
+        self.index.lock().unwrap().bytes()
+    }
+
+    fn scan(&self) -> Scan {
+        Scan {
+            root: self.root.clone(),
+            capacity: self.capacity,
+            block_size: self.block_size,
+            index: self.index.clone(),
+        }
+    }
+
+    fn block_file(&self, path: &Path, index: u64) -> PathBuf {
+        let hash = format!("{:016x}", fnv1a64(&[path.as_ref().as_bytes()]));
+        self.root
+            .join(&hash[..2])
+            .join(format!("{}-{}.blk", hash, index))
+    }
+
+    /// Fingerprint of the version of `path` last seen by a `HEAD`
+    fn version(&self, path: &Path) -> Option<u64> {
+        self.index.lock().unwrap().version(path).map(fingerprint)
+    }
+}
+
+impl Scan {
+    /// Index the block files found, newest first while they fit, and drop
+    /// temporary files of earlier runs, unreadable blocks and the rest
+    fn run(&self) -> io::Result<()> {
+        let writes = format!(".{}-", std::process::id());
+        let mut found = Vec::new();
+        let mut dropped = Vec::new();
+        for dir in fs::read_dir(&self.root)? {
+            let dir = dir?.path();
+            if !dir.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&dir)? {
+                let file = file?.path();
+                match file.extension().and_then(|ext| ext.to_str()) {
+                    // Writes of this process may be in flight
+                    Some("tmp") if !file.to_string_lossy().contains(&writes) => dropped.push(file),
+                    Some("blk") => match read_header(&file) {
+                        Ok((header, size, modified)) if header.block_size == self.block_size => {
+                            found.push((modified, header, size, file));
+                        }
+                        Ok(_) => dropped.push(file),
+                        Err(e) => {
+                            debug!("Dropping cached block {}: {:?}", file.display(), e);
+                            dropped.push(file);
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        found.sort_by_key(|(modified, ..)| std::cmp::Reverse(*modified));
+        let bytes = {
+            let mut index = self.index.lock().unwrap();
+            let mut room = self.capacity.saturating_sub(index.bytes());
+            let mut kept = Vec::new();
+            for (_, header, size, file) in found {
+                let key = (Path::from(header.path), header.index);
+                // Blocks cached since startup are newer than any found here
+                if index.contains(&key) {
+                    continue;
+                }
+                if size > room {
+                    room = 0;
+                    dropped.push(file);
+                    continue;
+                }
+                room -= size;
+                kept.push((key, file, size));
+            }
+            for (key, file, size) in kept.into_iter().rev() {
+                dropped.extend(index.insert(key, file, size, self.capacity));
+            }
+            index.bytes()
+        };
+        dropped.iter().for_each(|file| remove_file(file));
+        info!(
+            "Disk cache at {} holds {} bytes",
+            self.root.display(),
+            bytes
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlockTier for DiskCache {
+    const NAME: &'static str = "disk_block";
+
+    fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    async fn get(&self, path: &Path, index: u64) -> Option<Bytes> {
+        let key = (path.clone(), index);
+        let (file, version) = {
+            let mut blocks = self.index.lock().unwrap();
+            // Until a HEAD says which version is current, nothing is served
+            let version = fingerprint(blocks.version(path)?);
+            (blocks.get(&key)?, version)
+        };
+
+        let expected = BlockHeader {
+            block_size: self.block_size,
+            index,
+            version,
+            crc: 0,
+            len: 0,
+            path: path.to_string(),
+        };
+        let read = {
+            let file = file.clone();
+            tokio::task::spawn_blocking(move || read_block(&file, &expected)).await
+        };
+        let error = match read {
+            Ok(Ok(data)) => {
+                touch(file);
+                return Some(data);
+            }
+            Ok(Err(e)) => e,
+            Err(_) => return None,
+        };
+
+        match &error {
+            ReadError::Corrupt(reason) => {
+                warn!("Cached block {} is corrupt: {}", file.display(), reason);
+                telemetry::record_disk_cache_corrupt();
+            }
+            ReadError::Io(e) if e.kind() != io::ErrorKind::NotFound => {
+                warn!("Failed to read cached block {}: {}", file.display(), e);
+            }
+            e => debug!("Not serving cached block {}: {:?}", file.display(), e),
+        }
+        self.index.lock().unwrap().remove(&key);
+        // A colliding block belongs to another object that may still use it
+        if !matches!(error, ReadError::Mismatch) {
+            remove_files(vec![file]).await;
+        }
+        None
+    }
+
+    async fn insert(&self, path: &Path, index: u64, data: Bytes) {
+        let Some(version) = self.version(path) else {
+            return;
+        };
+        let file = self.block_file(path, index);
+        let header = BlockHeader {
+            block_size: self.block_size,
+            index,
+            version,
+            crc: crc32fast::hash(&data),
+            len: data.len() as u32,
+            path: path.to_string(),
+        };
+        let size = (HEADER_LEN + header.path.len() + data.len()) as u64;
+        if size > self.capacity || data.len() > u32::MAX as usize {
+            return;
+        }
+
+        let written = {
+            let file = file.clone();
+            tokio::task::spawn_blocking(move || {
+                let previous = read_header(&file).ok();
+                write_block(&file, &header, &data).map(|()| previous)
+            })
+            .await
+        };
+        let previous = match written {
+            Ok(Ok(previous)) => previous,
+            Ok(Err(e)) => {
+                warn!("Failed to cache block {}: {}", file.display(), e);
+                return;
+            }
+            Err(_) => return,
+        };
+
+        let key = (path.clone(), index);
+        let evicted = {
+            let mut blocks = self.index.lock().unwrap();
+            // After a hash collision the file held another block, which is
+            // gone now; leaving its entry would count the file twice
+            if let Some((previous, ..)) = previous {
+                let previous = (Path::from(previous.path), previous.index);
+                if previous != key {
+                    blocks.remove(&previous);
+                }
+            }
+            // The object changed while the block was being written
+            if blocks.version(path).map(fingerprint) != Some(version) {
+                vec![file]
+            } else {
+                blocks.insert(key, file, size, self.capacity)
+            }
+        };
+        remove_files(evicted).await;
+    }
+
+    fn invalidate(&self, path: &Path) {
+        let removed = self.index.lock().unwrap().drop_path(path);
+        spawn_remove_files(removed);
+    }
+
+    fn validate(&self, meta: &ObjectMeta) {
+        let removed = self.index.lock().unwrap().validate(meta);
+        spawn_remove_files(removed);
+    }
+
+    fn known_meta(&self, path: &Path) -> Option<ObjectMeta> {
+        self.index.lock().unwrap().meta(path).cloned()
+    }
+}
+
+impl BlockHeader {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + self.path.len());
+        buf.extend_from_slice(MAGIC);
+        buf.push(FORMAT);
+        buf.extend_from_slice(&self.block_size.to_le_bytes());
+        buf.extend_from_slice(&self.index.to_le_bytes());
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        buf.extend_from_slice(&self.crc.to_le_bytes());
+        buf.extend_from_slice(&self.len.to_le_bytes());
+        buf.extend_from_slice(&(self.path.len() as u16).to_le_bytes());
+        buf.extend_from_slice(self.path.as_bytes());
+        buf
+    }
+
+    /// Parse the fixed part of a header, returning it and the length of the
+    /// path that follows
+    fn decode(buf: &[u8; HEADER_LEN]) -> Result<(Self, usize), ReadError> {
+        if &buf[..4] != MAGIC {
+            return Err(ReadError::Corrupt("bad magic"));
+        }
+        if buf[4] != FORMAT {
+            return Err(ReadError::Corrupt("unknown format"));
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(buf[at..at + 8].try_into().unwrap());
+        let u32_at = |at: usize| u32::from_le_bytes(buf[at..at + 4].try_into().unwrap());
+        let header = Self {
+            block_size: u64_at(5),
+            index: u64_at(13),
+            version: u64_at(21),
+            crc: u32_at(29),
+            len: u32_at(33),
+            path: String::new(),
+        };
+        let path_len = u16::from_le_bytes([buf[37], buf[38]]) as usize;
+        Ok((header, path_len))
+    }
+}
+
+/// Read a header from `reader`, leaving it at the start of the data
+fn decode_header(reader: &mut impl Read) -> Result<BlockHeader, ReadError> {
+    let mut fixed = [0u8; HEADER_LEN];
+    reader.read_exact(&mut fixed).map_err(ReadError::Io)?;
+    let (mut header, path_len) = BlockHeader::decode(&fixed)?;
+    let mut path = vec![0u8; path_len];
+    reader.read_exact(&mut path).map_err(ReadError::Io)?;
+    header.path = String::from_utf8(path).map_err(|_| ReadError::Corrupt("bad path"))?;
+    Ok(header)
+}
+
+/// The header, file size and modification time of a block file
+fn read_header(file: &FsPath) -> Result<(BlockHeader, u64, SystemTime), ReadError> {
+    let mut reader = File::open(file).map_err(ReadError::Io)?;
+    let metadata = reader.metadata().map_err(ReadError::Io)?;
+    let header = decode_header(&mut reader)?;
+    let expected = (HEADER_LEN + header.path.len()) as u64 + u64::from(header.len);
+    if metadata.len() != expected {
+        return Err(ReadError::Corrupt("truncated"));
+    }
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    Ok((header, metadata.len(), modified))
+}
+
+/// The data of a block file, checked against the block it should hold
+fn read_block(file: &FsPath, expected: &BlockHeader) -> Result<Bytes, ReadError> {
+    let mut reader = File::open(file).map_err(ReadError::Io)?;
+    let header = decode_header(&mut reader)?;
+    if header.path != expected.path || header.index != expected.index {
+        return Err(ReadError::Mismatch);
+    }
+    if header.block_size != expected.block_size || header.version != expected.version {
+        return Err(ReadError::Stale);
+    }
+    let mut data = Vec::with_capacity(header.len as usize);
+    reader.read_to_end(&mut data).map_err(ReadError::Io)?;
+    if data.len() != header.len as usize {
+        return Err(ReadError::Corrupt("length mismatch"));
+    }
+    if crc32fast::hash(&data) != header.crc {
+        return Err(ReadError::Corrupt("checksum mismatch"));
+    }
+    Ok(data.into())
+}
+
+/// Write a block file atomically, so readers and restarts never see a
+/// partial block
+fn write_block(file: &FsPath, header: &BlockHeader, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut name = file.as_os_str().to_owned();
+    name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp = PathBuf::from(name);
+
+    let written = File::create(&tmp).and_then(|mut out| {
+        out.write_all(&header.encode())?;
+        out.write_all(data)
+    });
+    match written.and_then(|()| fs::rename(&tmp, file)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Mark a block file recently used, for the order after a restart
+fn touch(file: PathBuf) {
+    tokio::task::spawn_blocking(move || {
+        let _ = File::options()
+            .write(true)
+            .open(&file)
+            .and_then(|f| f.set_modified(SystemTime::now()));
+    });
+}
+
+fn remove_file(file: &FsPath) {
+    if let Err(e) = fs::remove_file(file) {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("Failed to remove cached block {}: {}", file.display(), e);
+        }
+    }
+}
+
+async fn remove_files(files: Vec<PathBuf>) {
+    if files.is_empty() {
+        return;
+    }
+    let _ = tokio::task::spawn_blocking(move || files.iter().for_each(|f| remove_file(f))).await;
+}
+
+/// Remove files from a synchronous caller without waiting for it
+fn spawn_remove_files(files: Vec<PathBuf>) {
+    if files.is_empty() {
+        return;
+    }
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(move || files.iter().for_each(|f| remove_file(f)));
+        }
+        Err(_) => files.iter().for_each(|f| remove_file(f)),
+    }
+}
+
+/// Stable identifier of an object version, stored in each block's header
+fn fingerprint(version: &Version) -> u64 {
+    fnv1a64(&[
+        &(version.size as u64).to_le_bytes(),
+        &version.last_modified.to_le_bytes(),
+        version.e_tag.as_deref().unwrap_or("").as_bytes(),
+    ])
+}
+
+/// FNV-1a over `parts`; unlike `DefaultHasher`, stable across builds
+pub(crate) fn fnv1a64(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+    use object_store::ObjectStore;
+    use std::time::Duration;
+
+    use crate::storage::cache::CachedStore;
+
+    fn path() -> Path {
+        Path::from("clip.h265")
+    }
+
+    /// A backend holding `len` bytes at `clip.h265`, and its metadata
+    async fn backend(len: usize) -> (Arc<InMemory>, ObjectMeta, Bytes) {
+        let data: Bytes = (0..=255u8).cycle().take(len).collect::<Vec<_>>().into();
+        let backend = Arc::new(InMemory::new());
+        backend.put(&path(), data.clone().into()).await.unwrap();
+        let meta = backend.head(&path()).await.unwrap();
+        (backend, meta, data)
+    }
+
+    fn block_files(root: &FsPath) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for dir in fs::read_dir(root).unwrap() {
+            for file in fs::read_dir(dir.unwrap().path()).unwrap() {
+                files.push(file.unwrap().path());
+            }
+        }
+        files
+    }
+
+    #[tokio::test]
+    async fn test_blocks_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, meta, data) = backend(250).await;
+
+        let cache = DiskCache::open(dir.path(), 1 << 20, 100).unwrap();
+        // Nothing is cached before the version is known
+        cache.insert(&path(), 0, data.slice(0..100)).await;
+        assert_eq!(cache.size(), 0);
+        cache.validate(&meta);
+        cache.insert(&path(), 0, data.slice(0..100)).await;
+        cache.insert(&path(), 2, data.slice(200..250)).await;
+        assert_eq!(cache.get(&path(), 0).await, Some(data.slice(0..100)));
+        drop(cache);
+
+        let cache = DiskCache::open(dir.path(), 1 << 20, 100).unwrap();
+        assert!(cache.size() > 150);
+        assert_eq!(cache.get(&path(), 0).await, None);
+        cache.validate(&meta);
+        assert_eq!(cache.get(&path(), 0).await, Some(data.slice(0..100)));
+        assert_eq!(cache.get(&path(), 2).await, Some(data.slice(200..250)));
+        assert_eq!(cache.get(&path(), 1).await, None);
+
+        // A different block size starts over
+        let cache = DiskCache::open(dir.path(), 1 << 20, 50).unwrap();
+        assert_eq!(cache.size(), 0);
+        assert!(block_files(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_blocks_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, meta, data) = backend(250).await;
+
+        let cache = DiskCache::open(dir.path(), 1 << 20, 100).unwrap();
+        cache.validate(&meta);
+        cache.insert(&path(), 0, data.slice(0..100)).await;
+        drop(cache);
+
+        backend.put(&path(), vec![7u8; 300].into()).await.unwrap();
+        let cache = DiskCache::open(dir.path(), 1 << 20, 100).unwrap();
+        cache.validate(&backend.head(&path()).await.unwrap());
+        assert_eq!(cache.get(&path(), 0).await, None);
+        assert_eq!(cache.size(), 0);
+        assert!(block_files(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, meta, data) = backend(400).await;
+        let block = (HEADER_LEN + "clip.h265".len() + 100) as u64;
+
+        let cache = DiskCache::open(dir.path(), 2 * block, 100).unwrap();
+        cache.validate(&meta);
+        cache.insert(&path(), 0, data.slice(0..100)).await;
+        cache.insert(&path(), 1, data.slice(100..200)).await;
+        assert!(cache.get(&path(), 0).await.is_some());
+        cache.insert(&path(), 2, data.slice(200..300)).await;
+
+        assert_eq!(cache.size(), 2 * block);
+        assert_eq!(block_files(dir.path()).len(), 2);
+        assert!(cache.get(&path(), 1).await.is_none());
+        assert!(cache.get(&path(), 0).await.is_some());
+        assert!(cache.get(&path(), 2).await.is_some());
+        drop(cache);
+
+        // Reopening with less room keeps what fits
+        let cache = DiskCache::open(dir.path(), block, 100).unwrap();
+        assert_eq!(cache.size(), block);
+        assert_eq!(block_files(dir.path()).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_block_refetched() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _, data) = backend(250).await;
+        let cache = DiskCache::open(dir.path(), 1 << 20, 100).unwrap();
+        let store = CachedStore::new(backend, cache);
+
+        store.head(&path()).await.unwrap();
+        let range = 0..100;
+        assert_eq!(
+            store.get_range(&path(), range.clone()).await.unwrap(),
+            data.slice(range.clone())
+        );
+
+        let files = block_files(dir.path());
+        assert_eq!(files.len(), 1);
+        let mut contents = fs::read(&files[0]).unwrap();
+        *contents.last_mut().unwrap() ^= 0xff;
+        fs::write(&files[0], contents).unwrap();
+
+        assert_eq!(
+            store.get_range(&path(), range.clone()).await.unwrap(),
+            data.slice(range)
+        );
+        // Rewritten from the store
+        let header = decode_header(&mut File::open(&files[0]).unwrap()).unwrap();
+        assert_eq!(header.crc, crc32fast::hash(&data.slice(0..100)));
+        assert_eq!(read_block(&files[0], &header).unwrap(), data.slice(0..100));
+    }
+
+    #[tokio::test]
+    async fn test_colliding_block_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, meta, data) = backend(250).await;
+        let other = Path::from("other.h265");
+        backend.put(&other, vec![7u8; 100].into()).await.unwrap();
+        let other_meta = backend.head(&other).await.unwrap();
+
+        let cache = DiskCache::open(dir.path(), 1 << 20, 100).unwrap();
+        cache.validate(&meta);
+        cache.validate(&other_meta);
+        // `other` block 0 hashed to the file `clip.h265` block 0 uses
+        let file = cache.block_file(&path(), 0);
+        let header = BlockHeader {
+            block_size: 100,
+            index: 0,
+            version: cache.version(&other).unwrap(),
+            crc: crc32fast::hash(&[7u8; 100]),
+            len: 100,
+            path: other.to_string(),
+        };
+        write_block(&file, &header, &[7u8; 100]).unwrap();
+        let size = fs::metadata(&file).unwrap().len();
+        cache
+            .index
+            .lock()
+            .unwrap()
+            .insert((other.clone(), 0), file.clone(), size, 1 << 20);
+
+        cache.insert(&path(), 0, data.slice(0..100)).await;
+        assert_eq!(cache.size(), fs::metadata(&file).unwrap().len());
+        assert_eq!(cache.get(&other, 0).await, None);
+        assert_eq!(cache.get(&path(), 0).await, Some(data.slice(0..100)));
+    }
+
+    #[tokio::test]
+    async fn test_scan_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, meta, data) = backend(250).await;
+        let root = dir.path().to_str().unwrap();
+        let config = Config {
+            fetch_cache_block_size: 100,
+            ..Config::default()
+        };
+
+        let cache = DiskCache::from_config(&config, root).unwrap();
+        cache.validate(&meta);
+        cache.insert(&path(), 0, data.slice(0..100)).await;
+        assert!(cache.size() > 0);
+        drop(cache);
+
+        let cache = DiskCache::from_config(&config, root).unwrap();
+        cache.validate(&meta);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while cache.size() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(cache.get(&path(), 0).await, Some(data.slice(0..100)));
+    }
+}
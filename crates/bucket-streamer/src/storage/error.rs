@@ -0,0 +1,247 @@
+use std::time::Duration;
+
+/// Broad cause of a failed storage operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The object does not exist
+    NotFound,
+    /// The store refused our credentials
+    PermissionDenied,
+    /// The store asked us to slow down (429, S3 `SlowDown`)
+    Throttled,
+    /// Timeouts, connection failures and 5xx responses
+    Transient,
+    /// The store's circuit breaker is open after repeated failures
+    Unavailable,
+    /// Anything retrying will not fix
+    Permanent,
+}
+
+impl ErrorKind {
+    pub fn classify(e: &object_store::Error) -> Self {
+        match e {
+            object_store::Error::NotFound { .. } => ErrorKind::NotFound,
+            object_store::Error::PermissionDenied { .. }
+            | object_store::Error::Unauthenticated { .. } => ErrorKind::PermissionDenied,
+            object_store::Error::Generic { source, .. } => classify_cause(source.as_ref()),
+            _ => ErrorKind::Permanent,
+        }
+    }
+
+    /// Whether another attempt may succeed
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorKind::Throttled | ErrorKind::Transient)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::PermissionDenied => "permission_denied",
+            ErrorKind::Throttled => "throttled",
+            ErrorKind::Transient => "transient",
+            ErrorKind::Unavailable => "unavailable",
+            ErrorKind::Permanent => "permanent",
+        }
+    }
+}
+
+/// A failed storage operation, classified by [`ErrorKind`]
+///
+/// The message tells clients what happened and whether to retry, and is
+/// safe to return to them; the backend error it wraps can name buckets,
+/// hosts or local paths and is only logged.
+#[derive(Debug, thiserror::Error)]
+#[error("{}", message(.kind))]
+pub struct StorageError {
+    pub kind: ErrorKind,
+    #[source]
+    source: object_store::Error,
+}
+
+impl From<object_store::Error> for StorageError {
+    fn from(source: object_store::Error) -> Self {
+        Self {
+            kind: ErrorKind::classify(&source),
+            source,
+        }
+    }
+}
+
+impl StorageError {
+    /// Whether a conditional read failed because the object changed
+    pub fn is_precondition(&self) -> bool {
+        matches!(self.source, object_store::Error::Precondition { .. })
+    }
+}
+
+fn message(kind: &ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::NotFound => "Not found",
+        ErrorKind::PermissionDenied => "Storage denied access to the video",
+        ErrorKind::Throttled => "Storage is throttling requests; retry shortly",
+        ErrorKind::Transient => "Storage is temporarily unreachable; retry shortly",
+        ErrorKind::Unavailable => "Storage is unavailable after repeated failures; retry later",
+        ErrorKind::Permanent => "Storage request failed",
+    }
+}
+
+/// Failures raised by the read policy itself rather than the backend
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("attempt timed out after {0:?}")]
+    TimedOut(Duration),
+
+    #[error("circuit breaker open")]
+    CircuitOpen,
+}
+
+impl PolicyError {
+    pub fn into_store_error(self) -> object_store::Error {
+        object_store::Error::Generic {
+            store: "ReadPolicy",
+            source: Box::new(self),
+        }
+    }
+}
+
+/// Classify the source of a `Generic` error
+///
+/// Only typed causes are trusted: our own policy errors, I/O errors and the
+/// `reqwest` error object_store wraps around timeouts, connection failures
+/// and 5xx responses it gave up retrying. object_store maps 401, 403, 404
+/// and 412 to their own variants; other 4xx responses (including 429) come
+/// back in its private error type, so they, like anything unrecognised, are
+/// permanent.
+fn classify_cause(source: &(dyn std::error::Error + 'static)) -> ErrorKind {
+    let mut cause = Some(source);
+    while let Some(e) = cause {
+        if let Some(e) = e.downcast_ref::<PolicyError>() {
+            return match e {
+                PolicyError::TimedOut(_) => ErrorKind::Transient,
+                PolicyError::CircuitOpen => ErrorKind::Unavailable,
+            };
+        }
+        if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            return classify_io(e.kind());
+        }
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            return classify_reqwest(e);
+        }
+        cause = e.source();
+    }
+    ErrorKind::Permanent
+}
+
+fn classify_reqwest(e: &reqwest::Error) -> ErrorKind {
+    use reqwest::StatusCode;
+    match e.status() {
+        // S3 answers `SlowDown` with 503
+        Some(StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE) => {
+            ErrorKind::Throttled
+        }
+        Some(status) if status == StatusCode::REQUEST_TIMEOUT || status.is_server_error() => {
+            ErrorKind::Transient
+        }
+        Some(_) => ErrorKind::Permanent,
+        None if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() => {
+            ErrorKind::Transient
+        }
+        None => ErrorKind::Permanent,
+    }
+}
+
+fn classify_io(kind: std::io::ErrorKind) -> ErrorKind {
+    use std::io::ErrorKind as Io;
+    match kind {
+        Io::NotFound => ErrorKind::NotFound,
+        Io::PermissionDenied => ErrorKind::PermissionDenied,
+        Io::TimedOut
+        | Io::Interrupted
+        | Io::WouldBlock
+        | Io::ConnectionRefused
+        | Io::ConnectionReset
+        | Io::ConnectionAborted
+        | Io::BrokenPipe
+        | Io::UnexpectedEof => ErrorKind::Transient,
+        _ => ErrorKind::Permanent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generic(source: Box<dyn std::error::Error + Send + Sync>) -> object_store::Error {
+        object_store::Error::Generic {
+            store: "S3",
+            source,
+        }
+    }
+
+    /// The `reqwest` error for a response with `status`
+    fn http_error(status: u16) -> Box<dyn std::error::Error + Send + Sync> {
+        let response = axum::http::Response::builder()
+            .status(status)
+            .body("")
+            .unwrap();
+        let error = reqwest::Response::from(response)
+            .error_for_status()
+            .unwrap_err();
+        Box::new(error)
+    }
+
+    #[test]
+    fn test_classify() {
+        let not_found = object_store::Error::NotFound {
+            path: "a.h265".to_string(),
+            source: "404".into(),
+        };
+        assert_eq!(ErrorKind::classify(&not_found), ErrorKind::NotFound);
+        let precondition = object_store::Error::Precondition {
+            path: "a.h265".to_string(),
+            source: "412".into(),
+        };
+        assert_eq!(ErrorKind::classify(&precondition), ErrorKind::Permanent);
+
+        let cases = [
+            (429, ErrorKind::Throttled),
+            (503, ErrorKind::Throttled),
+            (500, ErrorKind::Transient),
+            (408, ErrorKind::Transient),
+            (416, ErrorKind::Permanent),
+        ];
+        for (status, kind) in cases {
+            assert_eq!(
+                ErrorKind::classify(&generic(http_error(status))),
+                kind,
+                "{}",
+                status
+            );
+        }
+
+        // Messages are not parsed
+        let unknown = generic("Client error with status 503 Service Unavailable".into());
+        assert_eq!(ErrorKind::classify(&unknown), ErrorKind::Permanent);
+
+        let timed_out = PolicyError::TimedOut(Duration::from_secs(1)).into_store_error();
+        assert_eq!(ErrorKind::classify(&timed_out), ErrorKind::Transient);
+        let open = PolicyError::CircuitOpen.into_store_error();
+        assert_eq!(ErrorKind::classify(&open), ErrorKind::Unavailable);
+
+        let io = object_store::Error::Generic {
+            store: "LocalFileSystem",
+            source: Box::new(std::io::Error::from(std::io::ErrorKind::PermissionDenied)),
+        };
+        assert_eq!(ErrorKind::classify(&io), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_message_hides_backend_error() {
+        let error = StorageError::from(object_store::Error::PermissionDenied {
+            path: "a.h265".to_string(),
+            source: "Client error with status 403 Forbidden: s3://secret-bucket/a.h265".into(),
+        });
+        assert_eq!(error.kind, ErrorKind::PermissionDenied);
+        assert_eq!(error.to_string(), "Storage denied access to the video");
+    }
+}
@@ -1,3 +1,17 @@
 pub mod backend;
+pub mod cache;
+pub mod credentials;
+pub mod disk_cache;
+pub mod error;
+pub mod parallel;
+pub mod policy;
+pub mod registry;
+pub mod resilience;
 
-pub use backend::{create_store, exists, fetch_all, fetch_range, get_size};
+pub use backend::{
+    create_store, exists, fetch_all, fetch_optional, fetch_range, fetch_ranges, fetch_sized,
+    fetch_version, get_size, head, list, list_page, object_url, put,
+};
+pub use error::{ErrorKind, StorageError};
+pub use policy::{PathError, PathPolicy};
+pub use registry::StoreRegistry;
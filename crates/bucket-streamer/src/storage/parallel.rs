@@ -0,0 +1,465 @@
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures_util::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use object_store::{
+    path::Path, Attributes, Error, GetOptions, GetRange, GetResult, GetResultPayload, ListResult,
+    MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult,
+    Result,
+};
+
+use crate::config::Config;
+use crate::telemetry;
+
+/// How one store's large and multi-range reads are split up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeReads {
+    /// Largest single range request
+    pub part_size: u64,
+    /// Requests of one read in flight at a time
+    pub concurrency: usize,
+    /// Ranges at most this far apart are fetched together
+    pub coalesce_gap: u64,
+}
+
+impl RangeReads {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            part_size: config.storage_part_size.max(1),
+            concurrency: config.storage_fetch_concurrency.max(1),
+            coalesce_gap: config.storage_coalesce_gap,
+        }
+    }
+}
+
+/// A store that fetches large ranges as concurrent parts
+///
+/// A range read longer than the part size becomes one request per part,
+/// up to `concurrency` at a time, reassembled into one contiguous `Bytes`.
+/// A multi-range read first merges ranges that overlap or lie within
+/// `coalesce_gap` of each other, so frames from the same GOP cost one
+/// fetch. Everything else passes straight through.
+///
+/// Every part of one read must come from the same version of the object:
+/// a ranged `get_opts` passes its `if_match` to each part, and otherwise
+/// parts whose ETags differ fail the read with a precondition error rather
+/// than being joined.
+#[derive(Debug)]
+pub struct ParallelStore {
+    inner: Arc<dyn ObjectStore>,
+    reads: RangeReads,
+}
+
+impl ParallelStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, reads: RangeReads) -> Self {
+        Self { inner, reads }
+    }
+
+    /// One range and the metadata of the version it was read from
+    async fn get_versioned(
+        &self,
+        location: &Path,
+        range: Range<usize>,
+    ) -> Result<(ObjectMeta, Bytes)> {
+        let options = GetOptions {
+            range: Some(range.into()),
+            ..GetOptions::default()
+        };
+        let result = self.get_opts(location, options).await?;
+        let meta = result.meta.clone();
+        Ok((meta, result.bytes().await?))
+    }
+
+    /// Fetch `parts` concurrently with `options`, joined in order
+    async fn get_parts(
+        &self,
+        location: &Path,
+        parts: Vec<Range<usize>>,
+        options: &GetOptions,
+    ) -> Result<(ObjectMeta, Bytes)> {
+        telemetry::record_fetch_parts(parts.len());
+        let len = parts.iter().map(Range::len).sum();
+        let parts: Vec<(ObjectMeta, Bytes)> = stream::iter(parts)
+            .map(|part| async move {
+                let options = GetOptions {
+                    range: Some(part.into()),
+                    ..options.clone()
+                };
+                let result = self.inner.get_opts(location, options).await?;
+                let meta = result.meta.clone();
+                Ok::<_, Error>((meta, result.bytes().await?))
+            })
+            .buffered(self.reads.concurrency)
+            .try_collect()
+            .await?;
+        same_version(location, parts.iter().map(|(meta, _)| meta))?;
+
+        let mut bytes = BytesMut::with_capacity(len);
+        for (_, part) in &parts {
+            bytes.extend_from_slice(part);
+        }
+        let (meta, _) = parts.into_iter().next().expect("split reads have parts");
+        Ok((meta, bytes.freeze()))
+    }
+}
+
+/// The bounded range of a plain range read: one with no conditions other
+/// than `if_match`
+pub fn bounded_range(options: &GetOptions) -> Option<Range<usize>> {
+    let plain = options.if_none_match.is_none()
+        && options.if_modified_since.is_none()
+        && options.if_unmodified_since.is_none()
+        && options.version.is_none()
+        && !options.head;
+    match &options.range {
+        Some(GetRange::Bounded(range)) if plain => Some(range.clone()),
+        _ => None,
+    }
+}
+
+/// A `get_opts` result for `data` read from `range` of `meta`'s object
+pub fn ranged_result(meta: ObjectMeta, range: Range<usize>, data: Bytes) -> GetResult {
+    let range = range.start..range.start + data.len();
+    GetResult {
+        payload: GetResultPayload::Stream(stream::once(async move { Ok(data) }).boxed()),
+        meta,
+        range,
+        attributes: Attributes::default(),
+    }
+}
+
+/// Fail unless every part was read from the same version of `location`
+fn same_version<'a>(
+    location: &Path,
+    mut metas: impl Iterator<Item = &'a ObjectMeta>,
+) -> Result<()> {
+    let Some(first) = metas.next() else {
+        return Ok(());
+    };
+    if metas.all(|meta| meta.e_tag == first.e_tag) {
+        return Ok(());
+    }
+    Err(Error::Precondition {
+        path: location.to_string(),
+        source: "object changed while its parts were read".into(),
+    })
+}
+
+impl fmt::Display for ParallelStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for ParallelStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let Some(range) = bounded_range(&options) else {
+            return self.inner.get_opts(location, options).await;
+        };
+        let parts = split_range(range.clone(), self.reads.part_size);
+        if parts.len() <= 1 {
+            return self.inner.get_opts(location, options).await;
+        }
+        let (meta, data) = self.get_parts(location, parts, &options).await?;
+        Ok(ranged_result(meta, range, data))
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        let parts = split_range(range.clone(), self.reads.part_size);
+        if parts.len() <= 1 {
+            return self.inner.get_range(location, range).await;
+        }
+        let (_, data) = self
+            .get_parts(location, parts, &GetOptions::default())
+            .await?;
+        Ok(data)
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        let merged = merge_ranges(ranges, self.reads.coalesce_gap);
+        let fetched: Vec<(ObjectMeta, Bytes)> = stream::iter(merged.iter().cloned())
+            .map(|range| self.get_versioned(location, range))
+            .buffered(self.reads.concurrency)
+            .try_collect()
+            .await?;
+        same_version(location, fetched.iter().map(|(meta, _)| meta))?;
+        let fetched: Vec<Bytes> = fetched.into_iter().map(|(_, data)| data).collect();
+
+        Ok(ranges
+            .iter()
+            .map(|range| {
+                // Merged ranges are sorted and disjoint; the last one
+                // starting at or before `range` contains it
+                let i = merged.partition_point(|m| m.start <= range.start) - 1;
+                let start = range.start - merged[i].start;
+                let end = (range.end - merged[i].start).min(fetched[i].len());
+                fetched[i].slice(start.min(end)..end)
+            })
+            .collect())
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+/// `range` cut into consecutive parts of at most `part_size` bytes
+fn split_range(range: Range<usize>, part_size: u64) -> Vec<Range<usize>> {
+    let part_size = part_size as usize;
+    if range.len() <= part_size {
+        return vec![range];
+    }
+    (range.start..range.end)
+        .step_by(part_size)
+        .map(|start| start..(start + part_size).min(range.end))
+        .collect()
+}
+
+/// Sorted, disjoint ranges covering `ranges`, joining any that overlap or
+/// are at most `gap` bytes apart
+pub(crate) fn merge_ranges(ranges: &[Range<usize>], gap: u64) -> Vec<Range<usize>> {
+    let mut sorted = ranges.to_vec();
+    sorted.sort_unstable_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(sorted.len());
+    for range in sorted {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(gap as usize) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts range requests reaching the backend
+    #[derive(Debug, Default)]
+    struct CountingStore {
+        inner: InMemory,
+        ranges: AtomicUsize,
+    }
+
+    impl fmt::Display for CountingStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "CountingStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for CountingStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> Result<PutResult> {
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOpts,
+        ) -> Result<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+            if options.range.is_some() {
+                self.ranges.fetch_add(1, Ordering::SeqCst);
+            }
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn delete(&self, location: &Path) -> Result<()> {
+            self.inner.delete(location).await
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    fn reads(part_size: u64, coalesce_gap: u64) -> RangeReads {
+        RangeReads {
+            part_size,
+            concurrency: 3,
+            coalesce_gap,
+        }
+    }
+
+    async fn store_with_data(reads: RangeReads) -> (ParallelStore, Arc<CountingStore>, Bytes) {
+        let data: Bytes = (0..=255u8).cycle().take(1000).collect::<Vec<_>>().into();
+        let backend = Arc::new(CountingStore::default());
+        backend
+            .put(&Path::from("clip.h265"), data.clone().into())
+            .await
+            .unwrap();
+        (ParallelStore::new(backend.clone(), reads), backend, data)
+    }
+
+    #[test]
+    fn test_split_range() {
+        assert_eq!(split_range(0..10, 4), [0..4, 4..8, 8..10]);
+        let small = 5..9;
+        assert_eq!(split_range(small.clone(), 4), [small]);
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        assert_eq!(merge_ranges(&[10..20, 0..5, 18..30], 0), [0..5, 10..30]);
+        assert_eq!(merge_ranges(&[0..5, 8..10, 40..50], 3), [0..10, 40..50]);
+        let outer = 0..50;
+        assert_eq!(merge_ranges(&[outer.clone(), 10..20], 0), [outer]);
+        assert!(merge_ranges(&[], 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_large_range_split_into_parts() {
+        let (store, backend, data) = store_with_data(reads(128, 0)).await;
+
+        let bytes = store
+            .get_range(&Path::from("clip.h265"), 100..900)
+            .await
+            .unwrap();
+        assert_eq!(bytes, data.slice(100..900));
+        assert_eq!(backend.ranges.load(Ordering::SeqCst), 7);
+
+        // Small ranges go out as a single request
+        let bytes = store
+            .get_range(&Path::from("clip.h265"), 0..64)
+            .await
+            .unwrap();
+        assert_eq!(bytes, data.slice(0..64));
+        assert_eq!(backend.ranges.load(Ordering::SeqCst), 8);
+    }
+
+    #[tokio::test]
+    async fn test_adjacent_ranges_coalesced() {
+        let (store, backend, data) = store_with_data(reads(4096, 16)).await;
+
+        let ranges = [300..400, 0..100, 100..150, 160..200, 800..900];
+        let fetched = store
+            .get_ranges(&Path::from("clip.h265"), &ranges)
+            .await
+            .unwrap();
+        for (range, bytes) in ranges.iter().zip(&fetched) {
+            assert_eq!(*bytes, data.slice(range.clone()));
+        }
+        // 0..200, 300..400 and 800..900
+        assert_eq!(backend.ranges.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_parts_checked_against_version() {
+        let (store, backend, data) = store_with_data(reads(128, 0)).await;
+        let path = Path::from("clip.h265");
+        let meta = store.head(&path).await.unwrap();
+
+        let options = |if_match: Option<String>| GetOptions {
+            if_match,
+            range: Some((100..900).into()),
+            ..GetOptions::default()
+        };
+        let result = store.get_opts(&path, options(meta.e_tag)).await.unwrap();
+        assert_eq!(result.range, 100..900);
+        assert_eq!(result.bytes().await.unwrap(), data.slice(100..900));
+        assert_eq!(backend.ranges.load(Ordering::SeqCst), 7);
+
+        let stale = Some("\"stale\"".to_string());
+        assert!(matches!(
+            store.get_opts(&path, options(stale)).await,
+            Err(Error::Precondition { .. })
+        ));
+    }
+
+    #[test]
+    fn test_same_version() {
+        let path = Path::from("clip.h265");
+        let meta = |e_tag: &str| ObjectMeta {
+            location: path.clone(),
+            last_modified: Default::default(),
+            size: 1000,
+            e_tag: Some(e_tag.to_string()),
+            version: None,
+        };
+        let (old, new) = (meta("1"), meta("2"));
+
+        assert!(same_version(&path, [&old, &old].into_iter()).is_ok());
+        assert!(same_version(&path, std::iter::empty()).is_ok());
+        assert!(matches!(
+            same_version(&path, [&old, &new].into_iter()),
+            Err(Error::Precondition { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_part_failure_fails_read() {
+        let (store, _, _) = store_with_data(reads(128, 0)).await;
+        // The last part starts past the end of the object
+        assert!(store
+            .get_range(&Path::from("clip.h265"), 900..1200)
+            .await
+            .is_err());
+    }
+}
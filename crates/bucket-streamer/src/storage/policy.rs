@@ -0,0 +1,241 @@
+use crate::config::Config;
+
+/// Reasons a client-supplied path is rejected
+///
+/// Messages describe the request only and are safe to return to clients.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PathError {
+    #[error("Path is empty")]
+    Empty,
+
+    #[error("Path exceeds {max} bytes")]
+    TooLong { max: usize },
+
+    #[error("Path contains control characters")]
+    ControlCharacter,
+
+    #[error("Path contains '.' or '..' segments")]
+    Traversal,
+
+    #[error("Path is outside the allowed prefixes")]
+    PrefixNotAllowed,
+
+    #[error("File extension is not allowed")]
+    ExtensionNotAllowed,
+
+    #[error("Malformed storage URL")]
+    InvalidUrl,
+
+    #[error("Unsupported storage URL scheme '{0}'")]
+    UnsupportedScheme(String),
+
+    #[error("Bucket or directory is not an allowed store")]
+    StoreNotAllowed,
+}
+
+/// Rules every client-supplied object path must satisfy
+///
+/// Applied before a path reaches `object_store::path::Path::from`, so the
+/// store only ever sees normalized, relative paths inside the allowed
+/// prefixes.
+#[derive(Debug, Clone, Default)]
+pub struct PathPolicy {
+    /// Directory-style prefixes paths must fall under (empty = any)
+    allowed_prefixes: Vec<Vec<String>>,
+    /// Lowercase extensions without the dot (empty = any)
+    allowed_extensions: Vec<String>,
+    /// Maximum path length in bytes (0 = unlimited)
+    max_length: usize,
+}
+
+impl PathPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            allowed_prefixes: config
+                .allowed_prefixes
+                .iter()
+                .map(|prefix| {
+                    prefix
+                        .split('/')
+                        .filter(|s| !s.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .collect(),
+            allowed_extensions: config
+                .allowed_extensions
+                .iter()
+                .map(|ext| ext.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|ext| !ext.is_empty())
+                .collect(),
+            max_length: config.max_path_length,
+        }
+    }
+
+    /// Validate a video path and return its normalized form
+    ///
+    /// Leading and repeated slashes are removed.
+    pub fn video_path(&self, path: &str) -> Result<String, PathError> {
+        let normalized = self.normalize(path)?;
+        if normalized.is_empty() {
+            return Err(PathError::Empty);
+        }
+
+        if !self.allowed_extensions.is_empty() {
+            let extension = normalized
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.rsplit_once('.'))
+                .map(|(_, ext)| ext.to_ascii_lowercase());
+            if !extension.is_some_and(|ext| self.allowed_extensions.contains(&ext)) {
+                return Err(PathError::ExtensionNotAllowed);
+            }
+        }
+
+        Ok(normalized)
+    }
+
+    /// Validate a listing prefix and return its normalized form
+    ///
+    /// A prefix may lie above an allowed prefix (e.g. the store root); the
+    /// listing itself is filtered with [`PathPolicy::allows`].
+    pub fn list_prefix(&self, prefix: &str) -> Result<String, PathError> {
+        let normalized = self.check_syntax(prefix)?;
+        let segments: Vec<&str> = normalized.split('/').filter(|s| !s.is_empty()).collect();
+
+        let related = self.allowed_prefixes.is_empty()
+            || self.allowed_prefixes.iter().any(|allowed| {
+                let n = allowed.len().min(segments.len());
+                allowed[..n].iter().zip(&segments[..n]).all(|(a, b)| a == b)
+            });
+        if !related {
+            return Err(PathError::PrefixNotAllowed);
+        }
+
+        Ok(normalized)
+    }
+
+    /// Check a path from a store listing against the policy
+    pub fn allows(&self, path: &str) -> bool {
+        self.video_path(path).is_ok()
+    }
+
+    /// Syntax checks plus the prefix allow-list
+    fn normalize(&self, path: &str) -> Result<String, PathError> {
+        let normalized = self.check_syntax(path)?;
+
+        if !self.allowed_prefixes.is_empty() {
+            let segments: Vec<&str> = normalized.split('/').collect();
+            let allowed = self.allowed_prefixes.iter().any(|prefix| {
+                prefix.len() < segments.len() && prefix.iter().zip(&segments).all(|(a, b)| a == b)
+            });
+            if !allowed {
+                return Err(PathError::PrefixNotAllowed);
+            }
+        }
+
+        Ok(normalized)
+    }
+
+    /// Reject overlong paths, control characters and traversal, and drop
+    /// empty segments
+    fn check_syntax(&self, path: &str) -> Result<String, PathError> {
+        if self.max_length > 0 && path.len() > self.max_length {
+            return Err(PathError::TooLong {
+                max: self.max_length,
+            });
+        }
+        if path.chars().any(|c| c.is_control()) {
+            return Err(PathError::ControlCharacter);
+        }
+
+        let mut segments = Vec::new();
+        // Backslashes are separators on Windows local stores
+        for segment in path.split(['/', '\\']) {
+            match segment {
+                "" => {}
+                "." | ".." => return Err(PathError::Traversal),
+                _ => segments.push(segment),
+            }
+        }
+
+        Ok(segments.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(prefixes: &[&str], extensions: &[&str]) -> PathPolicy {
+        let config = Config {
+            allowed_prefixes: prefixes.iter().map(|s| s.to_string()).collect(),
+            allowed_extensions: extensions.iter().map(|s| s.to_string()).collect(),
+            max_path_length: 64,
+            ..Config::default()
+        };
+        PathPolicy::from_config(&config)
+    }
+
+    #[test]
+    fn test_normalization() {
+        let policy = policy(&[], &[]);
+        assert_eq!(policy.video_path("/clips//a.h265").unwrap(), "clips/a.h265");
+        assert_eq!(policy.video_path("a.h265").unwrap(), "a.h265");
+        assert_eq!(policy.video_path("/"), Err(PathError::Empty));
+    }
+
+    #[test]
+    fn test_rejects_unsafe_paths() {
+        let policy = policy(&[], &[]);
+        assert_eq!(
+            policy.video_path("../etc/passwd"),
+            Err(PathError::Traversal)
+        );
+        assert_eq!(policy.video_path("a/./b.h265"), Err(PathError::Traversal));
+        assert_eq!(
+            policy.video_path("a\\..\\b.h265"),
+            Err(PathError::Traversal)
+        );
+        assert_eq!(
+            policy.video_path("a\nb.h265"),
+            Err(PathError::ControlCharacter)
+        );
+        assert_eq!(
+            policy.video_path(&"a".repeat(65)),
+            Err(PathError::TooLong { max: 64 })
+        );
+    }
+
+    #[test]
+    fn test_allow_lists() {
+        let policy = policy(&["videos/public", "shared"], &["h265", ".MP4"]);
+
+        assert!(policy.video_path("videos/public/a.h265").is_ok());
+        assert!(policy.video_path("shared/deep/a.mp4").is_ok());
+        assert_eq!(
+            policy.video_path("videos/publicity/a.h265"),
+            Err(PathError::PrefixNotAllowed)
+        );
+        assert_eq!(
+            policy.video_path("shared"),
+            Err(PathError::PrefixNotAllowed)
+        );
+        assert_eq!(
+            policy.video_path("shared/notes.txt"),
+            Err(PathError::ExtensionNotAllowed)
+        );
+        assert_eq!(
+            policy.video_path("shared/h265"),
+            Err(PathError::ExtensionNotAllowed)
+        );
+
+        assert_eq!(policy.list_prefix("videos").unwrap(), "videos");
+        assert_eq!(policy.list_prefix("").unwrap(), "");
+        assert!(policy.list_prefix("shared/deep").is_ok());
+        assert_eq!(
+            policy.list_prefix("private"),
+            Err(PathError::PrefixNotAllowed)
+        );
+    }
+}
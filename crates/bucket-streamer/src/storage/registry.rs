@@ -0,0 +1,510 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use object_store::ObjectStore;
+
+use super::backend::{check_range_support, create_store};
+use super::policy::{PathError, PathPolicy};
+use crate::config::{Config, StorageBackend, StoreConfig};
+
+/// Where a store lives, as named by its URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Location {
+    /// An S3 bucket, GCS bucket or Azure container
+    Bucket {
+        backend: StorageBackend,
+        bucket: String,
+    },
+    /// Segments of a canonical local directory
+    Directory(Vec<String>),
+    /// Base URL of an HTTP(S) origin, without a trailing slash
+    Origin(String),
+}
+
+impl Location {
+    fn for_config(config: &Config) -> Self {
+        let bucket = match config.storage_backend {
+            StorageBackend::Local => {
+                let root = std::fs::canonicalize(&config.local_path)
+                    .unwrap_or_else(|_| PathBuf::from(&config.local_path));
+                return Location::Directory(segments(&root.to_string_lossy()));
+            }
+            StorageBackend::Http => {
+                return Location::Origin(config.http_url.trim_end_matches('/').to_string());
+            }
+            StorageBackend::S3 => &config.s3_bucket,
+            StorageBackend::Gcs => &config.gcs_bucket,
+            StorageBackend::Azure => &config.azure_container,
+        };
+        Location::Bucket {
+            backend: config.storage_backend,
+            bucket: bucket.clone(),
+        }
+    }
+}
+
+/// A client-supplied video reference, before it is matched to a store
+#[derive(Debug, PartialEq, Eq)]
+enum Reference<'a> {
+    /// A path in the default store
+    Plain(&'a str),
+    /// `s3://bucket/key`, `gs://bucket/key` or `az://container/key`
+    Bucket {
+        backend: StorageBackend,
+        bucket: &'a str,
+        key: &'a str,
+    },
+    /// `fs:///absolute/path`
+    File(&'a str),
+    /// `https://host/path`, the whole reference
+    Http(&'a str),
+}
+
+impl<'a> Reference<'a> {
+    fn parse(reference: &'a str) -> Result<Self, PathError> {
+        let Some((scheme, rest)) = reference.split_once("://") else {
+            return Ok(Reference::Plain(reference));
+        };
+        // Anything before `://` that can't be a scheme is part of a path
+        if scheme.is_empty()
+            || !scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        {
+            return Ok(Reference::Plain(reference));
+        }
+
+        let backend = match scheme.to_ascii_lowercase().as_str() {
+            "fs" | "file" => {
+                if !rest.starts_with('/') {
+                    return Err(PathError::InvalidUrl);
+                }
+                return Ok(Reference::File(rest));
+            }
+            "http" | "https" => return Ok(Reference::Http(reference)),
+            "s3" | "s3a" => StorageBackend::S3,
+            "gs" | "gcs" => StorageBackend::Gcs,
+            "az" | "azure" => StorageBackend::Azure,
+            _ => return Err(PathError::UnsupportedScheme(scheme.to_string())),
+        };
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(PathError::InvalidUrl);
+        }
+        Ok(Reference::Bucket {
+            backend,
+            bucket,
+            key,
+        })
+    }
+}
+
+/// A video reference matched to its store
+#[derive(Clone)]
+pub struct ResolvedVideo {
+    pub store: Arc<dyn ObjectStore>,
+    /// Normalized path within `store`
+    pub key: String,
+    /// Path checked against the caller's scopes and shown in logs: `key` for
+    /// the default store, otherwise the object's URL
+    pub scope_path: String,
+}
+
+struct NamedStore {
+    name: String,
+    location: Location,
+    /// URL prefix objects in this store are scoped under
+    url: String,
+    store: Arc<dyn ObjectStore>,
+    policy: PathPolicy,
+    /// Object read at startup to check an HTTP origin supports ranges
+    range_probe: Option<String>,
+}
+
+/// Object stores clients may address by URL
+///
+/// Plain paths go to the default store. URLs must name the default store's
+/// own bucket, directory or origin, or one configured under `[[stores]]`;
+/// every other bucket is refused, so the registry is also the bucket
+/// allow-list.
+pub struct StoreRegistry {
+    default: NamedStore,
+    stores: Vec<NamedStore>,
+}
+
+impl StoreRegistry {
+    /// Build the configured stores around the already-created default store
+    pub fn from_config(config: &Config, default: Arc<dyn ObjectStore>) -> Result<Self> {
+        let location = Location::for_config(config);
+        let default = NamedStore {
+            name: "default".to_string(),
+            url: location_url(&location),
+            location,
+            store: default,
+            policy: PathPolicy::from_config(config),
+            range_probe: range_probe(config),
+        };
+
+        let mut stores: Vec<NamedStore> = Vec::new();
+        for store_config in &config.stores {
+            let store = build_store(config, store_config)
+                .with_context(|| format!("Failed to create store '{}'", store_config.name))?;
+            if let Some(other) = std::iter::once(&default)
+                .chain(&stores)
+                .find(|other| other.location == store.location)
+            {
+                bail!(
+                    "Stores '{}' and '{}' both use {}",
+                    other.name,
+                    store.name,
+                    store.url
+                );
+            }
+            tracing::info!("Store '{}' at {}", store.name, store.url);
+            stores.push(store);
+        }
+
+        Ok(Self { default, stores })
+    }
+
+    /// Check every HTTP origin with an `http_range_probe` honours ranges
+    pub async fn check_origins(&self) -> Result<()> {
+        for store in self.all() {
+            if let Some(probe) = &store.range_probe {
+                check_range_support(&*store.store, probe)
+                    .await
+                    .with_context(|| format!("Store '{}' at {}", store.name, store.url))?;
+                tracing::info!("Store '{}' supports range requests", store.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// The store plain paths resolve to
+    pub fn default_store(&self) -> &Arc<dyn ObjectStore> {
+        &self.default.store
+    }
+
+    /// Match a plain path or storage URL to its store, and check the path
+    /// against that store's policy
+    pub fn resolve(&self, reference: &str) -> Result<ResolvedVideo, PathError> {
+        let (store, key) = match Reference::parse(reference)? {
+            Reference::Plain(path) => (&self.default, path.to_string()),
+            Reference::Bucket {
+                backend,
+                bucket,
+                key,
+            } => {
+                let location = Location::Bucket {
+                    backend,
+                    bucket: bucket.to_string(),
+                };
+                let store = self
+                    .all()
+                    .find(|store| store.location == location)
+                    .ok_or(PathError::StoreNotAllowed)?;
+                (store, key.to_string())
+            }
+            Reference::File(path) => {
+                let path = segments(path);
+                // The most specific directory wins when roots are nested
+                let store = self
+                    .all()
+                    .filter_map(|store| match &store.location {
+                        Location::Directory(root) if path.starts_with(root) => {
+                            Some((root.len(), store))
+                        }
+                        _ => None,
+                    })
+                    .max_by_key(|(depth, _)| *depth)
+                    .map(|(_, store)| store)
+                    .ok_or(PathError::StoreNotAllowed)?;
+                let Location::Directory(root) = &store.location else {
+                    unreachable!("matched a directory store");
+                };
+                (store, path[root.len()..].join("/"))
+            }
+            Reference::Http(url) => self
+                .all()
+                .filter_map(|store| match &store.location {
+                    Location::Origin(base) => url
+                        .strip_prefix(base.as_str())
+                        .and_then(|rest| rest.strip_prefix('/'))
+                        .map(|key| (base.len(), store, key)),
+                    _ => None,
+                })
+                .max_by_key(|(len, _, _)| *len)
+                .map(|(_, store, key)| (store, key.to_string()))
+                .ok_or(PathError::StoreNotAllowed)?,
+        };
+
+        let key = store.policy.video_path(&key)?;
+        let scope_path = if std::ptr::eq(store, &self.default) {
+            key.clone()
+        } else {
+            format!("{}/{}", store.url, key)
+        };
+        Ok(ResolvedVideo {
+            store: store.store.clone(),
+            key,
+            scope_path,
+        })
+    }
+
+    fn all(&self) -> impl Iterator<Item = &NamedStore> {
+        std::iter::once(&self.default).chain(&self.stores)
+    }
+}
+
+/// Create one `[[stores]]` entry, inheriting unset settings from `base`
+fn build_store(base: &Config, store_config: &StoreConfig) -> Result<NamedStore> {
+    let mut config = store_config.apply_to(base);
+    let location = match Reference::parse(&store_config.url)? {
+        Reference::Plain(_) => bail!("'{}' is not a storage URL", store_config.url),
+        Reference::Bucket {
+            backend,
+            bucket,
+            key,
+        } => {
+            if !key.trim_matches('/').is_empty() {
+                bail!(
+                    "'{}' must name a bucket or container, not a path in one",
+                    store_config.url
+                );
+            }
+            config.storage_backend = backend;
+            match backend {
+                StorageBackend::S3 => config.s3_bucket = bucket.to_string(),
+                StorageBackend::Gcs => config.gcs_bucket = bucket.to_string(),
+                StorageBackend::Azure => config.azure_container = bucket.to_string(),
+                StorageBackend::Local | StorageBackend::Http => {
+                    unreachable!("bucket URLs are never local or HTTP")
+                }
+            }
+            Location::for_config(&config)
+        }
+        Reference::File(path) => {
+            config.storage_backend = StorageBackend::Local;
+            config.local_path = path.to_string();
+            Location::for_config(&config)
+        }
+        Reference::Http(url) => {
+            config.storage_backend = StorageBackend::Http;
+            config.http_url = url.to_string();
+            Location::for_config(&config)
+        }
+    };
+
+    Ok(NamedStore {
+        name: store_config.name.clone(),
+        url: location_url(&location),
+        store: create_store(&config)?,
+        policy: PathPolicy::from_config(&config),
+        range_probe: range_probe(&config),
+        location,
+    })
+}
+
+fn range_probe(config: &Config) -> Option<String> {
+    match config.storage_backend {
+        StorageBackend::Http => config.http_range_probe.clone(),
+        _ => None,
+    }
+}
+
+/// URL of a store's root, in the same format as [`super::object_url`]
+fn location_url(location: &Location) -> String {
+    match location {
+        Location::Directory(root) => format!("fs:///{}", root.join("/")),
+        Location::Origin(base) => base.clone(),
+        Location::Bucket { backend, bucket } => {
+            let scheme = match backend {
+                StorageBackend::S3 => "s3",
+                StorageBackend::Gcs => "gs",
+                StorageBackend::Azure => "az",
+                StorageBackend::Local | StorageBackend::Http => {
+                    unreachable!("not a bucket backend")
+                }
+            };
+            format!("{}://{}", scheme, bucket)
+        }
+    }
+}
+
+fn segments(path: &str) -> Vec<String> {
+    path.split(['/', '\\'])
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageSection;
+
+    fn store_config(name: &str, url: &str) -> StoreConfig {
+        StoreConfig {
+            name: name.to_string(),
+            url: url.to_string(),
+            storage: StorageSection::default(),
+            unknown: Default::default(),
+        }
+    }
+
+    fn registry(dir: &tempfile::TempDir, stores: Vec<StoreConfig>) -> StoreRegistry {
+        let config = Config {
+            local_path: dir.path().to_str().unwrap().to_string(),
+            stores,
+            ..Config::default()
+        };
+        let default = create_store(&config).unwrap();
+        StoreRegistry::from_config(&config, default).unwrap()
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        assert_eq!(
+            Reference::parse("clips/a.h265").unwrap(),
+            Reference::Plain("clips/a.h265")
+        );
+        assert_eq!(
+            Reference::parse("S3://videos/clips/a.h265").unwrap(),
+            Reference::Bucket {
+                backend: StorageBackend::S3,
+                bucket: "videos",
+                key: "clips/a.h265",
+            }
+        );
+        assert_eq!(
+            Reference::parse("fs:///srv/videos/a.h265").unwrap(),
+            Reference::File("/srv/videos/a.h265")
+        );
+        assert_eq!(
+            Reference::parse("https://cdn.example.com/a.h265").unwrap(),
+            Reference::Http("https://cdn.example.com/a.h265")
+        );
+        assert_eq!(
+            Reference::parse("ftp://host/a.h265"),
+            Err(PathError::UnsupportedScheme("ftp".to_string()))
+        );
+        assert_eq!(Reference::parse("gs:///a.h265"), Err(PathError::InvalidUrl));
+        assert_eq!(Reference::parse("fs://a.h265"), Err(PathError::InvalidUrl));
+    }
+
+    #[test]
+    fn test_resolve_to_store() {
+        let default_dir = tempfile::TempDir::new().unwrap();
+        let archive_dir = tempfile::TempDir::new().unwrap();
+        let archive_root = std::fs::canonicalize(archive_dir.path()).unwrap();
+        let archive_url = format!("fs://{}", archive_root.display());
+
+        let mut s3 = store_config("s3", "s3://footage");
+        s3.storage.s3_endpoint = Some("http://localhost:9000".to_string());
+        s3.storage.s3_access_key = Some("key".to_string());
+        s3.storage.s3_secret_key = Some("secret".to_string());
+        s3.storage.allowed_prefixes = Some(vec!["public".to_string()]);
+        let registry = registry(
+            &default_dir,
+            vec![store_config("archive", &archive_url), s3],
+        );
+
+        let plain = registry.resolve("/clips//a.h265").unwrap();
+        assert!(Arc::ptr_eq(&plain.store, registry.default_store()));
+        assert_eq!(plain.key, "clips/a.h265");
+        assert_eq!(plain.scope_path, "clips/a.h265");
+
+        let archived = registry
+            .resolve(&format!("{}/2024/a.h265", archive_url))
+            .unwrap();
+        assert!(!Arc::ptr_eq(&archived.store, registry.default_store()));
+        assert_eq!(archived.key, "2024/a.h265");
+        assert_eq!(archived.scope_path, format!("{}/2024/a.h265", archive_url));
+
+        let s3 = registry.resolve("s3://footage/public/a.h265").unwrap();
+        assert_eq!(s3.key, "public/a.h265");
+        assert_eq!(s3.scope_path, "s3://footage/public/a.h265");
+
+        // Each store applies its own path policy
+        assert!(matches!(
+            registry.resolve("s3://footage/private/a.h265"),
+            Err(PathError::PrefixNotAllowed)
+        ));
+        assert!(matches!(
+            registry.resolve(&format!("{}/../a.h265", archive_url)),
+            Err(PathError::Traversal)
+        ));
+    }
+
+    #[test]
+    fn test_default_store_by_url() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = std::fs::canonicalize(dir.path()).unwrap();
+        let registry = registry(&dir, Vec::new());
+
+        let video = registry
+            .resolve(&format!("fs://{}/clips/a.h265", root.display()))
+            .unwrap();
+        assert!(Arc::ptr_eq(&video.store, registry.default_store()));
+        assert_eq!(video.scope_path, "clips/a.h265");
+    }
+
+    #[test]
+    fn test_unlisted_stores_refused() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let registry = registry(&dir, Vec::new());
+
+        assert!(matches!(
+            registry.resolve("s3://other-bucket/a.h265"),
+            Err(PathError::StoreNotAllowed)
+        ));
+        assert!(matches!(
+            registry.resolve("fs:///etc/passwd"),
+            Err(PathError::StoreNotAllowed)
+        ));
+        assert!(matches!(
+            registry.resolve("https://cdn.example.com/a.h265"),
+            Err(PathError::StoreNotAllowed)
+        ));
+    }
+
+    #[test]
+    fn test_resolve_http_origin() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let registry = registry(
+            &dir,
+            vec![store_config("cdn", "https://cdn.example.com/videos/")],
+        );
+
+        let video = registry
+            .resolve("https://cdn.example.com/videos/2024/a.h265")
+            .unwrap();
+        assert_eq!(video.key, "2024/a.h265");
+        assert_eq!(
+            video.scope_path,
+            "https://cdn.example.com/videos/2024/a.h265"
+        );
+
+        // Only URLs under the configured base match
+        assert!(matches!(
+            registry.resolve("https://cdn.example.com/videos-private/a.h265"),
+            Err(PathError::StoreNotAllowed)
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_store_rejected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            local_path: dir.path().to_str().unwrap().to_string(),
+            stores: vec![store_config(
+                "again",
+                &format!("fs://{}", dir.path().display()),
+            )],
+            ..Config::default()
+        };
+        let default = create_store(&config).unwrap();
+        assert!(StoreRegistry::from_config(&config, default).is_err());
+    }
+}
@@ -0,0 +1,484 @@
+use std::fmt;
+use std::future::Future;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::future::{self, Either};
+use futures_util::stream::BoxStream;
+use object_store::{
+    path::Path, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+    PutMultipartOpts, PutOptions, PutPayload, PutResult, Result,
+};
+use tracing::{debug, warn};
+
+use super::error::{ErrorKind, PolicyError};
+use super::parallel::{bounded_range, ranged_result};
+use crate::config::Config;
+use crate::telemetry;
+
+/// Timeouts, retries, hedging and circuit breaking for one store's reads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadPolicy {
+    /// Limit on each attempt (`None` = wait as long as the backend does)
+    pub timeout: Option<Duration>,
+    /// Further attempts after a throttled or transient failure
+    pub max_retries: u32,
+    /// Delay ceiling before the first retry; doubles with each retry
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Start a second range read when the first is slower than this
+    pub hedge_after: Option<Duration>,
+    /// Consecutive failures that open the breaker (0 = never)
+    pub breaker_threshold: u32,
+    /// How long an open breaker refuses reads before letting one through
+    pub breaker_cooldown: Duration,
+}
+
+impl ReadPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        let millis = |ms: u64| Some(Duration::from_millis(ms)).filter(|d| !d.is_zero());
+        Self {
+            timeout: millis(config.storage_timeout_ms),
+            max_retries: config.storage_max_retries,
+            backoff: Duration::from_millis(config.storage_retry_backoff_ms),
+            max_backoff: Duration::from_millis(config.storage_retry_max_backoff_ms),
+            hedge_after: millis(config.storage_hedge_after_ms),
+            breaker_threshold: config.storage_breaker_threshold,
+            breaker_cooldown: Duration::from_millis(config.storage_breaker_cooldown_ms),
+        }
+    }
+
+    /// Delay before retry number `retry` (1-based)
+    ///
+    /// Full jitter: a random delay up to the exponential ceiling, so sessions
+    /// retrying the same failure spread out instead of arriving together.
+    fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+        ceiling.mul_f64(rand::random::<f64>())
+    }
+}
+
+/// A store whose reads follow a [`ReadPolicy`]
+///
+/// Range reads and `HEAD`s are limited per attempt and retried when the
+/// failure is throttling or transient; range reads may also be hedged. A
+/// bounded `get_opts` counts as a range read. Any other `get` is retried
+/// until its response starts, then streams as usual.
+/// Writes, deletes and listings pass straight through.
+#[derive(Debug)]
+pub struct ResilientStore {
+    inner: Arc<dyn ObjectStore>,
+    policy: ReadPolicy,
+    breaker: CircuitBreaker,
+}
+
+impl ResilientStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, policy: ReadPolicy) -> Self {
+        let breaker = CircuitBreaker::new(inner.to_string(), &policy);
+        Self {
+            inner,
+            policy,
+            breaker,
+        }
+    }
+
+    /// Run `op` under the policy; `hedge` allows a duplicate attempt
+    async fn run<T, F, Fut>(&self, op: &'static str, hedge: bool, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut + Send + Sync,
+        Fut: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
+        let mut retries = 0;
+        loop {
+            self.breaker.allow()?;
+            let error = match self.attempt(&f, hedge).await {
+                Ok(value) => {
+                    self.breaker.record(None);
+                    return Ok(value);
+                }
+                Err(e) => e,
+            };
+
+            let kind = ErrorKind::classify(&error);
+            self.breaker.record(Some(kind));
+            if !kind.is_retryable() || retries >= self.policy.max_retries {
+                return Err(error);
+            }
+            retries += 1;
+            let delay = self.policy.backoff(retries);
+            debug!(
+                "Storage {} failed ({}); retry {} of {} in {:?}: {}",
+                op,
+                kind.as_str(),
+                retries,
+                self.policy.max_retries,
+                delay,
+                error
+            );
+            telemetry::record_storage_retry(op, kind.as_str());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// One attempt, plus a hedged duplicate if the first is slow
+    async fn attempt<T, F, Fut>(&self, f: &F, hedge: bool) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let timed = || with_timeout(self.policy.timeout, f());
+        let Some(hedge_after) = self.policy.hedge_after.filter(|_| hedge) else {
+            return timed().await;
+        };
+
+        let first = timed();
+        tokio::pin!(first);
+        tokio::select! {
+            result = &mut first => return result,
+            _ = tokio::time::sleep(hedge_after) => {}
+        }
+
+        telemetry::record_storage_hedge();
+        let second = timed();
+        tokio::pin!(second);
+        // The first success wins; fail only once both have failed
+        match future::select(first, second).await {
+            Either::Left((Ok(value), _)) | Either::Right((Ok(value), _)) => Ok(value),
+            Either::Left((Err(_), other)) => other.await,
+            Either::Right((Err(_), other)) => other.await,
+        }
+    }
+}
+
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    attempt: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout {
+        Some(limit) => tokio::time::timeout(limit, attempt)
+            .await
+            .unwrap_or_else(|_| Err(PolicyError::TimedOut(limit).into_store_error())),
+        None => attempt.await,
+    }
+}
+
+impl fmt::Display for ResilientStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for ResilientStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let Some(range) = bounded_range(&options) else {
+            return self
+                .run("get", false, || {
+                    self.inner.get_opts(location, options.clone())
+                })
+                .await;
+        };
+        let (meta, data) = self
+            .run("range", true, || async {
+                let result = self.inner.get_opts(location, options.clone()).await?;
+                let meta = result.meta.clone();
+                Ok((meta, result.bytes().await?))
+            })
+            .await?;
+        Ok(ranged_result(meta, range, data))
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.run("range", true, || {
+            self.inner.get_range(location, range.clone())
+        })
+        .await
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.run("head", false, || self.inner.head(location)).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+/// Stops sending reads to a store that keeps failing
+///
+/// After `threshold` consecutive throttled or transient failures the breaker
+/// opens and reads fail at once. Once the cooldown passes a single read is
+/// let through: success closes the breaker, failure keeps it open for
+/// another cooldown.
+#[derive(Debug)]
+struct CircuitBreaker {
+    store: String,
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn new(store: String, policy: &ReadPolicy) -> Self {
+        Self {
+            store,
+            threshold: policy.breaker_threshold,
+            cooldown: policy.breaker_cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Whether a read may go out now
+    fn allow(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some(until) = state.open_until else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if now < until {
+            return Err(PolicyError::CircuitOpen.into_store_error());
+        }
+        // Let this read probe the store and hold others off until it reports
+        state.open_until = Some(now + self.cooldown);
+        Ok(())
+    }
+
+    /// Record an attempt's outcome: `None` for success
+    fn record(&self, failure: Option<ErrorKind>) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        // Any answer from the store, even "not found", shows it is reachable
+        if !failure.is_some_and(ErrorKind::is_retryable) {
+            if state.open_until.take().is_some() {
+                debug!("Storage circuit closed for {}", self.store);
+                telemetry::record_circuit(&self.store, false);
+            }
+            state.failures = 0;
+            return;
+        }
+
+        state.failures += 1;
+        if state.failures >= self.threshold && state.open_until.is_none() {
+            warn!(
+                "Storage circuit opened for {} after {} failures; retrying in {:?}",
+                self.store, state.failures, self.cooldown
+            );
+            state.open_until = Some(Instant::now() + self.cooldown);
+            telemetry::record_circuit(&self.store, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy() -> ReadPolicy {
+        ReadPolicy {
+            timeout: Some(Duration::from_millis(50)),
+            max_retries: 2,
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            hedge_after: None,
+            breaker_threshold: 0,
+            breaker_cooldown: Duration::from_secs(60),
+        }
+    }
+
+    fn store(policy: ReadPolicy) -> ResilientStore {
+        ResilientStore::new(Arc::new(InMemory::new()), policy)
+    }
+
+    fn throttled() -> object_store::Error {
+        let response = axum::http::Response::builder()
+            .status(429)
+            .body("")
+            .unwrap();
+        let error = reqwest::Response::from(response)
+            .error_for_status()
+            .unwrap_err();
+        object_store::Error::Generic {
+            store: "S3",
+            source: Box::new(error),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let store = store(policy());
+        let calls = AtomicU32::new(0);
+
+        let result = store
+            .run("range", false, || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err(throttled()),
+                    _ => Ok(7),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Not found is final
+        calls.store(0, Ordering::SeqCst);
+        let missing = store.head(&Path::from("missing.h265")).await;
+        assert!(matches!(missing, Err(object_store::Error::NotFound { .. })));
+
+        // Retries are bounded
+        let result: Result<()> = store
+            .run("range", false, || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(throttled())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_attempt_timeout() {
+        let store = store(ReadPolicy {
+            max_retries: 0,
+            ..policy()
+        });
+        let result: Result<()> = store
+            .run("range", false, || async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .await;
+        assert_eq!(
+            ErrorKind::classify(&result.unwrap_err()),
+            ErrorKind::Transient
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hedged_read() {
+        let store = store(ReadPolicy {
+            timeout: None,
+            hedge_after: Some(Duration::from_millis(10)),
+            ..policy()
+        });
+        let calls = AtomicU32::new(0);
+
+        // The first attempt stalls; the hedge answers
+        let started = Instant::now();
+        let result = store
+            .run("range", true, || async {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+                Ok("hedge")
+            })
+            .await;
+        assert_eq!(result.unwrap(), "hedge");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let store = store(ReadPolicy {
+            max_retries: 0,
+            breaker_threshold: 2,
+            breaker_cooldown: Duration::from_millis(50),
+            ..policy()
+        });
+        let fail = || async { Err::<(), _>(throttled()) };
+
+        assert!(store.run("range", false, fail).await.is_err());
+        assert!(store.run("range", false, fail).await.is_err());
+
+        // Open: refused without calling the backend
+        let calls = AtomicU32::new(0);
+        let result = store
+            .run("range", false, || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .await;
+        assert_eq!(
+            ErrorKind::classify(&result.unwrap_err()),
+            ErrorKind::Unavailable
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        // After the cooldown one probe goes through and closes it
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        store
+            .run("range", false, || async { Ok(()) })
+            .await
+            .unwrap();
+        store
+            .run("range", false, || async { Ok(()) })
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_backoff_bounds() {
+        let policy = ReadPolicy {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+            ..policy()
+        };
+        for retry in 1..10 {
+            let delay = policy.backoff(retry);
+            assert!(delay <= Duration::from_millis(300));
+            if retry == 1 {
+                assert!(delay <= Duration::from_millis(100));
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::Config;
+
+/// Open WebSocket sessions
+pub const SESSIONS_ACTIVE: &str = "bucket_streamer_sessions_active";
+
+/// WebSocket sessions opened, by `result` (accepted / rejected)
+pub const SESSIONS_TOTAL: &str = "bucket_streamer_sessions_total";
+
+/// Frames processed, by `result` (ok / error)
+pub const FRAMES_TOTAL: &str = "bucket_streamer_frames_total";
+
+/// Time spent per pipeline stage, by `stage`
+pub const STAGE_DURATION: &str = "bucket_streamer_stage_duration_seconds";
+
+/// Bytes read from storage, by `op` (range / full)
+pub const FETCH_BYTES: &str = "bucket_streamer_fetch_bytes_total";
+
+/// Size of encoded frames, by `format`
+pub const FRAME_BYTES: &str = "bucket_streamer_frame_bytes";
+
+/// Cache lookups, by `cache` and `result` (hit / miss)
+pub const CACHE_REQUESTS: &str = "bucket_streamer_cache_requests_total";
+
+/// Work waiting to be processed, by `queue`
+pub const QUEUE_DEPTH: &str = "bucket_streamer_queue_depth";
+
+/// Frames being decoded or encoded on the blocking pool
+pub const FRAMES_IN_FLIGHT: &str = "bucket_streamer_frames_in_flight";
+
+/// Storage reads retried, by `op` (get / range / head) and failure `kind`
+pub const STORAGE_RETRIES: &str = "bucket_streamer_storage_retries_total";
+
+/// Range reads that sent a hedged second request
+pub const STORAGE_HEDGES: &str = "bucket_streamer_storage_hedged_total";
+
+/// Range reads split into concurrent parts
+pub const STORAGE_SPLIT_READS: &str = "bucket_streamer_storage_split_reads_total";
+
+/// Part requests sent for split range reads
+pub const STORAGE_READ_PARTS: &str = "bucket_streamer_storage_read_parts_total";
+
+/// Bytes read ahead of clients into the fetch cache
+pub const PREFETCH_BYTES: &str = "bucket_streamer_prefetch_bytes_total";
+
+/// Read-aheads abandoned because the client seeked
+pub const PREFETCH_CANCELLED: &str = "bucket_streamer_prefetch_cancelled_total";
+
+/// Blocks in the disk cache that failed their integrity check
+pub const DISK_CACHE_CORRUPT: &str = "bucket_streamer_disk_cache_corrupt_total";
+
+/// 1 while a store's circuit breaker is open, by `store`
+pub const STORAGE_CIRCUIT_OPEN: &str = "bucket_streamer_storage_circuit_open";
+
+/// Histogram buckets for stage durations (1 ms .. 10 s)
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Histogram buckets for frame sizes (4 KiB .. 4 MiB)
+const SIZE_BUCKETS: &[f64] = &[
+    4096.0, 16384.0, 65536.0, 131072.0, 262144.0, 524288.0, 1048576.0, 4194304.0,
+];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Pipeline stages timed in [`STAGE_DURATION`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading video bytes from storage
+    Fetch,
+    /// Decoding packets up to the target frame
+    Decode,
+    /// Encoding the decoded frame
+    Encode,
+    /// Decode plus encode for one frame
+    Process,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Fetch => "fetch",
+            Stage::Decode => "decode",
+            Stage::Encode => "encode",
+            Stage::Process => "process",
+        }
+    }
+}
+
+/// Records the time until it is dropped, so early returns are counted too
+pub struct StageTimer {
+    stage: Stage,
+    started: Instant,
+}
+
+impl StageTimer {
+    pub fn start(stage: Stage) -> Self {
+        Self {
+            stage,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for StageTimer {
+    fn drop(&mut self) {
+        record_stage(self.stage, self.started.elapsed());
+    }
+}
+
+/// Item in a queue; counted in [`QUEUE_DEPTH`] until dropped
+#[derive(Debug)]
+pub struct QueueSlot {
+    queue: &'static str,
+}
+
+impl QueueSlot {
+    pub fn new(queue: &'static str) -> Self {
+        gauge!(QUEUE_DEPTH, "queue" => queue).increment(1.0);
+        Self { queue }
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        gauge!(QUEUE_DEPTH, "queue" => self.queue).decrement(1.0);
+    }
+}
+
+/// A frame being processed; counted in [`FRAMES_IN_FLIGHT`] until dropped
+pub struct InFlightFrame(());
+
+impl InFlightFrame {
+    pub fn start() -> Self {
+        let in_flight = IN_FLIGHT.fetch_add(1, Ordering::Relaxed) + 1;
+        gauge!(FRAMES_IN_FLIGHT).set(in_flight as f64);
+        Self(())
+    }
+}
+
+impl Drop for InFlightFrame {
+    fn drop(&mut self) {
+        let in_flight = IN_FLIGHT.fetch_sub(1, Ordering::Relaxed) - 1;
+        gauge!(FRAMES_IN_FLIGHT).set(in_flight as f64);
+    }
+}
+
+/// Frames currently being processed across all sessions and requests
+pub fn frames_in_flight() -> usize {
+    IN_FLIGHT.load(Ordering::Relaxed)
+}
+
+pub fn record_stage(stage: Stage, elapsed: Duration) {
+    histogram!(STAGE_DURATION, "stage" => stage.as_str()).record(elapsed.as_secs_f64());
+}
+
+pub fn record_fetch(op: &'static str, bytes: usize) {
+    counter!(FETCH_BYTES, "op" => op).increment(bytes as u64);
+}
+
+pub fn record_storage_retry(op: &'static str, kind: &'static str) {
+    counter!(STORAGE_RETRIES, "op" => op, "kind" => kind).increment(1);
+}
+
+pub fn record_storage_hedge() {
+    counter!(STORAGE_HEDGES).increment(1);
+}
+
+pub fn record_fetch_parts(parts: usize) {
+    counter!(STORAGE_SPLIT_READS).increment(1);
+    counter!(STORAGE_READ_PARTS).increment(parts as u64);
+}
+
+pub fn record_prefetch(bytes: usize) {
+    counter!(PREFETCH_BYTES).increment(bytes as u64);
+}
+
+pub fn record_prefetch_cancelled() {
+    counter!(PREFETCH_CANCELLED).increment(1);
+}
+
+pub fn record_disk_cache_corrupt() {
+    counter!(DISK_CACHE_CORRUPT).increment(1);
+}
+
+pub fn record_circuit(store: &str, open: bool) {
+    gauge!(STORAGE_CIRCUIT_OPEN, "store" => store.to_string()).set(if open { 1.0 } else { 0.0 });
+}
+
+pub fn record_frame(ok: bool) {
+    let result = if ok { "ok" } else { "error" };
+    counter!(FRAMES_TOTAL, "result" => result).increment(1);
+}
+
+pub fn record_frame_size(format: &'static str, bytes: usize) {
+    histogram!(FRAME_BYTES, "format" => format).record(bytes as f64);
+}
+
+pub fn record_cache(cache: &'static str, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    counter!(CACHE_REQUESTS, "cache" => cache, "result" => result).increment(1);
+}
+
+pub fn record_session(accepted: bool) {
+    let result = if accepted { "accepted" } else { "rejected" };
+    counter!(SESSIONS_TOTAL, "result" => result).increment(1);
+}
+
+fn recorder_builder() -> PrometheusBuilder {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), DURATION_BUCKETS)
+        .and_then(|builder| {
+            builder.set_buckets_for_metric(Matcher::Full(FRAME_BYTES.to_string()), SIZE_BUCKETS)
+        })
+        .expect("histogram buckets are non-empty")
+}
+
+/// Install the global Prometheus recorder
+///
+/// Safe to call more than once; metrics recorded before this are dropped.
+pub fn install_metrics() -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            let recorder = recorder_builder().build_recorder();
+            let handle = recorder.handle();
+            if let Err(e) = metrics::set_global_recorder(recorder) {
+                tracing::warn!("Metrics recorder already installed: {}", e);
+            }
+            handle
+        })
+        .clone()
+}
+
+/// Current metrics in the Prometheus text format, if a recorder is installed
+pub fn render_metrics() -> Option<String> {
+    HANDLE.get().map(|handle| {
+        handle.run_upkeep();
+        handle.render()
+    })
+}
+
+/// Flushes exported spans when dropped
+pub struct TracingGuard {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the log subscriber, exporting spans over OTLP when configured
+///
+/// Keep the returned guard alive until shutdown so buffered spans are sent.
+pub fn init_tracing(config: &Config) -> Result<TracingGuard> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_level));
+
+    let provider = config
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| otlp_provider(endpoint, &config.otel_service_name))
+        .transpose()?;
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer("bucket-streamer"))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .init();
+
+    if let Some(endpoint) = &config.otlp_endpoint {
+        tracing::info!("Exporting traces to {}", endpoint);
+    }
+
+    Ok(TracingGuard { provider })
+}
+
+fn otlp_provider(endpoint: &str, service_name: &str) -> Result<TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .context("Failed to create OTLP exporter")?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )]))
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_rendering() {
+        let recorder = recorder_builder().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            record_stage(Stage::Decode, Duration::from_millis(3));
+            record_frame(true);
+            record_frame(false);
+            record_cache("offsets_sidecar", true);
+            record_frame_size("jpeg", 50_000);
+
+            let slot = QueueSlot::new("ws_requests");
+            drop(slot);
+        });
+
+        let output = handle.render();
+        assert!(output.contains(
+            r#"bucket_streamer_stage_duration_seconds_bucket{stage="decode",le="0.005"} 1"#
+        ));
+        assert!(output.contains(r#"bucket_streamer_frames_total{result="error"} 1"#));
+        assert!(output.contains(
+            r#"bucket_streamer_cache_requests_total{cache="offsets_sidecar",result="hit"} 1"#
+        ));
+        assert!(
+            output.contains(r#"bucket_streamer_frame_bytes_bucket{format="jpeg",le="65536"} 1"#)
+        );
+        assert!(output.contains(r#"bucket_streamer_queue_depth{queue="ws_requests"} 0"#));
+    }
+}
@@ -36,6 +36,10 @@ struct Cli {
     /// Directory to save received JPEGs
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Ask the server for per-frame stage timings and report averages
+    #[arg(long)]
+    debug_timings: bool,
 }
 
 //=============================================================================
@@ -64,6 +68,7 @@ struct FrameEntry {
 enum ClientMessage {
     SetVideo { path: String },
     RequestFrames { frames: Vec<FrameRequest> },
+    Ack { index: u32 },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,17 +89,42 @@ enum ServerMessage {
         index: u32,
         offset: u64,
         size: u32,
+        #[serde(default)]
+        timings: Option<FrameTimings>,
     },
     FrameError {
         index: u32,
         offset: u64,
         error: String,
     },
+    RateLimited {
+        limit: String,
+        retry_after_ms: u64,
+        index: Option<u32>,
+    },
+    QualityChanged {
+        quality: u8,
+    },
+    ServerShuttingDown {
+        deadline_ms: u64,
+    },
     Error {
         message: String,
     },
 }
 
+/// Server-side timings in microseconds (with `--debug-timings`)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct FrameTimings {
+    queue_us: u64,
+    fetch_us: u64,
+    decode_us: u64,
+    packets_decoded: u32,
+    convert_us: u64,
+    encode_us: u64,
+    send_us: u64,
+}
+
 //=============================================================================
 // Benchmark Results
 //=============================================================================
@@ -113,6 +143,47 @@ struct BenchmarkResult {
     latency_p95_ms: f64,
     latency_p99_ms: f64,
     total_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_timings: Option<TimingSummary>,
+}
+
+/// Average server-side time per frame, by stage
+#[derive(Debug, Default, Serialize)]
+struct TimingSummary {
+    frames: u32,
+    queue_avg_ms: f64,
+    fetch_avg_ms: f64,
+    decode_avg_ms: f64,
+    packets_decoded_avg: f64,
+    convert_avg_ms: f64,
+    encode_avg_ms: f64,
+    send_avg_ms: f64,
+}
+
+impl TimingSummary {
+    fn from_timings(timings: &[FrameTimings]) -> Option<Self> {
+        if timings.is_empty() {
+            return None;
+        }
+        let n = timings.len() as f64;
+        let avg_ms =
+            |f: fn(&FrameTimings) -> u64| timings.iter().map(f).sum::<u64>() as f64 / n / 1000.0;
+
+        Some(Self {
+            frames: timings.len() as u32,
+            queue_avg_ms: avg_ms(|t| t.queue_us),
+            fetch_avg_ms: avg_ms(|t| t.fetch_us),
+            decode_avg_ms: avg_ms(|t| t.decode_us),
+            packets_decoded_avg: timings
+                .iter()
+                .map(|t| t.packets_decoded as f64)
+                .sum::<f64>()
+                / n,
+            convert_avg_ms: avg_ms(|t| t.convert_us),
+            encode_avg_ms: avg_ms(|t| t.encode_us),
+            send_avg_ms: avg_ms(|t| t.send_us),
+        })
+    }
 }
 
 //=============================================================================
@@ -138,7 +209,10 @@ async fn run_benchmark(args: Cli) -> Result<()> {
     }
 
     // Connect to WebSocket
-    let url = Url::parse(&args.url).context("Invalid URL")?;
+    let mut url = Url::parse(&args.url).context("Invalid URL")?;
+    if args.debug_timings {
+        url.query_pairs_mut().append_pair("debug_timings", "true");
+    }
     let (ws, _) = connect_async(url.as_str())
         .await
         .context("Failed to connect")?;
@@ -163,6 +237,9 @@ async fn run_benchmark(args: Cli) -> Result<()> {
                 ServerMessage::Error { message } => {
                     anyhow::bail!("Server error: {}", message);
                 }
+                ServerMessage::RateLimited { limit, .. } => {
+                    anyhow::bail!("Rate limited by server ({})", limit);
+                }
                 ServerMessage::VideoSet { ok: true, .. } => {}
                 _ => anyhow::bail!("Unexpected response to SetVideo"),
             }
@@ -191,6 +268,7 @@ async fn run_benchmark(args: Cli) -> Result<()> {
     let mut received = 0u32;
     let mut errored = 0u32;
     let mut total_bytes = 0u64;
+    let mut server_timings: Vec<FrameTimings> = Vec::new();
 
     let overall_start = Instant::now();
 
@@ -218,9 +296,11 @@ async fn run_benchmark(args: Cli) -> Result<()> {
                             index,
                             offset,
                             size,
+                            timings,
                         } => {
                             binary_queue.push_back((index, offset));
                             total_bytes += size as u64;
+                            server_timings.extend(timings);
                         }
                         ServerMessage::FrameError {
                             index,
@@ -240,6 +320,30 @@ async fn run_benchmark(args: Cli) -> Result<()> {
                         ServerMessage::Error { message } => {
                             anyhow::bail!("Server error: {}", message);
                         }
+                        ServerMessage::RateLimited {
+                            limit,
+                            retry_after_ms,
+                            ..
+                        } => {
+                            anyhow::bail!(
+                                "Rate limited by server ({}), retry after {} ms",
+                                limit,
+                                retry_after_ms
+                            );
+                        }
+                        ServerMessage::ServerShuttingDown { deadline_ms } => {
+                            if !args.json {
+                                eprintln!(
+                                    "Server shutting down; closing within {} ms",
+                                    deadline_ms
+                                );
+                            }
+                        }
+                        ServerMessage::QualityChanged { quality } => {
+                            if !args.json {
+                                eprintln!("Server set JPEG quality to {}", quality);
+                            }
+                        }
                         _ => {}
                     }
                 }
@@ -249,6 +353,12 @@ async fn run_benchmark(args: Cli) -> Result<()> {
                         pending -= 1;
                         latencies.push(batch_start.elapsed().as_secs_f64() * 1000.0);
 
+                        // Return the credit so the server keeps sending
+                        let ack = ClientMessage::Ack { index };
+                        sender
+                            .send(Message::Text(serde_json::to_string(&ack)?.into()))
+                            .await?;
+
                         // Save frame if output directory specified
                         if let Some(ref out_dir) = args.output {
                             let path = out_dir.join(format!("frame_{:06}_{}.jpg", index, offset));
@@ -291,6 +401,7 @@ async fn run_benchmark(args: Cli) -> Result<()> {
         latency_p95_ms: percentile(&latencies, 95),
         latency_p99_ms: percentile(&latencies, 99),
         total_bytes,
+        server_timings: TimingSummary::from_timings(&server_timings),
     };
 
     // Output results
@@ -313,6 +424,20 @@ async fn run_benchmark(args: Cli) -> Result<()> {
         println!("  P95: {:.2}", result.latency_p95_ms);
         println!("  P99: {:.2}", result.latency_p99_ms);
 
+        if let Some(ref timings) = result.server_timings {
+            println!();
+            println!("Server timings (avg ms over {} frames):", timings.frames);
+            println!("  Queue:   {:.2}", timings.queue_avg_ms);
+            println!("  Fetch:   {:.2}", timings.fetch_avg_ms);
+            println!(
+                "  Decode:  {:.2} ({:.1} packets)",
+                timings.decode_avg_ms, timings.packets_decoded_avg
+            );
+            println!("  Convert: {:.2}", timings.convert_avg_ms);
+            println!("  Encode:  {:.2}", timings.encode_avg_ms);
+            println!("  Send:    {:.2}", timings.send_avg_ms);
+        }
+
         if let Some(ref out_dir) = args.output {
             println!();
             println!("Frames saved to: {}", out_dir.display());